@@ -0,0 +1,129 @@
+// src/arpeggiator.rs - Expands held chords into runs of single notes
+
+use crate::rng::Rng;
+use crate::score::ScoreNote;
+use crate::timing::PPQ;
+use crate::tuning::Tuning;
+
+/// Order in which chord notes are played
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ArpMode {
+    #[default]
+    Up,
+    Down,
+    UpDown,     // Up then back down, without repeating the top and bottom notes
+    Random,
+    AsPlayed,   // The order the chord was written in
+}
+
+impl ArpMode {
+    pub const ALL: [ArpMode; 5] = [ArpMode::Up, ArpMode::Down, ArpMode::UpDown, ArpMode::Random, ArpMode::AsPlayed];
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "up" => Some(ArpMode::Up),
+            "down" => Some(ArpMode::Down),
+            "updown" | "up-down" => Some(ArpMode::UpDown),
+            "random" => Some(ArpMode::Random),
+            "played" | "as-played" => Some(ArpMode::AsPlayed),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArpMode::Up => "up",
+            ArpMode::Down => "down",
+            ArpMode::UpDown => "updown",
+            ArpMode::Random => "random",
+            ArpMode::AsPlayed => "played",
+        }
+    }
+}
+
+/// Arpeggiator settings; the rate is in ticks so it follows the tempo
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arpeggiator {
+    pub mode: ArpMode,
+    pub octaves: u32,   // Octaves (periods of the tuning) the pattern spans, 1 to 4
+    pub rate: u64,      // Ticks per note
+    pub gate: f64,      // Share of each note's slot that sounds, 0.01 to 1.0
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self { mode: ArpMode::Up, octaves: 1, rate: PPQ as u64 / 4, gate: 0.8 }
+    }
+}
+
+impl Arpeggiator {
+    pub fn new(mode: ArpMode) -> Self {
+        Self { mode, ..Self::default() }
+    }
+
+    /// Rate from a length in beats, such as 1/4 for sixteenth notes
+    pub fn set_rate_beats(&mut self, beats: f64) {
+        self.rate = ((beats * PPQ as f64).round() as u64).max(1);
+    }
+
+    /// One-line summary for display
+    pub fn describe(&self) -> String {
+        let rate = match PPQ as u64 % self.rate {
+            0 => format!("1/{} beat", PPQ as u64 / self.rate),
+            _ => format!("{} ticks", self.rate),
+        };
+        format!("{} over {} octave(s), {} notes, {:.0}% gate",
+            self.mode.name(), self.octaves, rate, self.gate * 100.0)
+    }
+
+    /// Pitches and velocities of one pass through the pattern; octave
+    /// copies step through `tuning`, skipping keys it leaves unmapped
+    fn cycle(&self, chord: &[ScoreNote], tuning: &Tuning) -> Vec<(f32, f32)> {
+        let mut played: Vec<(f32, f32)> = chord.iter().map(|note| (note.freq, note.velocity)).collect();
+        for octave in 1..self.octaves.clamp(1, 4) as i32 {
+            played.extend(chord.iter().filter_map(|note| {
+                Some((tuning.shift_periods(note.freq, octave)?, note.velocity))
+            }));
+        }
+        if self.mode == ArpMode::AsPlayed {
+            return played;
+        }
+
+        let mut up = played;
+        up.sort_by(|a, b| a.0.total_cmp(&b.0));
+        match self.mode {
+            ArpMode::Down => up.into_iter().rev().collect(),
+            ArpMode::UpDown if up.len() > 2 => {
+                let down: Vec<_> = up[1..up.len() - 1].iter().rev().copied().collect();
+                up.extend(down);
+                up
+            }
+            _ => up,
+        }
+    }
+
+    /// Notes played while `chord` is held, from its first start to its last
+    /// release, with octaves as `tuning` plays them
+    pub fn expand(&self, chord: &[ScoreNote], tuning: &Tuning, rng: &mut Rng) -> Vec<ScoreNote> {
+        let Some(start) = chord.iter().map(|note| note.tick).min() else {
+            return Vec::new();
+        };
+        let end = chord.iter().map(|note| note.tick + note.ticks).max().unwrap_or(start);
+        let cycle = self.cycle(chord, tuning);
+        let rate = self.rate.max(1);
+        let sounding = ((rate as f64 * self.gate.clamp(0.01, 1.0)).round() as u64).max(1);
+
+        let mut notes = Vec::new();
+        let mut tick = start;
+        while tick < end {
+            let index = match self.mode {
+                ArpMode::Random => (rng.next_u64() % cycle.len() as u64) as usize,
+                _ => notes.len() % cycle.len(),
+            };
+            let (freq, velocity) = cycle[index];
+            notes.push(ScoreNote { tick, ticks: sounding.min(end - tick), freq, velocity });
+            tick += rate;
+        }
+        notes
+    }
+}
@@ -0,0 +1,235 @@
+// src/config.rs - Startup settings from the config file, environment and flags
+//
+//   # ~/.config/fm_synth/config
+//   device = usb             # part of an output device name
+//   preset = electric piano  # used when a command names none
+//   gain = -3                # master gain in dB, -60 to 24
+//   a4 = 432
+//
+// Later sources win: built-in defaults, then the file, then `FM_SYNTH_*`
+// environment variables, then command-line flags.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use fm_synth::master::{MasterParams, GAIN_RANGE_DB};
+use fm_synth::resolve::unquote;
+use fm_synth::tuning::Tuning;
+
+use crate::RENDER_SAMPLE_RATE;
+
+/// Environment variable naming a config file to read instead of the usual one
+pub const CONFIG_ENV: &str = "FM_SYNTH_CONFIG";
+
+/// Setting names and their environment variables
+pub const SETTINGS: [(&str, &str); 7] = [
+    ("device", "FM_SYNTH_DEVICE"),
+    ("preset", "FM_SYNTH_PRESET"),
+    ("gain", "FM_SYNTH_GAIN"),
+    ("tempo", "FM_SYNTH_TEMPO"),
+    ("a4", "FM_SYNTH_A4"),
+    ("post_roll", "FM_SYNTH_POST_ROLL"),
+    ("sample_rate", "FM_SYNTH_SAMPLE_RATE"),
+];
+
+/// Where a setting's value came from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Default,
+    File,
+    Env,
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Source::Default => "default",
+            Source::File => "config file",
+            Source::Env => "environment",
+            Source::Flag => "command line",
+        })
+    }
+}
+
+/// Effective settings, and where each came from
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub device: Option<String>,
+    pub preset: Option<String>,
+    pub gain_db: f32,
+    pub tempo: Option<f64>,
+    pub a4: f32,
+    pub post_roll: f64,   // Seconds
+    pub sample_rate: u32,
+    pub file: Option<PathBuf>,   // The config file looked for, if any
+    pub found: bool,             // Whether that file existed
+    sources: [Source; SETTINGS.len()],
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            device: None,
+            preset: None,
+            gain_db: MasterParams::default().gain_db,
+            tempo: None,
+            a4: Tuning::default().a4,
+            post_roll: 0.5,
+            sample_rate: RENDER_SAMPLE_RATE,
+            file: None,
+            found: false,
+            sources: [Source::Default; SETTINGS.len()],
+        }
+    }
+}
+
+/// `$<var>/fm_synth/<file>`, or `~/<fallback>/fm_synth/<file>` when the
+/// variable is unset, as the XDG base directory spec has it
+pub fn xdg_path(var: &str, fallback: &str, file: &str) -> Option<PathBuf> {
+    let base = std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))?;
+    Some(base.join("fm_synth").join(file))
+}
+
+/// Number from a setting's text
+fn number(key: &str, value: &str) -> Result<f64, String> {
+    value.parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| format!("Invalid {} '{}', expected a number", key, value))
+}
+
+/// Positive number from a setting's text
+fn positive(key: &str, value: &str) -> Result<f64, String> {
+    number(key, value)
+        .ok()
+        .filter(|n| *n > 0.0)
+        .ok_or_else(|| format!("Invalid {} '{}', expected a positive number", key, value))
+}
+
+impl Config {
+    /// Defaults, then the config file, then the environment. `file` replaces
+    /// the usual `$XDG_CONFIG_HOME/fm_synth/config` and must exist.
+    pub fn load(file: Option<&Path>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let explicit = file.map(PathBuf::from)
+            .or_else(|| std::env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()).map(PathBuf::from));
+        config.file = explicit.clone().or_else(|| xdg_path("XDG_CONFIG_HOME", ".config", "config"));
+
+        if let Some(path) = config.file.clone() {
+            match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    config.found = true;
+                    config.read(&text).map_err(|e| format!("{}:{}", path.display(), e))?;
+                }
+                // Without a config file the defaults stand
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && explicit.is_none() => {}
+                Err(e) => return Err(format!("Could not read config file '{}': {}", path.display(), e)),
+            }
+        }
+
+        for (key, var) in SETTINGS {
+            if let Some(value) = std::env::var_os(var) {
+                let value = value.to_string_lossy();
+                config.set(key, &value, Source::Env).map_err(|e| format!("{}: {}", var, e))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Settings from config file text; errors start with the line number
+    pub fn read(&mut self, text: &str) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(setting, _)| setting).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("{}: expected 'setting = value', got '{}'", number + 1, line))?;
+            self.set(key.trim(), value.trim(), Source::File).map_err(|e| format!("{}: {}", number + 1, e))?;
+        }
+        Ok(())
+    }
+
+    /// Change one setting from text; an empty value unsets those that can be unset
+    pub fn set(&mut self, key: &str, value: &str, source: Source) -> Result<(), String> {
+        let key = key.trim().to_lowercase().replace('-', "_");
+        let value = unquote(value.trim());
+        let index = SETTINGS.iter().position(|(name, _)| *name == key).ok_or_else(|| {
+            let names: Vec<&str> = SETTINGS.iter().map(|(name, _)| *name).collect();
+            format!("Unknown setting '{}'. Settings are: {}", key, names.join(", "))
+        })?;
+        let text = || (!value.is_empty()).then(|| value.to_string());
+
+        match key.as_str() {
+            "device" => self.device = text(),
+            "preset" => self.preset = text(),
+            "gain" => {
+                self.gain_db = number(&key, value).ok()
+                    .map(|db| db as f32)
+                    .filter(|db| GAIN_RANGE_DB.contains(db))
+                    .ok_or_else(|| format!("Invalid gain '{}', expected {} to {} dB",
+                        value, GAIN_RANGE_DB.start(), GAIN_RANGE_DB.end()))?;
+            }
+            "tempo" if value.is_empty() => self.tempo = None,
+            "tempo" => self.tempo = Some(positive(&key, value)?),
+            "a4" => self.a4 = positive(&key, value)? as f32,
+            "post_roll" => {
+                self.post_roll = number(&key, value).ok()
+                    .filter(|seconds| (0.0..=60.0).contains(seconds))
+                    .ok_or_else(|| format!("Invalid post_roll '{}', expected 0 to 60 seconds", value))?;
+            }
+            "sample_rate" => {
+                self.sample_rate = value.parse().ok()
+                    .filter(|rate| (8000..=192000).contains(rate))
+                    .ok_or_else(|| format!("Invalid sample_rate '{}', expected 8000 to 192000", value))?;
+            }
+            _ => unreachable!("every name in SETTINGS is handled"),
+        }
+        self.sources[index] = source;
+        Ok(())
+    }
+
+    /// Apply `key=value` from the command line
+    pub fn set_flag(&mut self, setting: &str) -> Result<(), String> {
+        let (key, value) = setting.split_once('=')
+            .ok_or_else(|| format!("Invalid setting '{}', use --set <setting>=<value>", setting))?;
+        self.set(key, value, Source::Flag)
+    }
+
+    pub fn post_roll(&self) -> Duration {
+        Duration::from_secs_f64(self.post_roll)
+    }
+
+    /// A setting's current value as it would be written in the file
+    fn value(&self, key: &str) -> String {
+        let or_empty = |value: &Option<String>| value.clone().unwrap_or_default();
+        match key {
+            "device" => or_empty(&self.device),
+            "preset" => or_empty(&self.preset),
+            "gain" => self.gain_db.to_string(),
+            "tempo" => self.tempo.map(|bpm| bpm.to_string()).unwrap_or_default(),
+            "a4" => self.a4.to_string(),
+            "post_roll" => self.post_roll.to_string(),
+            _ => self.sample_rate.to_string(),
+        }
+    }
+
+    /// Print the effective settings and where each came from
+    pub fn show(&self) {
+        match &self.file {
+            Some(path) if self.found => println!("Config file: {}", path.display()),
+            Some(path) => println!("Config file: {} (not found)", path.display()),
+            None => println!("Config file: none (set HOME, XDG_CONFIG_HOME or {})", CONFIG_ENV),
+        }
+        for (i, (key, var)) in SETTINGS.iter().enumerate() {
+            let value = self.value(key);
+            let shown = if value.is_empty() { "(unset)".to_string() } else { value };
+            println!("  {:<11} = {:<16} # {} (${})", key, shown, self.sources[i], var);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+// src/effects.rs - Master effects bus: chorus, tempo-synced delay and reverb
+
+use std::f32::consts::PI;
+
+/// Chorus/ensemble settings
+#[derive(Clone, Debug)]
+pub struct ChorusParams {
+    pub mix: f32,      // Wet/dry (0.0 = bypassed)
+    pub rate: f32,     // LFO rate in Hz
+    pub depth: f32,    // Delay modulation depth in milliseconds
+}
+
+impl Default for ChorusParams {
+    fn default() -> Self {
+        Self {
+            mix: 0.0,
+            rate: 0.8,
+            depth: 3.0,
+        }
+    }
+}
+
+/// Stereo delay settings; the time follows the session tempo
+#[derive(Clone, Debug)]
+pub struct DelayParams {
+    pub mix: f32,        // Wet/dry (0.0 = bypassed)
+    pub beats: f32,      // Delay time in beats (0.75 = dotted eighth)
+    pub feedback: f32,   // 0.0 - 0.95
+    pub ping_pong: bool, // Alternate repeats between left and right
+}
+
+impl Default for DelayParams {
+    fn default() -> Self {
+        Self {
+            mix: 0.0,
+            beats: 0.75,
+            feedback: 0.35,
+            ping_pong: true,
+        }
+    }
+}
+
+/// Freeverb-style reverb settings
+#[derive(Clone, Debug)]
+pub struct ReverbParams {
+    pub mix: f32,        // Wet/dry (0.0 = bypassed)
+    pub room_size: f32,  // 0.0 - 1.0
+    pub damping: f32,    // 0.0 - 1.0
+    pub width: f32,      // Stereo width (0.0 - 1.0)
+}
+
+impl Default for ReverbParams {
+    fn default() -> Self {
+        Self {
+            mix: 0.0,
+            room_size: 0.7,
+            damping: 0.5,
+            width: 1.0,
+        }
+    }
+}
+
+/// Effect settings stored in a patch or set for the session
+#[derive(Clone, Debug, Default)]
+pub struct EffectsParams {
+    pub chorus: ChorusParams,
+    pub delay: DelayParams,
+    pub reverb: ReverbParams,
+}
+
+/// Circular buffer with fractional read-back
+struct DelayLine {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(2)],
+            pos: 0,
+        }
+    }
+
+    fn write(&mut self, value: f32) {
+        self.buffer[self.pos] = value;
+        self.pos = (self.pos + 1) % self.buffer.len();
+    }
+
+    /// Sample written `delay` samples ago (linear interpolation)
+    fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(1.0, (len - 1) as f32);
+        let whole = delay.floor() as usize;
+        let frac = delay - whole as f32;
+        let a = self.buffer[(self.pos + len - whole) % len];
+        let b = self.buffer[(self.pos + len - whole - 1) % len];
+        a + (b - a) * frac
+    }
+}
+
+struct Chorus {
+    lines: [DelayLine; 2],
+    phase: f32,
+    sample_rate: f32,
+}
+
+impl Chorus {
+    const BASE_DELAY_MS: f32 = 12.0;
+
+    fn new(sample_rate: f32) -> Self {
+        let len = (sample_rate * 0.05) as usize;
+        Self {
+            lines: [DelayLine::new(len), DelayLine::new(len)],
+            phase: 0.0,
+            sample_rate,
+        }
+    }
+
+    fn process(&mut self, input: (f32, f32), params: &ChorusParams) -> (f32, f32) {
+        self.phase = (self.phase + params.rate / self.sample_rate).fract();
+        let ms_to_samples = self.sample_rate / 1000.0;
+
+        // Quadrature LFOs keep the two sides moving independently
+        let mut wet = [0.0; 2];
+        for (ch, (line, x)) in self.lines.iter_mut().zip([input.0, input.1]).enumerate() {
+            let lfo = (2.0 * PI * (self.phase + ch as f32 * 0.25)).sin();
+            let delay = (Self::BASE_DELAY_MS + params.depth * lfo) * ms_to_samples;
+            line.write(x);
+            wet[ch] = line.read(delay);
+        }
+
+        mix(input, (wet[0], wet[1]), params.mix)
+    }
+}
+
+struct Delay {
+    lines: [DelayLine; 2],
+    sample_rate: f32,
+}
+
+impl Delay {
+    const MAX_SECONDS: f32 = 4.0;
+
+    fn new(sample_rate: f32) -> Self {
+        let len = (sample_rate * Self::MAX_SECONDS) as usize;
+        Self {
+            lines: [DelayLine::new(len), DelayLine::new(len)],
+            sample_rate,
+        }
+    }
+
+    fn process(&mut self, input: (f32, f32), params: &DelayParams, tempo: f32) -> (f32, f32) {
+        let seconds = params.beats * 60.0 / tempo.max(1.0);
+        let delay = (seconds * self.sample_rate).min(Self::MAX_SECONDS * self.sample_rate - 2.0);
+        let feedback = params.feedback.clamp(0.0, 0.95);
+
+        let left = self.lines[0].read(delay);
+        let right = self.lines[1].read(delay);
+        if params.ping_pong {
+            // Feed the mono input into the left line and cross the repeats over
+            let mono = (input.0 + input.1) * 0.5;
+            self.lines[0].write(mono + right * feedback);
+            self.lines[1].write(left * feedback);
+        } else {
+            self.lines[0].write(input.0 + left * feedback);
+            self.lines[1].write(input.1 + right * feedback);
+        }
+
+        mix(input, (left, right), params.mix)
+    }
+}
+
+/// Lowpass-feedback comb filter (Freeverb)
+struct Comb {
+    line: Vec<f32>,
+    pos: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self { line: vec![0.0; len.max(1)], pos: 0, store: 0.0 }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.line[self.pos];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.line[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.line.len();
+        output
+    }
+}
+
+/// Schroeder allpass diffuser (Freeverb)
+struct Allpass {
+    line: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self { line: vec![0.0; len.max(1)], pos: 0 }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.line[self.pos];
+        self.line[self.pos] = input + buffered * 0.5;
+        self.pos = (self.pos + 1) % self.line.len();
+        buffered - input
+    }
+}
+
+struct Reverb {
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+impl Reverb {
+    // Freeverb tunings at 44.1 kHz
+    const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+    const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+    const STEREO_SPREAD: usize = 23;
+
+    fn new(sample_rate: f32) -> Self {
+        let scale = |len: usize, spread: usize| ((len + spread) as f32 * sample_rate / 44100.0) as usize;
+        let build = |spread: usize| {
+            (
+                Self::COMB_TUNING.iter().map(|&n| Comb::new(scale(n, spread))).collect::<Vec<_>>(),
+                Self::ALLPASS_TUNING.iter().map(|&n| Allpass::new(scale(n, spread))).collect::<Vec<_>>(),
+            )
+        };
+        let (left_combs, left_allpasses) = build(0);
+        let (right_combs, right_allpasses) = build(Self::STEREO_SPREAD);
+        Self {
+            combs: [left_combs, right_combs],
+            allpasses: [left_allpasses, right_allpasses],
+        }
+    }
+
+    fn process(&mut self, input: (f32, f32), params: &ReverbParams) -> (f32, f32) {
+        let feedback = 0.7 + 0.28 * params.room_size.clamp(0.0, 1.0);
+        let damping = 0.4 * params.damping.clamp(0.0, 1.0);
+        let mono = (input.0 + input.1) * 0.015;
+
+        let mut wet = [0.0; 2];
+        for (ch, out) in wet.iter_mut().enumerate() {
+            let mut acc: f32 = self.combs[ch].iter_mut()
+                .map(|comb| comb.process(mono, feedback, damping))
+                .sum();
+            for allpass in &mut self.allpasses[ch] {
+                acc = allpass.process(acc);
+            }
+            *out = acc;
+        }
+
+        let width = params.width.clamp(0.0, 1.0);
+        let wet1 = 0.5 + width * 0.5;
+        let wet2 = (1.0 - width) * 0.5;
+        let wet = (wet[0] * wet1 + wet[1] * wet2, wet[1] * wet1 + wet[0] * wet2);
+        mix(input, wet, params.mix)
+    }
+}
+
+fn mix(dry: (f32, f32), wet: (f32, f32), amount: f32) -> (f32, f32) {
+    let amount = amount.clamp(0.0, 1.0);
+    (
+        dry.0 * (1.0 - amount) + wet.0 * amount,
+        dry.1 * (1.0 - amount) + wet.1 * amount,
+    )
+}
+
+/// Chorus -> delay -> reverb, each skipped while its mix is zero
+pub struct EffectsChain {
+    params: EffectsParams,
+    tempo: f32,
+    chorus: Chorus,
+    delay: Delay,
+    reverb: Reverb,
+}
+
+impl EffectsChain {
+    pub fn new(sample_rate: f32, params: EffectsParams) -> Self {
+        Self {
+            params,
+            tempo: 120.0,
+            chorus: Chorus::new(sample_rate),
+            delay: Delay::new(sample_rate),
+            reverb: Reverb::new(sample_rate),
+        }
+    }
+
+    pub fn set_params(&mut self, params: EffectsParams) {
+        self.params = params;
+    }
+
+    /// Tempo in BPM used by the synced delay
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo = bpm;
+    }
+
+    pub fn process(&mut self, frame: (f32, f32)) -> (f32, f32) {
+        let mut frame = frame;
+        if self.params.chorus.mix > 0.0 {
+            frame = self.chorus.process(frame, &self.params.chorus);
+        }
+        if self.params.delay.mix > 0.0 {
+            frame = self.delay.process(frame, &self.params.delay, self.tempo);
+        }
+        if self.params.reverb.mix > 0.0 {
+            frame = self.reverb.process(frame, &self.params.reverb);
+        }
+        frame
+    }
+}
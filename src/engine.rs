@@ -0,0 +1,280 @@
+// src/engine.rs - Sample-accurate event playback through the synths, effects and master bus
+
+use std::collections::VecDeque;
+
+use crate::effects::{EffectsChain, EffectsParams};
+use crate::master::{MasterBus, MasterParams, MeterReading};
+use crate::scope::Scope;
+use crate::sequence::{EventKind, Sequence};
+use crate::sequencer::{PlayMode, Sequencer, Trigger};
+use crate::synth::{FMParams, FMSynth};
+
+/// An event for one track's synth at an absolute sample position
+struct Scheduled {
+    sample: u64,
+    track: usize,
+    kind: EventKind,
+    note: Option<u64>,   // Synth note id a sequencer release ends, rather than every note on its key
+}
+
+/// A note event as the engine played it, at its sample position
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayedEvent {
+    pub sample: u64,
+    pub track: usize,
+    pub kind: EventKind,
+}
+
+/// Synths, effects and an event queue driven by the sample clock; the same
+/// engine runs inside the audio callback and in offline renders
+pub struct Engine {
+    sample_rate: f32,
+    synths: Vec<FMSynth>,   // One per track; sequences play on the first
+    effects: EffectsChain,
+    master: MasterBus,
+    queue: VecDeque<Scheduled>,
+    clock: u64,
+    end: u64,
+    sequencer: Option<Sequencer>,
+    triggers: Vec<Trigger>,   // Reused buffer for sequencer output
+    scope: Option<Scope>,     // Recent output, when something displays it
+    log: Option<Vec<PlayedEvent>>,   // Events played, when something records them
+}
+
+impl Engine {
+    pub fn new(sample_rate: f32, params: FMParams) -> Self {
+        Self {
+            sample_rate,
+            effects: EffectsChain::new(sample_rate, params.effects.clone()),
+            master: MasterBus::new(sample_rate, MasterParams::default()),
+            synths: vec![FMSynth::new(sample_rate, params)],
+            queue: VecDeque::new(),
+            clock: 0,
+            end: 0,
+            sequencer: None,
+            triggers: Vec::new(),
+            scope: None,
+            log: None,
+        }
+    }
+
+    /// Engine with one synth per sequencer track, using the first track's effects
+    pub fn with_sequencer(sample_rate: f32, sequencer: Sequencer) -> Self {
+        let first = sequencer.tracks.first().map(|t| t.preset.clone()).unwrap_or_default();
+        let mut engine = Self::new(sample_rate, first);
+        engine.synths = sequencer.tracks.iter()
+            .map(|track| FMSynth::new(sample_rate, track.preset.clone()))
+            .collect();
+        if engine.synths.is_empty() {
+            engine.synths.push(FMSynth::new(sample_rate, FMParams::default()));
+        }
+        engine.sequencer = Some(sequencer);
+        engine
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn synth(&self) -> &FMSynth {
+        &self.synths[0]
+    }
+
+    pub fn synth_mut(&mut self) -> &mut FMSynth {
+        &mut self.synths[0]
+    }
+
+    pub fn sequencer(&self) -> Option<&Sequencer> {
+        self.sequencer.as_ref()
+    }
+
+    /// For live changes such as mute, solo and tempo
+    pub fn sequencer_mut(&mut self) -> Option<&mut Sequencer> {
+        self.sequencer.as_mut()
+    }
+
+    /// Start the sequencer at the current sample; see `Sequencer::start`
+    pub fn start_sequencer(&mut self, mode: PlayMode, passes: Option<u32>) {
+        let now = self.clock as f64 / self.sample_rate as f64;
+        if let Some(sequencer) = &mut self.sequencer {
+            self.effects.set_tempo(sequencer.tempo() as f32);
+            sequencer.start(mode, now, passes);
+        }
+    }
+
+    pub fn set_params(&mut self, params: FMParams) {
+        self.effects.set_params(params.effects.clone());
+        self.synths[0].set_params(params);
+    }
+
+    pub fn set_effects(&mut self, effects: EffectsParams) {
+        self.effects.set_params(effects);
+    }
+
+    pub fn set_master(&mut self, master: MasterParams) {
+        self.master.set_params(master);
+    }
+
+    /// Output levels since the engine started or the meter was last reset
+    pub fn meter(&self) -> MeterReading {
+        self.master.meter()
+    }
+
+    pub fn reset_meter(&mut self) {
+        self.master.reset_meter();
+    }
+
+    /// Keep the last `len` output samples (mixed to mono) for display
+    pub fn enable_scope(&mut self, len: usize) {
+        self.scope = Some(Scope::new(len));
+    }
+
+    pub fn scope(&self) -> Option<&Scope> {
+        self.scope.as_ref()
+    }
+
+    /// Keep a log of every note event played, for `take_events`
+    pub fn log_events(&mut self) {
+        self.log.get_or_insert_with(Vec::new);
+    }
+
+    /// Events played since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<PlayedEvent> {
+        self.log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Samples produced so far
+    pub fn position(&self) -> u64 {
+        self.clock
+    }
+
+    /// Start a note on the first synth straight away, as a live key press
+    pub fn note_on(&mut self, freq: f32, velocity: f32) {
+        self.dispatch(0, EventKind::NoteOn { freq, velocity }, None);
+    }
+
+    pub fn note_off(&mut self, freq: f32) {
+        self.dispatch(0, EventKind::NoteOff { freq }, None);
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.effects.set_tempo(bpm);
+    }
+
+    /// Queue a sequence to start at the current sample
+    pub fn play(&mut self, sequence: &Sequence) {
+        let start = self.clock;
+        let to_samples = |seconds: f64| start + (seconds * self.sample_rate as f64).round() as u64;
+
+        let mut events: Vec<Scheduled> = self.queue.drain(..).collect();
+        events.extend(sequence.events.iter().map(|e| Scheduled {
+            sample: to_samples(e.time),
+            track: 0,
+            kind: e.kind.clone(),
+            note: None,
+        }));
+        events.sort_by_key(|e| e.sample);
+        self.queue = events.into();
+        self.end = self.end.max(to_samples(sequence.length));
+    }
+
+    /// True until every queued event has fired, the sequence length has
+    /// elapsed and the sequencer has stopped
+    pub fn is_playing(&self) -> bool {
+        !self.queue.is_empty()
+            || self.clock < self.end
+            || self.sequencer.as_ref().is_some_and(|s| s.is_running())
+    }
+
+    /// Drop pending events, stop the sequencer and release all notes
+    pub fn stop(&mut self) {
+        // Pending releases still count as played, so logged notes end here
+        if let Some(log) = &mut self.log {
+            for event in self.queue.iter().filter(|e| matches!(e.kind, EventKind::NoteOff { .. })) {
+                log.push(PlayedEvent { sample: self.clock, track: event.track, kind: event.kind.clone() });
+            }
+        }
+        self.queue.clear();
+        self.end = self.clock;
+        if let Some(sequencer) = &mut self.sequencer {
+            sequencer.stop();
+        }
+        for synth in &mut self.synths {
+            synth.all_notes_off();
+        }
+    }
+
+    /// Play an event on a track's synth; returns the note id of a note-on,
+    /// and a note-off with `note` releases only that note
+    fn dispatch(&mut self, track: usize, kind: EventKind, note: Option<u64>) -> Option<u64> {
+        if let Some(log) = &mut self.log {
+            log.push(PlayedEvent { sample: self.clock, track, kind: kind.clone() });
+        }
+        let synth = self.synths.get_mut(track)?;
+        match (kind, note) {
+            (EventKind::NoteOn { freq, velocity }, _) => return Some(synth.note_on(freq, velocity)),
+            (EventKind::NoteOff { freq }, Some(id)) => synth.release_note(id, freq),
+            (EventKind::NoteOff { freq }, None) => synth.note_off(freq),
+        }
+        None
+    }
+
+    /// Start due sequencer steps now and queue their releases
+    fn run_sequencer(&mut self) {
+        let Some(sequencer) = &mut self.sequencer else { return };
+        sequencer.poll(self.clock as f64 / self.sample_rate as f64, &mut self.triggers);
+
+        let triggers = std::mem::take(&mut self.triggers);
+        for trigger in &triggers {
+            let freq = trigger.freq;
+            let note = self.dispatch(trigger.track, EventKind::NoteOn { freq, velocity: trigger.velocity }, None);
+            let release = ((trigger.time + trigger.length) * self.sample_rate as f64).round() as u64;
+            let index = self.queue.partition_point(|e| e.sample <= release);
+            self.queue.insert(index, Scheduled {
+                sample: release.max(self.clock + 1),
+                track: trigger.track,
+                kind: EventKind::NoteOff { freq },
+                note,
+            });
+        }
+        self.triggers = triggers;
+        self.triggers.clear();
+    }
+
+    pub fn next_frame(&mut self) -> (f32, f32) {
+        while self.queue.front().is_some_and(|e| e.sample <= self.clock) {
+            let event = self.queue.pop_front().unwrap();
+            self.dispatch(event.track, event.kind, event.note);
+        }
+        self.run_sequencer();
+        self.clock += 1;
+
+        let frame = self.synths.iter_mut()
+            .map(|synth| synth.next_frame())
+            .fold((0.0, 0.0), |mix, frame| (mix.0 + frame.0, mix.1 + frame.1));
+        let frame = self.effects.process(frame);
+        let frame = self.master.process(frame);
+        if let Some(scope) = &mut self.scope {
+            scope.push((frame.0 + frame.1) * 0.5);
+        }
+        frame
+    }
+
+    /// Play a sequence to completion offline, followed by `tail` seconds
+    /// for releases and effect tails
+    pub fn render(&mut self, sequence: &Sequence, tail: f32) -> Vec<(f32, f32)> {
+        self.play(sequence);
+        self.render_pending(tail)
+    }
+
+    /// Run whatever is playing to completion offline, followed by `tail` seconds
+    pub fn render_pending(&mut self, tail: f32) -> Vec<(f32, f32)> {
+        let mut frames = Vec::new();
+        while self.is_playing() {
+            frames.push(self.next_frame());
+        }
+        let tail_samples = (tail * self.sample_rate) as usize;
+        frames.extend((0..tail_samples).map(|_| self.next_frame()));
+        frames
+    }
+}
@@ -0,0 +1,96 @@
+// src/filter.rs - Resonant state-variable filter applied to each voice
+
+use std::f32::consts::PI;
+
+use crate::operator::key_octaves;
+
+/// Filter response
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterMode {
+    Off,
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+/// Filter settings stored in a patch
+#[derive(Clone, Debug)]
+pub struct FilterParams {
+    pub mode: FilterMode,
+    pub cutoff: f32,       // Cutoff frequency in Hz at middle C
+    pub resonance: f32,    // 0.0 (none) to 1.0 (self-oscillation)
+    pub key_track: f32,    // 1.0 moves the cutoff an octave per octave played
+    pub env_amount: f32,   // Octaves of cutoff added at full modulation envelope
+}
+
+impl Default for FilterParams {
+    fn default() -> Self {
+        Self {
+            mode: FilterMode::Off,
+            cutoff: 20000.0,
+            resonance: 0.0,
+            key_track: 0.0,
+            env_amount: 0.0,
+        }
+    }
+}
+
+impl FilterParams {
+    /// Cutoff for a note at `note_freq`, shifted by `octaves` of modulation
+    pub fn cutoff_for(&self, note_freq: f32, octaves: f32) -> f32 {
+        self.cutoff * 2.0_f32.powf(self.key_track * key_octaves(note_freq) + octaves)
+    }
+}
+
+/// Topology-preserving state-variable filter (one channel)
+#[derive(Clone, Debug, Default)]
+pub struct StateVariableFilter {
+    ic1eq: f32,
+    ic2eq: f32,
+    // Coefficients for the current block
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl StateVariableFilter {
+    /// Recompute coefficients for `cutoff` Hz and `resonance` (0.0 - 1.0)
+    pub fn set_cutoff(&mut self, cutoff: f32, resonance: f32, sample_rate: f32) {
+        let cutoff = cutoff.clamp(20.0, sample_rate * 0.45);
+        let g = (PI * cutoff / sample_rate).tan();
+        self.k = 2.0 - 1.98 * resonance.clamp(0.0, 1.0);
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    pub fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+
+    pub fn process(&mut self, input: f32, mode: FilterMode) -> f32 {
+        if mode == FilterMode::Off {
+            return input;
+        }
+
+        let v3 = input - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        let low = v2;
+        let band = v1;
+        let high = input - self.k * band - low;
+        match mode {
+            FilterMode::LowPass => low,
+            FilterMode::HighPass => high,
+            FilterMode::BandPass => band,
+            FilterMode::Notch => low + high,
+            FilterMode::Off => input,
+        }
+    }
+}
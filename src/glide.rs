@@ -0,0 +1,119 @@
+// src/glide.rs - Portamento between notes
+
+/// How the portamento time is interpreted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlideMode {
+    ConstantTime,  // Every glide takes `time` seconds
+    ConstantRate,  // `time` seconds per octave travelled
+}
+
+/// Shape of the pitch movement during a glide
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlideCurve {
+    Linear,       // Even speed in semitones
+    Exponential,  // Fast start that settles into the target note
+}
+
+/// Portamento settings stored in a patch
+#[derive(Clone, Debug)]
+pub struct PortamentoParams {
+    pub time: f32,          // Seconds (or seconds per octave); 0.0 disables glide
+    pub mode: GlideMode,
+    pub curve: GlideCurve,
+}
+
+impl Default for PortamentoParams {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            mode: GlideMode::ConstantTime,
+            curve: GlideCurve::Exponential,
+        }
+    }
+}
+
+impl PortamentoParams {
+    pub fn is_enabled(&self) -> bool {
+        self.time > 0.0
+    }
+
+    /// Glide duration in seconds for a move of `octaves`
+    pub fn duration(&self, octaves: f32) -> f32 {
+        match self.mode {
+            GlideMode::ConstantTime => self.time,
+            GlideMode::ConstantRate => self.time * octaves.abs(),
+        }
+    }
+}
+
+/// Steepness of the exponential curve; higher settles earlier
+const EXP_CURVE: f32 = 5.0;
+
+/// Pitch of a voice moving towards its target, tracked in octaves (log2 Hz)
+#[derive(Clone, Debug)]
+pub struct Glide {
+    start: f32,
+    target: f32,
+    progress: f32,
+    duration: f32,
+    curve: GlideCurve,
+}
+
+impl Glide {
+    pub fn new(freq: f32) -> Self {
+        let pitch = freq.log2();
+        Self {
+            start: pitch,
+            target: pitch,
+            progress: 1.0,
+            duration: 0.0,
+            curve: GlideCurve::Linear,
+        }
+    }
+
+    /// Move straight to `freq` without gliding
+    pub fn jump(&mut self, freq: f32) {
+        *self = Self::new(freq);
+    }
+
+    /// Start gliding from the current pitch to `freq`
+    pub fn glide_to(&mut self, freq: f32, params: &PortamentoParams) {
+        let current = self.pitch();
+        let target = freq.log2();
+        let duration = params.duration(target - current);
+
+        if !params.is_enabled() || duration <= 0.0 {
+            self.jump(freq);
+            return;
+        }
+
+        self.start = current;
+        self.target = target;
+        self.progress = 0.0;
+        self.duration = duration;
+        self.curve = params.curve;
+    }
+
+    /// Advance the glide by `dt` seconds
+    pub fn advance(&mut self, dt: f32) {
+        if self.progress < 1.0 {
+            self.progress = (self.progress + dt / self.duration).min(1.0);
+        }
+    }
+
+    /// Current pitch in octaves
+    pub fn pitch(&self) -> f32 {
+        let shape = match self.curve {
+            GlideCurve::Linear => self.progress,
+            GlideCurve::Exponential => {
+                (1.0 - (-EXP_CURVE * self.progress).exp()) / (1.0 - (-EXP_CURVE).exp())
+            }
+        };
+        self.start + (self.target - self.start) * shape
+    }
+
+    /// Current frequency in Hz
+    pub fn freq(&self) -> f32 {
+        2.0_f32.powf(self.pitch())
+    }
+}
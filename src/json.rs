@@ -0,0 +1,315 @@
+// src/json.rs - Minimal JSON writer for machine-readable preset and melody listings
+
+use std::fmt::{self, Write};
+
+use crate::effects::{ChorusParams, DelayParams, EffectsParams, ReverbParams};
+use crate::filter::FilterParams;
+use crate::glide::PortamentoParams;
+use crate::melodies::Melody;
+use crate::modulation::{LfoParams, ModRoute};
+use crate::operator::{Operator, OperatorFreq};
+use crate::presets::{variant_name, Preset};
+use crate::score::Score;
+use crate::synth::{EnvelopeParams, FMParams, UnisonParams};
+use crate::timing::PPQ;
+use crate::tuning::Tuning;
+
+/// A JSON value; objects keep their fields in the order given
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Json)>) -> Self {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<f32> for Json {
+    /// Through the shortest decimal form, so 0.3 stays 0.3 rather than 0.30000001192092896
+    fn from(value: f32) -> Self {
+        Json::Number(value.to_string().parse().unwrap_or(f64::NAN))
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> FromIterator<T> for Json {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Json::Array(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Compact JSON text on one line
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // JSON has no infinities or NaN
+            Json::Null => f.write_str("null"),
+            Json::Number(n) if !n.is_finite() => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// An enum setting by its snake_case name, as preset files write it
+fn variant(value: &impl fmt::Debug) -> Json {
+    Json::String(variant_name(value))
+}
+
+/// Conversion to JSON with every setting included
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl ToJson for Operator {
+    fn to_json(&self) -> Json {
+        let freq = match self.freq {
+            OperatorFreq::Ratio { coarse, fine } => Json::object([
+                ("type", "ratio".into()), ("coarse", coarse.into()), ("fine", fine.into()),
+            ]),
+            OperatorFreq::Fixed(hz) => Json::object([("type", "fixed".into()), ("hz", hz.into())]),
+        };
+        Json::object([
+            ("freq", freq),
+            ("detune", self.detune.into()),
+            ("level_scaling", self.level_scaling.into()),
+            ("rate_scaling", self.rate_scaling.into()),
+        ])
+    }
+}
+
+impl ToJson for EnvelopeParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("attack", self.attack.into()),
+            ("decay", self.decay.into()),
+            ("sustain", self.sustain.into()),
+            ("release", self.release.into()),
+        ])
+    }
+}
+
+impl ToJson for LfoParams {
+    fn to_json(&self) -> Json {
+        Json::object([("rate", self.rate.into()), ("shape", variant(&self.shape))])
+    }
+}
+
+impl ToJson for ModRoute {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("source", variant(&self.source)),
+            ("destination", variant(&self.destination)),
+            ("depth", self.depth.into()),
+        ])
+    }
+}
+
+impl ToJson for PortamentoParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("time", self.time.into()),
+            ("mode", variant(&self.mode)),
+            ("curve", variant(&self.curve)),
+        ])
+    }
+}
+
+impl ToJson for UnisonParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("voices", self.voices.into()),
+            ("detune", self.detune.into()),
+            ("spread", self.spread.into()),
+            ("random_phase", self.random_phase.into()),
+        ])
+    }
+}
+
+impl ToJson for FilterParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("mode", variant(&self.mode)),
+            ("cutoff", self.cutoff.into()),
+            ("resonance", self.resonance.into()),
+            ("key_track", self.key_track.into()),
+            ("env_amount", self.env_amount.into()),
+        ])
+    }
+}
+
+impl ToJson for ChorusParams {
+    fn to_json(&self) -> Json {
+        Json::object([("mix", self.mix.into()), ("rate", self.rate.into()), ("depth", self.depth.into())])
+    }
+}
+
+impl ToJson for DelayParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("mix", self.mix.into()),
+            ("beats", self.beats.into()),
+            ("feedback", self.feedback.into()),
+            ("ping_pong", self.ping_pong.into()),
+        ])
+    }
+}
+
+impl ToJson for ReverbParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("mix", self.mix.into()),
+            ("room_size", self.room_size.into()),
+            ("damping", self.damping.into()),
+            ("width", self.width.into()),
+        ])
+    }
+}
+
+impl ToJson for EffectsParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("chorus", self.chorus.to_json()),
+            ("delay", self.delay.to_json()),
+            ("reverb", self.reverb.to_json()),
+        ])
+    }
+}
+
+impl ToJson for FMParams {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("carrier", self.carrier.to_json()),
+            ("modulator", self.modulator.to_json()),
+            ("modulation_index", self.modulation_index.into()),
+            ("amplitude", self.amplitude.into()),
+            ("velocity_sensitivity", self.velocity_sensitivity.into()),
+            ("amp_envelope", self.amp_envelope.to_json()),
+            ("mod_envelope", self.mod_envelope.to_json()),
+            ("lfo1", self.lfo1.to_json()),
+            ("lfo2", self.lfo2.to_json()),
+            ("mod_routes", self.mod_routes.iter().map(ToJson::to_json).collect()),
+            ("voice_mode", variant(&self.voice_mode)),
+            ("portamento", self.portamento.to_json()),
+            ("pitch_bend_range", self.pitch_bend_range.into()),
+            ("unison", self.unison.to_json()),
+            ("filter", self.filter.to_json()),
+            ("effects", self.effects.to_json()),
+        ])
+    }
+}
+
+/// A preset with its 1-based number, as in listings
+pub fn preset_json(number: usize, preset: &Preset) -> Json {
+    Json::object([
+        ("number", number.into()),
+        ("name", preset.name.as_str().into()),
+        ("category", preset.category.name().into()),
+        ("tags", preset.tags.iter().map(String::as_str).collect()),
+        ("author", preset.author.as_str().into()),
+        ("description", preset.description.as_str().into()),
+        ("params", preset.params.to_json()),
+    ])
+}
+
+/// A melody with its 1-based number, tempo, and the notes and length it
+/// has when played in `tuning`; those are null if a note does not parse
+pub fn melody_json(number: usize, melody: &Melody, tuning: &Tuning) -> Json {
+    let score = Score::from_melody(melody, tuning).ok();
+    let signature = melody.timing.time_signature;
+    Json::object([
+        ("number", number.into()),
+        ("name", melody.name.into()),
+        ("tempo", melody.timing.tempo.initial().into()),
+        ("time_signature", format!("{}/{}", signature.beats, signature.unit).into()),
+        ("notes", score.as_ref().map(|score| score.notes.len()).into()),
+        ("beats", score.as_ref().map(|score| score.length as f64 / PPQ as f64).into()),
+        ("duration", score.as_ref().map(Score::duration).into()),
+    ])
+}
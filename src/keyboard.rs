@@ -0,0 +1,129 @@
+// src/keyboard.rs - QWERTY rows as a two-octave piano keyboard
+//
+//    2 3   5 6 7   9 0          s d   g h j   l ;
+//   q w e r t y u i o p        z x c v b n m , . /
+//
+// The bottom row starts an octave below the top row, and each row runs on
+// past its octave to the E above.
+
+/// Lowest MIDI note of the bottom row with no octave shift (C3)
+pub const BASE_NOTE: i32 = 48;
+
+/// Furthest the keyboard can be shifted, in octaves either way
+pub const MAX_OCTAVE_SHIFT: i32 = 3;
+
+/// Velocity of new notes until it is changed
+pub const DEFAULT_VELOCITY: f32 = 0.8;
+
+/// Bottom row keys and their semitones above its C
+pub const BOTTOM_ROW: [(char, i32); 17] = [
+    ('z', 0), ('s', 1), ('x', 2), ('d', 3), ('c', 4), ('v', 5), ('g', 6), ('b', 7),
+    ('h', 8), ('n', 9), ('j', 10), ('m', 11), (',', 12), ('l', 13), ('.', 14), (';', 15), ('/', 16),
+];
+
+/// Top row keys, starting an octave above the bottom row
+pub const TOP_ROW: [(char, i32); 17] = [
+    ('q', 12), ('2', 13), ('w', 14), ('3', 15), ('e', 16), ('r', 17), ('5', 18), ('t', 19),
+    ('6', 20), ('y', 21), ('7', 22), ('u', 23), ('i', 24), ('9', 25), ('o', 26), ('0', 27), ('p', 28),
+];
+
+/// Semitones above the bottom row's C for a key, if it plays a note
+pub fn key_offset(key: char) -> Option<i32> {
+    let key = key.to_ascii_lowercase();
+    BOTTOM_ROW.iter().chain(TOP_ROW.iter())
+        .find(|(k, _)| *k == key)
+        .map(|&(_, offset)| offset)
+}
+
+/// Semitones the keyboard spans, bottom row C to top row E
+pub fn span() -> i32 {
+    TOP_ROW[TOP_ROW.len() - 1].1 + 1
+}
+
+/// Key pressed at a time in seconds, with the MIDI note it started
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct HeldKey {
+    key: char,
+    note: i32,
+    seen: f64,
+}
+
+/// Octave shift, velocity and the keys currently down
+#[derive(Clone, Debug)]
+pub struct KeyboardState {
+    pub octave: i32,
+    pub velocity: f32,   // 0.1 - 1.0
+    held: Vec<HeldKey>,
+}
+
+impl Default for KeyboardState {
+    fn default() -> Self {
+        Self { octave: 0, velocity: DEFAULT_VELOCITY, held: Vec::new() }
+    }
+}
+
+impl KeyboardState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shift_octave(&mut self, by: i32) {
+        self.octave = (self.octave + by).clamp(-MAX_OCTAVE_SHIFT, MAX_OCTAVE_SHIFT);
+    }
+
+    /// Change the velocity of new notes, in steps of a tenth
+    pub fn change_velocity(&mut self, steps: i32) {
+        let tenths = (self.velocity * 10.0).round() as i32 + steps;
+        self.velocity = tenths.clamp(1, 10) as f32 / 10.0;
+    }
+
+    /// Lowest and highest notes the keys play at the current octave
+    pub fn range(&self) -> (i32, i32) {
+        let low = BASE_NOTE + 12 * self.octave;
+        (low, low + span() - 1)
+    }
+
+    /// MIDI note a key plays at the current octave
+    pub fn note(&self, key: char) -> Option<i32> {
+        key_offset(key).map(|offset| BASE_NOTE + 12 * self.octave + offset)
+    }
+
+    /// Record a press at `now`; returns the note to start, or None when the
+    /// key is already down (a repeat) or plays nothing
+    pub fn press(&mut self, key: char, now: f64) -> Option<i32> {
+        let key = key.to_ascii_lowercase();
+        if let Some(held) = self.held.iter_mut().find(|held| held.key == key) {
+            held.seen = now;
+            return None;
+        }
+        let note = self.note(key)?;
+        self.held.push(HeldKey { key, note, seen: now });
+        Some(note)
+    }
+
+    /// Record a release; returns the note the key started
+    pub fn release(&mut self, key: char) -> Option<i32> {
+        let key = key.to_ascii_lowercase();
+        let index = self.held.iter().position(|held| held.key == key)?;
+        Some(self.held.remove(index).note)
+    }
+
+    /// For terminals that report no releases: let go of keys not pressed or
+    /// repeated within `hold` seconds, returning their notes
+    pub fn expire(&mut self, now: f64, hold: f64) -> Vec<i32> {
+        let (expired, held): (Vec<HeldKey>, Vec<HeldKey>) = self.held.iter()
+            .partition(|held| now - held.seen >= hold);
+        self.held = held;
+        expired.into_iter().map(|held| held.note).collect()
+    }
+
+    /// Let go of every key, returning their notes
+    pub fn release_all(&mut self) -> Vec<i32> {
+        self.held.drain(..).map(|held| held.note).collect()
+    }
+
+    /// Notes of the keys currently down
+    pub fn held_notes(&self) -> impl Iterator<Item = i32> + '_ {
+        self.held.iter().map(|held| held.note)
+    }
+}
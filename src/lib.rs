@@ -1,186 +1,79 @@
-// src/lib.rs - WebAssembly library entry point
+// src/lib.rs - Synthesis engine shared by the desktop CLI and the WebAssembly build
+
+pub mod arpeggiator;
+pub mod effects;
+pub mod engine;
+pub mod filter;
+pub mod glide;
+pub mod json;
+pub mod keyboard;
+pub mod master;
+pub mod melodies;
+pub mod midi;
+pub mod modulation;
+pub mod notation;
+pub mod operator;
+pub mod pitch;
+pub mod presets;
+pub mod recording;
+pub mod resolve;
+pub mod rng;
+pub mod scope;
+pub mod score;
+pub mod script;
+pub mod sequence;
+pub mod sequencer;
+pub mod synth;
+pub mod timing;
+pub mod tuning;
+pub mod wav;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 #[cfg(target_arch = "wasm32")]
-use web_sys::AudioContext;
-
-/// FM Synthesizer parameters
-#[derive(Clone, Debug)]
-struct FMParams {
-    carrier_freq: f32,
-    modulator_freq: f32,
-    modulation_index: f32,
-    amplitude: f32,
-}
+use web_sys::{AudioContext, AudioNode, BiquadFilterNode, BiquadFilterType, DynamicsCompressorNode};
 
-impl Default for FMParams {
-    fn default() -> Self {
-        Self {
-            carrier_freq: 440.0,
-            modulator_freq: 220.0,
-            modulation_index: 2.0,
-            amplitude: 0.3,
-        }
-    }
-}
-
-/// Note frequencies
-fn note_freq(note: &str) -> f32 {
-    match note {
-        "C3" => 130.81, "C#3" => 138.59, "D3" => 146.83, "D#3" => 155.56, "E3" => 164.81,
-        "F3" => 174.61, "F#3" => 185.00, "G3" => 196.00, "G#3" => 207.65, "A3" => 220.00,
-        "A#3" => 233.08, "B3" => 246.94,
-        "C4" => 261.63, "C#4" => 277.18, "D4" => 293.66, "D#4" => 311.13, "E4" => 329.63,
-        "F4" => 349.23, "F#4" => 369.99, "G4" => 392.00, "G#4" => 415.30, "A4" => 440.00,
-        "A#4" => 466.16, "B4" => 493.88,
-        "C5" => 523.25, "C#5" => 554.37, "D5" => 587.33, "D#5" => 622.25, "E5" => 659.25,
-        "F5" => 698.46, "F#5" => 739.99, "G5" => 783.99, "G#5" => 830.61, "A5" => 880.00,
-        _ => 0.0, // Rest
-    }
-}
+#[cfg(target_arch = "wasm32")]
+use crate::filter::FilterMode;
+#[cfg(target_arch = "wasm32")]
+use crate::glide::GlideCurve;
+#[cfg(target_arch = "wasm32")]
+use crate::json::{melody_json, preset_json, Json};
+#[cfg(target_arch = "wasm32")]
+use crate::melodies::{get_melodies, Melody};
+#[cfg(target_arch = "wasm32")]
+use crate::midi::{MidiFile, MidiFormat};
+#[cfg(target_arch = "wasm32")]
+use crate::tuning::{KeyboardMap, Scale, Tuning};
+#[cfg(target_arch = "wasm32")]
+use crate::presets::{add_preset, get_presets, search, Category, Preset};
+#[cfg(target_arch = "wasm32")]
+use crate::resolve::resolve;
+#[cfg(target_arch = "wasm32")]
+use crate::score::Score;
+#[cfg(target_arch = "wasm32")]
+use crate::sequence::EventKind;
+#[cfg(target_arch = "wasm32")]
+use crate::sequencer::{PlayMode, Sequencer, DEMO_SONG};
+#[cfg(target_arch = "wasm32")]
+use crate::synth::{FMParams, VoiceMode};
 
-/// Preset definitions
-fn get_presets() -> Vec<(&'static str, FMParams)> {
-    vec![
-        ("Bell", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 7.0,
-            amplitude: 0.3,
-        }),
-        ("Bass", FMParams {
-            carrier_freq: 110.0,
-            modulator_freq: 110.0,
-            modulation_index: 1.5,
-            amplitude: 0.5,
-        }),
-        ("Electric Piano", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 880.0,
-            modulation_index: 3.0,
-            amplitude: 0.4,
-        }),
-        ("Brass", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 2.5,
-            amplitude: 0.4,
-        }),
-        ("Organ", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 880.0,
-            modulation_index: 1.0,
-            amplitude: 0.4,
-        }),
-        ("Synth Lead", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 1320.0,
-            modulation_index: 4.0,
-            amplitude: 0.35,
-        }),
-        ("Marimba", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 3.5,
-            amplitude: 0.4,
-        }),
-        ("Strings", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 220.0,
-            modulation_index: 0.8,
-            amplitude: 0.3,
-        }),
-        ("Flute", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 0.5,
-            amplitude: 0.25,
-        }),
-        ("Metallic", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 567.0,
-            modulation_index: 9.0,
-            amplitude: 0.3,
-        }),
-        ("Glockenspiel", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 1760.0,
-            modulation_index: 2.5,
-            amplitude: 0.3,
-        }),
-        ("Wood Block", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 300.0,
-            modulation_index: 12.0,
-            amplitude: 0.4,
-        }),
-    ]
+/// A JavaScript value with the same structure as `json`
+#[cfg(target_arch = "wasm32")]
+fn to_js(json: Json) -> Result<JsValue, JsValue> {
+    js_sys::JSON::parse(&json.to_string())
 }
 
-/// Melody definitions
-fn get_melodies() -> Vec<(&'static str, Vec<(&'static str, u64)>)> {
-    vec![
-        ("Twinkle Twinkle", vec![
-            ("C4", 500), ("C4", 500), ("G4", 500), ("G4", 500),
-            ("A4", 500), ("A4", 500), ("G4", 1000),
-            ("F4", 500), ("F4", 500), ("E4", 500), ("E4", 500),
-            ("D4", 500), ("D4", 500), ("C4", 1000),
-        ]),
-        ("Happy Birthday", vec![
-            ("C4", 250), ("C4", 250), ("D4", 500), ("C4", 500),
-            ("F4", 500), ("E4", 1000),
-            ("C4", 250), ("C4", 250), ("D4", 500), ("C4", 500),
-            ("G4", 500), ("F4", 1000),
-        ]),
-        ("Ode to Joy", vec![
-            ("E4", 500), ("E4", 500), ("F4", 500), ("G4", 500),
-            ("G4", 500), ("F4", 500), ("E4", 500), ("D4", 500),
-            ("C4", 500), ("C4", 500), ("D4", 500), ("E4", 500),
-            ("E4", 750), ("D4", 250), ("D4", 1000),
-        ]),
-        ("Mary Had a Little Lamb", vec![
-            ("E4", 500), ("D4", 500), ("C4", 500), ("D4", 500),
-            ("E4", 500), ("E4", 500), ("E4", 1000),
-            ("D4", 500), ("D4", 500), ("D4", 1000),
-            ("E4", 500), ("G4", 500), ("G4", 1000),
-        ]),
-        ("Chromatic Scale", vec![
-            ("C4", 200), ("C#4", 200), ("D4", 200), ("D#4", 200),
-            ("E4", 200), ("F4", 200), ("F#4", 200), ("G4", 200),
-            ("G#4", 200), ("A4", 200), ("A#4", 200), ("B4", 200),
-            ("C5", 400),
-        ]),
-        ("Major Arpeggio", vec![
-            ("C4", 300), ("E4", 300), ("G4", 300), ("C5", 300),
-            ("G4", 300), ("E4", 300), ("C4", 600),
-        ]),
-        ("Minor Pentatonic", vec![
-            ("A3", 400), ("C4", 400), ("D4", 400), ("E4", 400),
-            ("G4", 400), ("A4", 400), ("G4", 400), ("E4", 400),
-            ("D4", 400), ("C4", 400), ("A3", 800),
-        ]),
-        ("Jazz Lick", vec![
-            ("C4", 200), ("E4", 200), ("G4", 200), ("A#4", 200),
-            ("A4", 400), ("F4", 200), ("D4", 400),
-            ("G4", 200), ("E4", 200), ("C4", 600),
-        ]),
-        ("Bach Invention", vec![
-            ("C4", 200), ("D4", 200), ("E4", 200), ("F4", 200),
-            ("D4", 200), ("E4", 200), ("C4", 400),
-            ("G4", 200), ("F4", 200), ("E4", 200), ("D4", 200),
-            ("B3", 200), ("C4", 600),
-        ]),
-        ("Synth Demo", vec![
-            ("C4", 150), ("E4", 150), ("G4", 150), ("C5", 150),
-            ("E5", 150), ("G5", 150), ("E5", 150), ("C5", 150),
-            ("G4", 150), ("E4", 150), ("C4", 300),
-            ("REST", 300),
-            ("F4", 150), ("A4", 150), ("C5", 150), ("F5", 150),
-            ("C5", 150), ("A4", 150), ("F4", 300),
-        ]),
-    ]
+/// Resolve after `ms` milliseconds on the browser's timer
+#[cfg(target_arch = "wasm32")]
+async fn sleep(ms: i32) -> Result<(), JsValue> {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let window = web_sys::window().unwrap();
+        window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms).unwrap();
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await?;
+    Ok(())
 }
 
 // WebAssembly exports
@@ -188,8 +81,11 @@ fn get_melodies() -> Vec<(&'static str, Vec<(&'static str, u64)>)> {
 #[wasm_bindgen]
 pub struct WebFMSynth {
     context: AudioContext,
-    presets: Vec<(&'static str, FMParams)>,
-    melodies: Vec<(&'static str, Vec<(&'static str, u64)>)>,
+    master: DynamicsCompressorNode,
+    presets: Vec<Preset>,
+    melodies: Vec<Melody>,
+    tuning: Tuning,
+    tempo: Option<f64>,   // Overrides each melody's own tempo when set
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -201,27 +97,141 @@ impl WebFMSynth {
         console_error_panic_hook::set_once();
         
         let context = AudioContext::new()?;
+        
+        // Master limiter: a fast, hard-knee compressor just below full scale
+        let master = context.create_dynamics_compressor()?;
+        master.threshold().set_value(-1.0);
+        master.knee().set_value(0.0);
+        master.ratio().set_value(20.0);
+        master.attack().set_value(0.003);
+        master.release().set_value(0.1);
+        master.connect_with_audio_node(&context.destination())?;
+        
         Ok(WebFMSynth {
             context,
+            master,
             presets: get_presets(),
             melodies: get_melodies(),
+            tuning: Tuning::default(),
+            tempo: None,
         })
     }
 
-    pub fn list_presets(&self) -> String {
-        self.presets.iter()
+    /// Set the A4 reference pitch in Hz
+    pub fn set_a4(&mut self, hz: f32) -> Result<(), JsValue> {
+        if !(hz.is_finite() && hz > 0.0) {
+            return Err(JsValue::from_str("Invalid A4 frequency"));
+        }
+        self.tuning.a4 = hz;
+        Ok(())
+    }
+
+    pub fn a4(&self) -> f32 {
+        self.tuning.a4
+    }
+
+    /// Use a built-in scale (`just`, `meantone`, `19edo`, ...) or `12tet`
+    pub fn set_scale(&mut self, name: &str) -> Result<(), JsValue> {
+        let scale = Scale::builtin(name)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown scale '{}'", name)))?;
+        self.tuning.set_scale(Some(scale));
+        Ok(())
+    }
+
+    /// Use the text of a Scala `.scl` file
+    pub fn load_scl(&mut self, text: &str) -> Result<(), JsValue> {
+        let scale = Scale::parse(text).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.tuning.set_scale(Some(scale));
+        Ok(())
+    }
+
+    /// Use the text of a Scala `.kbm` keyboard map; empty text clears it
+    pub fn load_kbm(&mut self, text: &str) -> Result<(), JsValue> {
+        let map = match text.trim() {
+            "" => None,
+            text => Some(KeyboardMap::parse(text).map_err(|e| JsValue::from_str(&e.to_string()))?),
+        };
+        self.tuning.set_keyboard(map);
+        Ok(())
+    }
+
+    pub fn describe_tuning(&self) -> String {
+        self.tuning.describe()
+    }
+
+    /// Presets as an array of `{number, name, category, tags, author, description, params}`
+    /// objects with every parameter
+    pub fn list_presets(&self) -> Result<JsValue, JsValue> {
+        to_js(self.presets.iter()
+            .enumerate()
+            .map(|(i, preset)| preset_json(i + 1, preset))
+            .collect())
+    }
+
+    /// Presets in a category such as `bass`, described as `list_presets` does;
+    /// an error for an unknown category names the categories
+    pub fn list_category(&self, category: &str) -> Result<JsValue, JsValue> {
+        let category = Category::lookup(category).map_err(|e| JsValue::from_str(&e))?;
+        to_js(self.presets.iter()
             .enumerate()
-            .map(|(i, (name, _))| format!("{}. {}", i + 1, name))
-            .collect::<Vec<_>>()
-            .join("\n")
+            .filter(|(_, preset)| preset.category == category)
+            .map(|(i, preset)| preset_json(i + 1, preset))
+            .collect())
+    }
+
+    /// Presets whose name, category, tags, author or description contain
+    /// every word of `query`, as `list_presets` describes them
+    pub fn search_presets(&self, query: &str) -> Result<JsValue, JsValue> {
+        to_js(search(&self.presets, query)
+            .into_iter()
+            .map(|i| preset_json(i + 1, &self.presets[i]))
+            .collect())
+    }
+
+    /// Add a preset from the text of a preset file (see presets.rs), replacing
+    /// one of the same name; returns its index
+    pub fn load_preset(&mut self, text: &str) -> Result<usize, JsValue> {
+        let preset = Preset::parse(text, |name| {
+            self.find_preset(name).ok().map(|index| self.presets[index].clone())
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(add_preset(&mut self.presets, preset))
     }
 
-    pub fn list_melodies(&self) -> String {
-        self.melodies.iter()
+    /// Melodies as an array of `{number, name, tempo, time_signature, notes, beats, duration}`
+    /// objects, with the note count and length in the current tuning
+    pub fn list_melodies(&self) -> Result<JsValue, JsValue> {
+        to_js(self.melodies.iter()
             .enumerate()
-            .map(|(i, (name, _))| format!("{}. {}", i + 1, name))
-            .collect::<Vec<_>>()
-            .join("\n")
+            .map(|(i, melody)| melody_json(i + 1, melody, &self.tuning))
+            .collect())
+    }
+
+    /// Index of the preset named by number, name, prefix or close misspelling
+    pub fn find_preset(&self, name: &str) -> Result<usize, JsValue> {
+        resolve("Preset", name, self.presets.iter().map(|preset| preset.name.as_str()))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Index of the melody named by number, name, prefix or close misspelling
+    pub fn find_melody(&self, name: &str) -> Result<usize, JsValue> {
+        resolve("Melody", name, self.melodies.iter().map(|melody| melody.name))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// A melody as Standard MIDI File bytes, at the tempo set by `set_tempo`
+    pub fn export_midi(&self, melody_idx: usize) -> Result<Vec<u8>, JsValue> {
+        let melody = self.melodies.get(melody_idx).ok_or_else(|| JsValue::from_str("Invalid melody index"))?;
+        let mut score = Score::from_melody(melody, &self.tuning).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        if let Some(bpm) = self.tempo {
+            score.set_tempo(bpm);
+        }
+        Ok(MidiFile::from_score(melody.name, &score, &self.tuning).encode(MidiFormat::default()))
+    }
+
+    /// Play melodies at `bpm` instead of their own tempo; 0 restores it
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.tempo = (bpm.is_finite() && bpm > 0.0).then_some(bpm);
     }
 
     pub async fn play_melody(&self, preset_idx: usize, melody_idx: usize) -> Result<(), JsValue> {
@@ -229,70 +239,231 @@ impl WebFMSynth {
             return Err(JsValue::from_str("Invalid preset or melody index"));
         }
 
-        let preset = &self.presets[preset_idx].1;
-        let melody = &self.melodies[melody_idx].1;
+        let preset = &self.presets[preset_idx].params;
+        let score = Score::from_melody(&self.melodies[melody_idx], &self.tuning)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.play_score(preset, score).await
+    }
+
+    /// Play melody notation text; errors carry the line and column
+    pub async fn play_notation(&self, preset_idx: usize, text: &str) -> Result<(), JsValue> {
+        let preset = self.presets.get(preset_idx)
+            .map(|preset| &preset.params)
+            .ok_or_else(|| JsValue::from_str("Invalid preset index"))?;
+        let score = notation::parse(text, &self.tuning).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.play_score(preset, score).await
+    }
+
+    /// Text of the built-in step sequencer song
+    pub fn demo_song(&self) -> String {
+        DEMO_SONG.to_string()
+    }
+
+    /// Play a step sequencer song (see sequencer.rs for the format) once through
+    pub async fn play_sequencer(&self, text: &str) -> Result<(), JsValue> {
+        let mut sequencer = Sequencer::parse(text, |name| {
+            self.presets.iter()
+                .enumerate()
+                .find(|(i, preset)| preset.name.eq_ignore_ascii_case(name) || name.parse::<usize>() == Ok(i + 1))
+                .map(|(_, preset)| (preset.name.clone(), preset.params.clone()))
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        sequencer.tuning = self.tuning.clone();
+        if let Some(bpm) = self.tempo {
+            sequencer.set_tempo(bpm);
+        }
+        if sequencer.song.is_empty() {
+            return Err(JsValue::from_str("The song has no patterns"));
+        }
+
+        let (triggers, length) = sequencer.schedule(PlayMode::Song, 1);
+        let mut elapsed = 0.0;
+        for trigger in &triggers {
+            if trigger.time > elapsed {
+                sleep(((trigger.time - elapsed) * 1000.0) as i32).await?;
+                elapsed = trigger.time;
+            }
+            let preset = &sequencer.tracks[trigger.track].preset;
+            self.play_note(trigger.freq, None, preset, trigger.length as f32, trigger.velocity)?;
+        }
+
+        if length > elapsed {
+            sleep(((length - elapsed) * 1000.0) as i32).await?;
+        }
+        Ok(())
+    }
+
+    /// Schedule each note on the audio clock as its start time comes up
+    async fn play_score(&self, preset: &FMParams, mut score: Score) -> Result<(), JsValue> {
+        if let Some(bpm) = self.tempo {
+            score.set_tempo(bpm);
+        }
+        let sequence = score.to_sequence();
+        let mut previous = None;
+        let mut elapsed = 0.0;
+
+        for (i, event) in sequence.events.iter().enumerate() {
+            let EventKind::NoteOn { freq, velocity } = event.kind else { continue };
+            let release = sequence.events[i..].iter()
+                .find(|e| e.kind == EventKind::NoteOff { freq })
+                .map_or(sequence.length, |e| e.time);
 
-        for (note, duration) in melody {
-            let freq = note_freq(note);
-            if freq > 0.0 {
-                self.play_note(freq, preset, *duration as f32 / 1000.0)?;
+            if event.time > elapsed {
+                sleep(((event.time - elapsed) * 1000.0) as i32).await?;
+                elapsed = event.time;
             }
-            
-            // Wait for note duration
-            let promise = js_sys::Promise::new(&mut |resolve, _| {
-                let window = web_sys::window().unwrap();
-                window.set_timeout_with_callback_and_timeout_and_arguments_0(
-                    &resolve,
-                    *duration as i32,
-                ).unwrap();
-            });
-            wasm_bindgen_futures::JsFuture::from(promise).await?;
+            // Only single-voice patches slide from the previous note
+            let glide_from = previous.filter(|_| preset.voice_mode != VoiceMode::Poly);
+            self.play_note(freq, glide_from, preset, (release - event.time) as f32, velocity)?;
+            previous = Some(freq);
         }
 
+        if sequence.length > elapsed {
+            sleep(((sequence.length - elapsed) * 1000.0) as i32).await?;
+        }
         Ok(())
     }
 
-    fn play_note(&self, freq: f32, preset: &FMParams, duration: f32) -> Result<(), JsValue> {
+    fn play_note(&self, freq: f32, glide_from: Option<f32>, preset: &FMParams, duration: f32, velocity: f32) -> Result<(), JsValue> {
         let current_time = self.context.current_time();
         
-        // Create carrier oscillator
-        let carrier = self.context.create_oscillator()?;
-        carrier.frequency().set_value(freq);
-        
-        // Create modulator oscillator
-        let modulator = self.context.create_oscillator()?;
-        let freq_ratio = freq / 440.0;
-        modulator.frequency().set_value(preset.modulator_freq * freq_ratio);
-        
-        // Create modulation gain
-        let mod_gain = self.context.create_gain()?;
-        mod_gain.gain().set_value(preset.modulation_index * freq);
-        
         // Create output gain with envelope
         let output_gain = self.context.create_gain()?;
         let gain_param = output_gain.gain();
         
-        // ADSR envelope (convert f32 duration to f64)
+        // ADSR envelope (convert f32 duration to f64), unison stack kept at single-oscillator level
         let duration_f64 = duration as f64;
-        let amplitude_f64 = preset.amplitude as f64;
+        let unison_gain = 1.0 / (preset.unison.count() as f32).sqrt();
+        let velocity_gain = 1.0 - preset.velocity_sensitivity.clamp(0.0, 1.0) * (1.0 - velocity);
+        let amplitude_f64 = (preset.amplitude * preset.carrier.level_gain(freq) * unison_gain * velocity_gain) as f64;
         gain_param.set_value_at_time(0.0, current_time)?;
         gain_param.linear_ramp_to_value_at_time(amplitude_f64 as f32, current_time + 0.01)?;
         gain_param.exponential_ramp_to_value_at_time(amplitude_f64 as f32 * 0.7, current_time + 0.1)?;
         gain_param.linear_ramp_to_value_at_time(0.001, current_time + duration_f64)?;
+        output_gain.connect_with_audio_node(&self.master)?;
+        
+        // Optional filter between the unison stack and the envelope
+        let voice_input: AudioNode = match self.create_filter(freq, preset, current_time, duration_f64)? {
+            Some(filter) => {
+                filter.connect_with_audio_node(&output_gain)?;
+                filter.into()
+            }
+            None => output_gain.clone().into(),
+        };
+        
+        // One carrier/modulator pair per unison copy
+        for i in 0..preset.unison.count() {
+            let ratio = preset.unison.detune_ratio(i);
+            let panner = self.context.create_stereo_panner()?;
+            panner.pan().set_value(preset.unison.pan(i));
+            panner.connect_with_audio_node(&voice_input)?;
+            self.play_operators(
+                freq * ratio,
+                glide_from.map(|from| from * ratio),
+                preset,
+                &panner,
+                current_time + duration_f64 + 0.1,
+            )?;
+        }
+        
+        Ok(())
+    }
+
+    fn create_filter(
+        &self,
+        freq: f32,
+        preset: &FMParams,
+        start: f64,
+        duration: f64,
+    ) -> Result<Option<BiquadFilterNode>, JsValue> {
+        let params = &preset.filter;
+        let filter_type = match params.mode {
+            FilterMode::Off => return Ok(None),
+            FilterMode::LowPass => BiquadFilterType::Lowpass,
+            FilterMode::HighPass => BiquadFilterType::Highpass,
+            FilterMode::BandPass => BiquadFilterType::Bandpass,
+            FilterMode::Notch => BiquadFilterType::Notch,
+        };
+        
+        let filter = self.context.create_biquad_filter()?;
+        filter.set_type(filter_type);
+        // Same damping as the native state-variable filter. Web Audio takes
+        // low- and high-pass Q as the resonant peak in dB, the others as plain Q.
+        let q = 1.0 / (2.0 - 1.98 * params.resonance.clamp(0.0, 1.0));
+        filter.q().set_value(match params.mode {
+            FilterMode::LowPass | FilterMode::HighPass => 20.0 * q.log10(),
+            _ => q,
+        });
+        
+        // Follow the modulation envelope's attack and decay, then fall back to the base cutoff
+        let env = &preset.mod_envelope;
+        let cutoff = filter.frequency();
+        let base = params.cutoff_for(freq, 0.0);
+        cutoff.set_value_at_time(base, start)?;
+        if params.env_amount != 0.0 {
+            let attack = start + env.attack as f64;
+            let decay = attack + env.decay as f64;
+            cutoff.exponential_ramp_to_value_at_time(params.cutoff_for(freq, params.env_amount), attack)?;
+            cutoff.exponential_ramp_to_value_at_time(params.cutoff_for(freq, params.env_amount * env.sustain), decay)?;
+            cutoff.exponential_ramp_to_value_at_time(base, (start + duration).max(decay))?;
+        }
+        
+        Ok(Some(filter))
+    }
+
+    fn play_operators(
+        &self,
+        freq: f32,
+        glide_from: Option<f32>,
+        preset: &FMParams,
+        output: &AudioNode,
+        stop_time: f64,
+    ) -> Result<(), JsValue> {
+        let current_time = self.context.current_time();
+        
+        // Create carrier oscillator
+        let carrier = self.context.create_oscillator()?;
+        let carrier_freq = preset.carrier.frequency(freq, 1.0);
+        carrier.frequency().set_value(carrier_freq);
+        
+        // Create modulator oscillator
+        let modulator = self.context.create_oscillator()?;
+        modulator.frequency().set_value(preset.modulator.frequency(freq, 1.0));
+        
+        // Portamento: ramp both oscillators from the previous note's pitch
+        if let Some(from) = glide_from.filter(|_| preset.portamento.is_enabled()) {
+            let glide_time = preset.portamento.duration((freq / from).log2()) as f64;
+            for (param, op) in [
+                (carrier.frequency(), &preset.carrier),
+                (modulator.frequency(), &preset.modulator),
+            ] {
+                let (start, end) = (op.frequency(from, 1.0), op.frequency(freq, 1.0));
+                param.set_value_at_time(start, current_time)?;
+                // An exponential ramp in Hz is a straight line in semitones
+                match preset.portamento.curve {
+                    GlideCurve::Linear => param.exponential_ramp_to_value_at_time(end, current_time + glide_time)?,
+                    GlideCurve::Exponential => param.set_target_at_time(end, current_time, glide_time / 5.0)?,
+                };
+            }
+        }
+        
+        // Create modulation gain (peak deviation in Hz, with keyboard level scaling)
+        let mod_gain = self.context.create_gain()?;
+        let index = preset.modulation_index * preset.modulator.level_gain(freq);
+        mod_gain.gain().set_value(index * carrier_freq);
         
         // Connect FM synthesis chain
         modulator.connect_with_audio_node(&mod_gain)?;
         mod_gain.connect_with_audio_param(&carrier.frequency())?;
-        carrier.connect_with_audio_node(&output_gain)?;
-        output_gain.connect_with_audio_node(&self.context.destination())?;
+        carrier.connect_with_audio_node(output)?;
         
         // Start oscillators
         modulator.start()?;
         carrier.start()?;
         
-        // Stop oscillators after duration (convert f32 to f64)
-        modulator.stop_with_when(current_time + duration_f64 + 0.1)?;
-        carrier.stop_with_when(current_time + duration_f64 + 0.1)?;
+        // Stop oscillators once the envelope has finished
+        modulator.stop_with_when(stop_time)?;
+        carrier.stop_with_when(stop_time)?;
         
         Ok(())
     }
@@ -1,444 +1,1208 @@
-use std::f32::consts::PI;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use std::io::{self, IsTerminal, Write};
+use std::process::ExitCode;
+
+#[cfg(not(target_arch = "wasm32"))]
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(not(target_arch = "wasm32"))]
+use rustyline::completion::{Completer, Pair};
+#[cfg(not(target_arch = "wasm32"))]
+use rustyline::error::ReadlineError;
+#[cfg(not(target_arch = "wasm32"))]
+use rustyline::history::FileHistory;
+#[cfg(not(target_arch = "wasm32"))]
+use rustyline::{Context, Editor, Helper, highlight::Highlighter, hint::Hinter, validate::Validator};
+
+use fm_synth::arpeggiator::{ArpMode, Arpeggiator};
+use fm_synth::effects::EffectsParams;
+use fm_synth::engine::Engine;
+use fm_synth::json::{melody_json, preset_json, Json};
+use fm_synth::master::{MasterParams, MeterReading, GAIN_RANGE_DB};
+use fm_synth::midi::{MidiFile, MidiFormat};
+use fm_synth::melodies::{get_melodies, Melody};
+use fm_synth::notation;
+use fm_synth::tuning::{KeyboardMap, Scale, Tuning};
+use fm_synth::presets::{add_preset, get_presets, search, Category, Preset};
+use fm_synth::recording::{Recording, MAX_RECORDING_SECONDS};
+use fm_synth::resolve::{resolve, resolve_exact, split_command, unquote, ResolveError};
+use fm_synth::score::{Articulation, Score};
+use fm_synth::script;
+use fm_synth::sequencer::{Pattern, PlayMode, Sequencer, SongEntry, Step, DEFAULT_STEPS, DEMO_SONG};
+use fm_synth::operator::OperatorFreq;
+use fm_synth::synth::FMParams;
+use fm_synth::timing::{TimeSignature, Timing, PPQ};
+use fm_synth::wav::write_wav;
+
+/// Default sample rate for offline renders
+const RENDER_SAMPLE_RATE: u32 = 44100;
+
+/// Exit code for a runtime failure such as a missing audio device or unwritable file
+const EXIT_FAILURE: u8 = 1;
+
+/// Exit code for bad arguments, including unknown presets and melodies (as clap uses)
+const EXIT_USAGE: u8 = 2;
 
 #[cfg(not(target_arch = "wasm32"))]
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
-/// FM Synthesizer parameters
-#[derive(Clone, Debug)]
-struct FMParams {
-    carrier_freq: f32,      // Carrier frequency in Hz
-    modulator_freq: f32,    // Modulator frequency in Hz
-    modulation_index: f32,  // Modulation depth
-    amplitude: f32,         // Output amplitude (0.0 - 1.0)
+#[cfg(not(target_arch = "wasm32"))]
+use config::{Config, Source};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod config;
+#[cfg(not(target_arch = "wasm32"))]
+mod piano;
+#[cfg(not(target_arch = "wasm32"))]
+mod tui;
+
+/// CLI interface
+#[allow(clippy::upper_case_acronyms)]
+struct CLI {
+    presets: Vec<Preset>,
+    favorites: Vec<String>,          // Preset names, saved in favorites_path()
+    melodies: Vec<Melody>,
+    session_effects: Option<EffectsParams>,  // Overrides the preset's effects when set
+    master: MasterParams,
+    last_meter: Option<MeterReading>,
+    tuning: Tuning,
+    sequencer: Sequencer,
+    arpeggiator: Option<Arpeggiator>,   // Applied to the chords of every melody played
+    device: Option<String>,             // Output device name; None uses the default
+    sample_rate: u32,                   // For renders
+    recorder: Option<Recorder>,         // Captures live playback while set
+    config: Config,                     // Settings the session started with
+    interactive: bool,                  // At the prompt on a terminal, so Enter can stop a loop
 }
 
-impl Default for FMParams {
-    fn default() -> Self {
+impl CLI {
+    fn new() -> Self {
         Self {
-            carrier_freq: 440.0,
-            modulator_freq: 220.0,
-            modulation_index: 2.0,
-            amplitude: 0.3,
+            presets: get_presets(),
+            favorites: Vec::new(),
+            melodies: get_melodies(),
+            session_effects: None,
+            master: MasterParams::default(),
+            last_meter: None,
+            tuning: Tuning::default(),
+            sequencer: Sequencer::new(),
+            arpeggiator: None,
+            device: None,
+            sample_rate: RENDER_SAMPLE_RATE,
+            recorder: None,
+            config: Config::default(),
+            interactive: false,
         }
     }
-}
 
-/// Note structure for melodies
-#[derive(Clone, Debug)]
-struct Note {
-    frequency: f32,
-    duration_ms: u64,
-}
+    /// Start the session from the settings in `config`
+    fn configure(&mut self, config: Config) {
+        self.device = config.device.clone();
+        self.sample_rate = config.sample_rate;
+        self.master.gain_db = config.gain_db;
+        self.tuning.a4 = config.a4;
+        self.config = config;
+    }
 
-/// FM Synthesizer oscillator
-struct FMOscillator {
-    sample_rate: f32,
-    carrier_phase: f32,
-    modulator_phase: f32,
-    params: FMParams,
-}
+    /// Preset for commands that name none: the configured one, or the first
+    fn default_preset(&self) -> String {
+        self.config.preset.clone().unwrap_or_else(|| self.presets[0].name.clone())
+    }
 
-impl FMOscillator {
-    fn new(sample_rate: f32, params: FMParams) -> Self {
-        Self {
-            sample_rate,
-            carrier_phase: 0.0,
-            modulator_phase: 0.0,
-            params,
+    fn print_menu(&self) {
+        println!("\n=== FM Synthesizer CLI ===");
+        println!("Commands:");
+        println!("  list presets  - Show all available presets");
+        println!("  list melodies - Show all available melodies");
+        println!("  list devices  - Show audio output devices");
+        println!("    list presets --category <category> or --favorites to show only some presets");
+        println!("  search <words...> - Find presets by name, category, tag, author or description");
+        println!("  fav [preset] / unfav <preset> - Mark or unmark a favourite preset, or list favourites");
+        println!("  load-preset <file.preset> - Load a preset file (also read from ~/.config/fm_synth/presets)");
+        println!("    add --json to list or info for machine-readable output");
+        println!("  play [preset] <melody> [tempo <bpm>] - Play a melody with a preset");
+        println!("  render <preset> <melody> <file.wav> [tempo <bpm>] - Render a melody to a WAV file");
+        println!("    <melody> is a melody name or number, a notation file, or quoted notation");
+        println!("    such as \"tempo=96 mf C4:1/2 Eb4 G4 [C4 Eb4 G4]:2\"");
+        println!("  export-midi <melody> <file.mid> [type 0|1] [tempo <bpm>] - Export a melody as a MIDI file");
+        println!("  fx [chorus|delay|reverb <mix>|preset] - Show or set session effects");
+        println!("  master [gain <dB>|limiter on|off|softclip on|off [drive]] - Master output settings");
+        println!("  meter - Show output levels of the last play or render");
+        println!("  tuning [a4 <hz>|scale <name|file.scl>|kbm <file.kbm|off>|list|reset] - Tuning and temperament");
+        println!("  arp [off|up|down|updown|random|played] [octaves <n>] [rate <beats>] [gate <%>] - Arpeggiate chords");
+        println!("  seq [demo|load <file>|play|render <file.wav>|show|mute|solo|tempo|loop|...] - Step sequencer");
+        println!("  piano [preset] - Play a preset from the computer keyboard");
+        println!("  tui [preset] - Full-screen editor with scope, voices and a playable keyboard");
+        println!("  record [start <file>|stop] - Record everything played to <file>.mid and <file>.wav");
+        println!("  config [show] - Show the settings from the config file, environment and flags");
+        println!("  info [preset] - Show version and audio setup, or a preset's settings");
+        println!("  demo - Play all presets with a scale");
+        println!("  wait <seconds> - Pause (useful in scripts)");
+        println!("  echo <text> - Print text");
+        println!("  clear - Clear the screen");
+        println!("  help - Show this menu");
+        println!("  quit - Exit the program");
+        println!();
+    }
+
+    /// Presets by number with their category and tags, or as JSON with all
+    /// their parameters; favourites are starred
+    fn list_presets(&self, json: bool, filter: PresetFilter) {
+        let indices: Vec<usize> = (0..self.presets.len())
+            .filter(|&i| filter.category.is_none_or(|category| self.presets[i].category == category))
+            .filter(|&i| !filter.favorites || self.is_favorite(&self.presets[i]))
+            .collect();
+        if !json {
+            match (filter.category, filter.favorites) {
+                (None, false) => println!("\nAvailable Presets:"),
+                (None, true) => println!("\nFavourite Presets:"),
+                (Some(category), favorites) => println!("\n{} Presets{}:", title_case(category.name()),
+                    if favorites { " (favourites)" } else { "" }),
+            }
         }
+        self.print_presets(&indices, json);
     }
 
-    fn next_sample(&mut self) -> f32 {
-        let modulator = (2.0 * PI * self.modulator_phase).sin();
-        let modulated_freq = self.params.carrier_freq * 
-            (1.0 + self.params.modulation_index * modulator);
-        let carrier = (2.0 * PI * self.carrier_phase).sin();
-        
-        self.carrier_phase += modulated_freq / self.sample_rate;
-        self.modulator_phase += self.params.modulator_freq / self.sample_rate;
-        
-        if self.carrier_phase >= 1.0 {
-            self.carrier_phase -= 1.0;
+    /// Presets whose name, category, tags, author or description contain every word of `query`
+    fn search_presets(&self, query: &str, json: bool) {
+        let indices = search(&self.presets, query);
+        if !json {
+            println!("\nPresets matching '{}':", query);
         }
-        if self.modulator_phase >= 1.0 {
-            self.modulator_phase -= 1.0;
+        self.print_presets(&indices, json);
+    }
+
+    /// Listing of some presets, numbered by their place in the full list
+    fn print_presets(&self, indices: &[usize], json: bool) {
+        if json {
+            let presets: Json = indices.iter().map(|&i| preset_json(i + 1, &self.presets[i])).collect();
+            println!("{}", presets);
+            return;
+        }
+        if indices.is_empty() {
+            println!("  (none)");
+        }
+        for &i in indices {
+            let preset = &self.presets[i];
+            let star = if self.is_favorite(preset) { '*' } else { ' ' };
+            println!(" {}{:>2}. {:<16} {:<11} {}", star, i + 1, preset.name, preset.category.name(), preset.tags.join(", "));
         }
-        
-        carrier * self.params.amplitude
     }
 
-    fn set_params(&mut self, params: FMParams) {
-        self.params = params;
+    /// Melodies by number, or as JSON with their note counts and lengths
+    fn list_melodies(&self, json: bool) {
+        if json {
+            let melodies: Json = self.melodies.iter()
+                .enumerate()
+                .map(|(i, melody)| melody_json(i + 1, melody, &self.tuning))
+                .collect();
+            println!("{}", melodies);
+            return;
+        }
+        println!("\nAvailable Melodies:");
+        for (i, melody) in self.melodies.iter().enumerate() {
+            let signature = melody.timing.time_signature;
+            println!("  {}. {} ({} bpm, {}/{})", i + 1, melody.name,
+                melody.timing.tempo.initial(), signature.beats, signature.unit);
+        }
     }
-}
 
-/// ADSR Envelope generator
-struct Envelope {
-    attack: f32,
-    decay: f32,
-    sustain: f32,
-    release: f32,
-    
-    sample_rate: f32,
-    state: EnvelopeState,
-    level: f32,
-    time: f32,
-}
+    /// Position of a preset in the list, by number or (partial) name
+    fn find_preset_index(&self, name: &str) -> Result<usize, ResolveError> {
+        resolve("Preset", name, self.presets.iter().map(|preset| preset.name.as_str()))
+    }
 
-#[derive(PartialEq)]
-enum EnvelopeState {
-    Idle,
-    Attack,
-    Decay,
-    Sustain,
-    Release,
-}
+    /// Preset and its full name, by number or (partial) name
+    fn find_preset_named(&self, name: &str) -> Result<(String, FMParams), ResolveError> {
+        let index = self.find_preset_index(name)?;
+        let preset = &self.presets[index];
+        Ok((preset.name.clone(), preset.params.clone()))
+    }
 
-impl Envelope {
-    fn new(sample_rate: f32) -> Self {
-        Self {
-            attack: 0.01,
-            decay: 0.1,
-            sustain: 0.7,
-            release: 0.5,
-            sample_rate,
-            state: EnvelopeState::Idle,
-            level: 0.0,
-            time: 0.0,
+    fn is_favorite(&self, preset: &Preset) -> bool {
+        self.favorites.iter().any(|name| name.eq_ignore_ascii_case(&preset.name))
+    }
+
+    /// Add a preset to the favourites, or remove it, and save them
+    fn set_favorite(&mut self, name: &str, favorite: bool) -> anyhow::Result<Result<(), String>> {
+        let index = match self.find_preset_index(name) {
+            Ok(index) => index,
+            Err(e) => return Ok(Err(lookup_message(e, "presets"))),
+        };
+        let name = self.presets[index].name.clone();
+        self.favorites.retain(|favorite| !favorite.eq_ignore_ascii_case(&name));
+        if favorite {
+            self.favorites.push(name.clone());
+        }
+        if let Some(path) = favorites_path() {
+            path.parent().map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::write(&path, self.favorites.iter().map(|name| format!("{}\n", name)).collect::<String>()))
+                .map_err(|e| anyhow::anyhow!("Could not save favourites to {}: {}", path.display(), e))?;
+        }
+        match favorite {
+            true => println!("Added '{}' to favourites", name),
+            false => println!("Removed '{}' from favourites", name),
+        }
+        Ok(Ok(()))
+    }
+
+    /// Add the preset in a preset file (see presets.rs), replacing any of
+    /// the same name; returns its index
+    fn load_preset(&mut self, path: &str) -> Result<usize, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read '{}': {}", path, e))?;
+        let preset = Preset::parse(&text, |base| {
+            self.find_preset_index(base).ok().map(|index| self.presets[index].clone())
+        }).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(add_preset(&mut self.presets, preset))
+    }
+
+    /// Load the favourites and the user's preset files, reporting problems
+    /// as warnings so a bad file does not stop the program
+    fn load_library(&mut self) {
+        if let Some(text) = favorites_path().and_then(|path| std::fs::read_to_string(path).ok()) {
+            self.favorites = text.lines().map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect();
+        }
+        let Some(dir) = config::xdg_path("XDG_CONFIG_HOME", ".config", "presets") else {
+            return;
+        };
+        let mut files: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "preset"))
+                .collect(),
+            Err(_) => return,
+        };
+        files.sort();
+        for file in files {
+            if let Err(e) = self.load_preset(&file.to_string_lossy()) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+    }
+
+    /// Apply the session effects, if any, on top of a preset
+    fn with_session_effects(&self, mut preset: FMParams) -> FMParams {
+        if let Some(effects) = &self.session_effects {
+            preset.effects = effects.clone();
         }
+        preset
     }
 
-    fn trigger(&mut self) {
-        self.state = EnvelopeState::Attack;
-        self.time = 0.0;
+    fn set_effect(&mut self, effect: &str, mix: &str) -> Result<(), String> {
+        let mix: f32 = mix.parse()
+            .map_err(|_| format!("Invalid mix '{}'. Use a value from 0.0 to 1.0", mix))?;
+        let mix = mix.clamp(0.0, 1.0);
+        let effects = self.session_effects.get_or_insert_with(EffectsParams::default);
+        match effect {
+            "chorus" => effects.chorus.mix = mix,
+            "delay" => effects.delay.mix = mix,
+            "reverb" => effects.reverb.mix = mix,
+            _ => return Err(format!("Unknown effect '{}'. Use chorus, delay or reverb", effect)),
+        }
+        Ok(())
     }
 
-    fn release(&mut self) {
-        if self.state != EnvelopeState::Idle {
-            self.state = EnvelopeState::Release;
-            self.time = 0.0;
+    fn print_effects(&self) {
+        match &self.session_effects {
+            Some(effects) => {
+                println!("Session effects (override preset effects):");
+                println!("  chorus: {:.2}", effects.chorus.mix);
+                println!("  delay:  {:.2}", effects.delay.mix);
+                println!("  reverb: {:.2}", effects.reverb.mix);
+            }
+            None => println!("Using each preset's own effects. Set one with 'fx reverb 0.3'"),
+        }
+    }
+
+    fn set_master(&mut self, args: &[&str]) -> Result<(), String> {
+        let switch = |value: &str| match value {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(format!("Expected 'on' or 'off', got '{}'", value)),
+        };
+        let number = |value: &str| value.parse::<f32>().ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| format!("Invalid number '{}'", value));
+
+        match args {
+            ["gain", db] => {
+                self.master.gain_db = number(db).ok()
+                    .filter(|db| GAIN_RANGE_DB.contains(db))
+                    .ok_or_else(|| format!("Invalid gain '{}', expected {} to {} dB",
+                        db, GAIN_RANGE_DB.start(), GAIN_RANGE_DB.end()))?;
+            }
+            ["limiter", state] => self.master.limiter = switch(state)?,
+            ["softclip", state] => self.master.soft_clip = switch(state)?,
+            ["softclip", state, drive] => {
+                self.master.soft_clip = switch(state)?;
+                self.master.drive = number(drive)?.max(0.1);
+            }
+            _ => return Err("Usage: master [gain <dB>|limiter on|off|softclip on|off [drive]]".to_string()),
         }
+        Ok(())
+    }
+
+    fn print_master(&self) {
+        let on_off = |flag: bool| if flag { "on" } else { "off" };
+        println!("Master output:");
+        println!("  gain:     {:+.1} dB", self.master.gain_db);
+        println!("  softclip: {} (drive {:.1})", on_off(self.master.soft_clip), self.master.drive);
+        println!("  limiter:  {} (ceiling {:.1} dBFS)", on_off(self.master.limiter), self.master.ceiling_db);
     }
 
-    fn process(&mut self) -> f32 {
-        let dt = 1.0 / self.sample_rate;
-        
-        match self.state {
-            EnvelopeState::Idle => {
-                self.level = 0.0;
+    fn print_meter(&self) {
+        match &self.last_meter {
+            Some(meter) => {
+                println!("Output levels:");
+                println!("  peak: {:.1} dBFS", meter.peak_db());
+                println!("  rms:  {:.1} dBFS", meter.rms_db());
+                println!("  limiter: {:.1} dB max reduction, {} samples over the ceiling before limiting",
+                    meter.max_reduction, meter.overs);
             }
-            EnvelopeState::Attack => {
-                self.level = self.time / self.attack;
-                if self.time >= self.attack {
-                    self.state = EnvelopeState::Decay;
-                    self.time = 0.0;
+            None => println!("Nothing measured yet. Play or render something first."),
+        }
+    }
+
+    fn set_tuning(&mut self, args: &[&str]) -> Result<(), String> {
+        let read = |path: &str| std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read '{}': {}", path, e));
+
+        match args {
+            ["a4", hz] => {
+                let hz: f32 = hz.parse()
+                    .ok()
+                    .filter(|hz: &f32| hz.is_finite() && *hz > 0.0)
+                    .ok_or_else(|| format!("Invalid A4 frequency '{}'", hz))?;
+                self.tuning.a4 = hz;
+            }
+            ["scale", name] => {
+                let scale = match Scale::builtin(name) {
+                    Some(scale) => scale,
+                    None => Scale::parse(&read(name)?).map_err(|e| format!("{}: {}", name, e))?,
+                };
+                self.tuning.set_scale(Some(scale));
+            }
+            ["kbm", "off"] => self.tuning.set_keyboard(None),
+            ["kbm", path] => {
+                let map = KeyboardMap::parse(&read(path)?).map_err(|e| format!("{}: {}", path, e))?;
+                self.tuning.set_keyboard(Some(map));
+            }
+            ["reset"] => self.tuning = Tuning::default(),
+            _ => return Err("Usage: tuning [a4 <hz>|scale <name|file.scl>|kbm <file.kbm|off>|list|reset]".to_string()),
+        }
+        Ok(())
+    }
+
+    fn print_tuning(&self) {
+        println!("Tuning: {}", self.tuning.describe());
+    }
+
+    fn list_tunings(&self) {
+        println!("\nBuilt-in scales:");
+        for name in Scale::builtin_names() {
+            println!("  {}", name);
+        }
+        println!("  <n>edo   - n equal divisions of the octave, e.g. 19edo");
+        println!("Or load a Scala file with 'tuning scale <file.scl>'");
+    }
+
+    fn find_melody(&self, name: &str) -> Result<&Melody, ResolveError> {
+        resolve("Melody", name, self.melodies.iter().map(|m| m.name)).map(|index| &self.melodies[index])
+    }
+
+    /// Resolve a melody argument: quoted notation, a notation file,
+    /// a built-in melody, or else bare notation
+    fn find_score(&self, arg: &str) -> Result<Score, String> {
+        let mut score = self.load_score(arg)?;
+        if let Some(arpeggiator) = &self.arpeggiator {
+            score.arpeggiate(arpeggiator, &self.tuning);
+        }
+        Ok(score)
+    }
+
+    /// Session arpeggiator: `off`, or a mode with optional settings
+    fn set_arpeggiator(&mut self, args: &[&str]) -> Result<(), String> {
+        let (mode, mut options) = match args {
+            ["off"] => {
+                self.arpeggiator = None;
+                return Ok(());
+            }
+            [mode, options @ ..] => (*mode, options),
+            [] => return Err(ARP_USAGE.to_string()),
+        };
+        let mode = ArpMode::parse(mode).ok_or_else(|| format!("Unknown arpeggiator mode '{}'", mode))?;
+        let mut arpeggiator = Arpeggiator::new(mode);
+        while let [option, value, rest @ ..] = options {
+            match *option {
+                "octaves" => arpeggiator.octaves = value.parse().ok()
+                    .filter(|n| (1..=4).contains(n))
+                    .ok_or("Octaves must be 1 to 4")?,
+                "rate" => arpeggiator.set_rate_beats(notation::parse_beats(value).ok_or_else(|| format!("Invalid rate '{}'", value))?),
+                "gate" => arpeggiator.gate = value.trim_end_matches('%').parse::<f64>().ok()
+                    .filter(|gate| *gate > 0.0 && *gate <= 100.0)
+                    .map(|gate| gate / 100.0)
+                    .ok_or("Gate must be 1 to 100")?,
+                _ => return Err(ARP_USAGE.to_string()),
+            }
+            options = rest;
+        }
+        if !options.is_empty() {
+            return Err(ARP_USAGE.to_string());
+        }
+        self.arpeggiator = Some(arpeggiator);
+        Ok(())
+    }
+
+    fn print_arpeggiator(&self) {
+        match &self.arpeggiator {
+            Some(arpeggiator) => println!("Arpeggiator: {}", arpeggiator.describe()),
+            None => println!("Arpeggiator: off (chords play together)"),
+        }
+    }
+
+    fn load_score(&self, arg: &str) -> Result<Score, String> {
+        let parse = |text: &str| notation::parse(text, &self.tuning).map_err(|e| format!("Notation error at {}", e));
+        let melody_score = |melody: &Melody| Score::from_melody(melody, &self.tuning)
+            .map_err(|e| format!("Melody '{}' has a bad note: {}", melody.name, e));
+
+        // Quoted text is notation, unless it only makes sense as a melody name
+        if arg.starts_with('"') && arg.ends_with('"') && arg.len() >= 2 {
+            return match (parse(unquote(arg)), self.find_melody(arg)) {
+                (Ok(score), _) => Ok(score),
+                (Err(_), Ok(melody)) => melody_score(melody),
+                (Err(e), Err(_)) => Err(e),
+            };
+        }
+        if std::path::Path::new(arg).is_file() {
+            let text = std::fs::read_to_string(arg)
+                .map_err(|e| format!("Could not read '{}': {}", arg, e))?;
+            return parse(&text).map_err(|e| format!("{}: {}", arg, e));
+        }
+        // Notation wins over a misspelt melody name, so `C4` is a note
+        match resolve_exact("Melody", arg, self.melodies.iter().map(|m| m.name)) {
+            Ok(index) => melody_score(&self.melodies[index]),
+            Err(e @ ResolveError::Ambiguous { .. }) => Err(e.to_string()),
+            Err(_) => parse(arg).or_else(|_| match self.find_melody(arg) {
+                Ok(melody) => melody_score(melody),
+                Err(e) => Err(lookup_message(e, "melodies")),
+            }),
+        }
+    }
+
+    /// Preset and score for a play or render, with the tempo override applied
+    fn resolve(&self, preset_name: &str, melody_name: &str, tempo: Option<f64>) -> Result<(String, FMParams, Score), String> {
+        let (name, preset) = self.find_preset_named(preset_name).map_err(|e| lookup_message(e, "presets"))?;
+        let mut score = self.find_score(melody_name)?;
+        if let Some(bpm) = tempo.or(self.config.tempo) {
+            score.set_tempo(bpm);
+        }
+        Ok((name, self.with_session_effects(preset), score))
+    }
+
+    /// Play a melody; the inner error reports an unknown preset or melody
+    fn play(&mut self, preset_name: &str, melody_name: &str, tempo: Option<f64>) -> anyhow::Result<Result<(), String>> {
+        let (preset_name, preset, score) = match self.resolve(preset_name, melody_name, tempo) {
+            Ok(resolved) => resolved,
+            Err(e) => return Ok(Err(e)),
+        };
+        println!("Playing '{}' melody with '{}' preset at {:.0} bpm...",
+            melody_name, preset_name, score.timing.tempo.initial());
+        self.last_meter = Some(play_melody(preset, &score, &self.master, self.output())?);
+        println!("Done!");
+        Ok(Ok(()))
+    }
+
+    /// Render a melody to a WAV file; the inner error reports an unknown preset or melody
+    fn render(&mut self, preset_name: &str, melody_name: &str, path: &str, tempo: Option<f64>) -> anyhow::Result<Result<(), String>> {
+        let (preset_name, preset, score) = match self.resolve(preset_name, melody_name, tempo) {
+            Ok(resolved) => resolved,
+            Err(e) => return Ok(Err(e)),
+        };
+        println!("Rendering '{}' melody with '{}' preset at {:.0} bpm to {}...",
+            melody_name, preset_name, score.timing.tempo.initial(), path);
+        let meter = render_melody(preset, &score, &self.master, path, self.sample_rate)?;
+        self.last_meter = Some(meter);
+        println!("Done! Peak {:.1} dBFS, RMS {:.1} dBFS", meter.peak_db(), meter.rms_db());
+        Ok(Ok(()))
+    }
+
+    /// Play a scale through every preset
+    fn demo(&mut self) -> anyhow::Result<()> {
+        println!("Playing demo with all presets...");
+        let eighth = PPQ / 2;
+        let scale = Score::from_melody(&Melody {
+            name: "Scale",
+            timing: Timing::new(100.0, TimeSignature::default()),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", eighth), ("D4", eighth), ("E4", eighth), ("F4", eighth),
+                ("G4", eighth), ("A4", eighth), ("B4", eighth), ("C5", PPQ),
+            ],
+        }, &self.tuning)?;
+        for preset in &self.presets {
+            println!("  Playing: {}", preset.name);
+            let preset = self.with_session_effects(preset.params.clone());
+            self.last_meter = Some(play_melody(preset, &scale, &self.master, self.output())?);
+        }
+        println!("Demo complete!");
+        Ok(())
+    }
+
+    /// Output devices and which is the default, or as JSON
+    fn list_devices(&self, json: bool) {
+        let host = cpal::default_host();
+        let default = host.default_output_device().and_then(|d| d.name().ok());
+        let devices: Result<Vec<(String, bool)>, String> = host.output_devices()
+            .map(|devices| devices
+                .map(|device| device.name().unwrap_or_else(|_| "(unnamed)".to_string()))
+                .map(|name| {
+                    let is_default = Some(&name) == default.as_ref();
+                    (name, is_default)
+                })
+                .collect())
+            .map_err(|e| e.to_string());
+
+        if json {
+            match devices {
+                Ok(devices) => println!("{}", devices.into_iter()
+                    .map(|(name, is_default)| Json::object([("name", name.into()), ("default", is_default.into())]))
+                    .collect::<Json>()),
+                Err(e) => println!("{}", Json::object([("error", e.into())])),
+            }
+            return;
+        }
+        println!("\nOutput Devices:");
+        match devices {
+            Ok(devices) => {
+                for (i, (name, is_default)) in devices.iter().enumerate() {
+                    let marker = if *is_default { " (default)" } else { "" };
+                    println!("  {}. {}{}", i + 1, name, marker);
                 }
             }
-            EnvelopeState::Decay => {
-                self.level = 1.0 - ((1.0 - self.sustain) * (self.time / self.decay));
-                if self.time >= self.decay {
-                    self.state = EnvelopeState::Sustain;
-                    self.time = 0.0;
+            Err(e) => println!("  Could not list devices: {}", e),
+        }
+    }
+
+    /// Version, library contents and audio setup, as text or JSON
+    fn print_info(&self, json: bool) {
+        // Name, sample rate and channels of the output device
+        let output = output_device(self.device.as_deref()).map(|device| {
+            let name = device.name().unwrap_or_else(|_| "(unnamed)".to_string());
+            let config = device.default_output_config().ok();
+            (name, config.map(|config| (config.sample_rate().0, config.channels())))
+        });
+
+        if json {
+            let output = match output {
+                Ok((name, config)) => Json::object([
+                    ("name", name.into()),
+                    ("sample_rate", config.map(|(rate, _)| rate).into()),
+                    ("channels", config.map(|(_, channels)| channels as u32).into()),
+                ]),
+                Err(e) => Json::object([("error", e.to_string().into())]),
+            };
+            println!("{}", Json::object([
+                ("version", env!("CARGO_PKG_VERSION").into()),
+                ("presets", self.presets.len().into()),
+                ("melodies", self.melodies.len().into()),
+                ("tuning", self.tuning.describe().into()),
+                ("a4", self.tuning.a4.into()),
+                ("render_sample_rate", self.sample_rate.into()),
+                ("output", output),
+            ]));
+            return;
+        }
+        println!("fm_synth {}", env!("CARGO_PKG_VERSION"));
+        println!("  Presets:     {}", self.presets.len());
+        println!("  Melodies:    {}", self.melodies.len());
+        println!("  Tuning:      {}", self.tuning.describe());
+        println!("  Render rate: {} Hz", self.sample_rate);
+        match output {
+            Ok((name, Some((rate, channels)))) => println!("  Output:      {} ({} Hz, {} channels)", name, rate, channels),
+            Ok((name, None)) => println!("  Output:      {}", name),
+            Err(e) => println!("  Output:      {}", e),
+        }
+    }
+
+    /// Where live playback goes, including any recording
+    fn output(&self) -> Output<'_> {
+        Output { device: self.device.as_deref(), recorder: self.recorder.as_ref(), post_roll: self.config.post_roll() }
+    }
+
+    /// Record everything played from now on to `path`.mid and `path`.wav
+    fn start_recording(&mut self, path: &str) -> Result<(), String> {
+        if let Some(recorder) = &self.recorder {
+            return Err(format!("Already recording to {}. Use 'record stop' first.", recorder.path.display()));
+        }
+        let recorder = Recorder::new(path);
+        println!("Recording to {} and {}", recorder.file("mid").display(), recorder.file("wav").display());
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Finish the recording and write its files
+    fn stop_recording(&mut self) -> anyhow::Result<Result<(), String>> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(Err("Not recording".to_string()));
+        };
+        let (duration, notes) = {
+            let recording = recorder.recording.lock().unwrap();
+            (recording.duration(), recording.notes().len())
+        };
+        if recorder.save(&self.tuning)? {
+            println!("Recorded {:.1}s and {} notes to {} and {}", duration, notes,
+                recorder.file("mid").display(), recorder.file("wav").display());
+        } else {
+            println!("Nothing was played, so no files were written");
+        }
+        Ok(Ok(()))
+    }
+
+    fn print_recording(&self) {
+        match &self.recorder {
+            Some(recorder) => {
+                let recording = recorder.recording.lock().unwrap();
+                println!("Recording to {} (.mid, .wav): {:.1}s, {} notes so far{}",
+                    recorder.path.display(), recording.duration(), recording.notes().len(),
+                    if recording.is_full() { " (full)" } else { "" });
+            }
+            None => println!("Not recording. Use 'record start <file>' to begin."),
+        }
+    }
+
+    /// Summary of one preset's settings
+    fn print_preset(&self, name: &str, json: bool) -> Result<(), String> {
+        let index = self.find_preset_index(name).map_err(|e| lookup_message(e, "presets"))?;
+        if json {
+            println!("{}", preset_json(index + 1, &self.presets[index]));
+            return Ok(());
+        }
+        let Preset { name, category, tags, author, description, params: preset } = &self.presets[index];
+        let ratio = |op: &fm_synth::operator::Operator| match op.freq {
+            OperatorFreq::Ratio { coarse, fine } => format!("ratio {:.3}", coarse * (1.0 + fine)),
+            OperatorFreq::Fixed(hz) => format!("fixed {:.1} Hz", hz),
+        };
+        let env = |e: &fm_synth::synth::EnvelopeParams| format!("A {:.3}s D {:.3}s S {:.2} R {:.3}s",
+            e.attack, e.decay, e.sustain, e.release);
+        println!("{}", name);
+        println!("  Category:     {}, tags {}", category.name(), tags.join(", "));
+        if !description.is_empty() {
+            println!("  Description:  {}", description);
+        }
+        if !author.is_empty() {
+            println!("  Author:       {}", author);
+        }
+        println!("  Carrier:      {}", ratio(&preset.carrier));
+        println!("  Modulator:    {}, index {:.2}", ratio(&preset.modulator), preset.modulation_index);
+        println!("  Amplitude:    {:.2}, velocity sensitivity {:.2}", preset.amplitude, preset.velocity_sensitivity);
+        println!("  Amp env:      {}", env(&preset.amp_envelope));
+        println!("  Mod env:      {}", env(&preset.mod_envelope));
+        println!("  Voice mode:   {:?}, unison {}", preset.voice_mode, preset.unison.voices);
+        println!("  Filter:       {:?} at {:.0} Hz, resonance {:.2}",
+            preset.filter.mode, preset.filter.cutoff, preset.filter.resonance);
+        println!("  Effects:      chorus {:.2}, delay {:.2}, reverb {:.2}",
+            preset.effects.chorus.mix, preset.effects.delay.mix, preset.effects.reverb.mix);
+        Ok(())
+    }
+
+    fn print_sequencer(&self) {
+        let seq = &self.sequencer;
+        if seq.tracks.is_empty() {
+            println!("The sequencer is empty. Try 'seq demo' or 'seq load <file>'.");
+            return;
+        }
+        let step = match PPQ as u64 % seq.step_ticks {
+            0 => format!("1/{} beat", PPQ as u64 / seq.step_ticks),
+            _ => format!("{} ticks", seq.step_ticks),
+        };
+        println!("Sequencer: {:.0} bpm, swing {:.2}, steps of {}, loop {}",
+            seq.tempo(), seq.timing.swing.amount, step, if seq.looping { "on" } else { "off" });
+        println!("Tracks:");
+        for (i, track) in seq.tracks.iter().enumerate() {
+            let state = match (track.mute, track.solo) {
+                (true, _) => " [muted]",
+                (_, true) => " [solo]",
+                _ => "",
+            };
+            println!("  {}. {} ({}){}", i + 1, track.name, track.preset_name, state);
+        }
+        println!("Patterns:");
+        for (i, pattern) in seq.patterns.iter().enumerate() {
+            println!("  {}. {} ({} steps)", i + 1, pattern.name, pattern.steps);
+        }
+        let song: Vec<String> = seq.song.iter()
+            .map(|e| match e.repeats {
+                1 => seq.patterns[e.pattern].name.clone(),
+                n => format!("{}*{}", seq.patterns[e.pattern].name, n),
+            })
+            .collect();
+        println!("Song: {}", song.join(" "));
+    }
+
+    /// Step grid of one pattern
+    fn print_pattern(&self, index: usize) {
+        let seq = &self.sequencer;
+        let pattern = &seq.patterns[index];
+        println!("Pattern '{}':", pattern.name);
+        for (track_index, track) in seq.tracks.iter().enumerate() {
+            let cells: Vec<String> = (0..pattern.steps)
+                .map(|i| match pattern.step(track_index, i) {
+                    Some(step) => format!("{:>4}", step.note),
+                    None => "   .".to_string(),
+                })
+                .collect();
+            println!("  {:>8} {}", track.name, cells.join(""));
+        }
+    }
+
+    /// Engine set up to play the sequencer with the session's tuning, effects and master
+    fn sequencer_engine(&self, sample_rate: f32) -> Engine {
+        let mut sequencer = self.sequencer.clone();
+        sequencer.tuning = self.tuning.clone();
+        let mut engine = Engine::with_sequencer(sample_rate, sequencer);
+        if let Some(effects) = &self.session_effects {
+            engine.set_effects(effects.clone());
+        }
+        engine.set_master(self.master.clone());
+        engine
+    }
+
+    /// Song mode, or one pattern when named
+    fn sequencer_mode(&self, pattern: Option<&str>) -> Result<PlayMode, String> {
+        match pattern {
+            Some(name) => self.sequencer.find_pattern(name)
+                .map(PlayMode::Pattern)
+                .ok_or_else(|| format!("Pattern '{}' not found", name)),
+            None if self.sequencer.song.is_empty() => Err("Nothing to play. Try 'seq demo'.".to_string()),
+            None => Ok(PlayMode::Song),
+        }
+    }
+
+    fn find_track(&self, name: &str) -> Result<usize, String> {
+        self.sequencer.find_track(name).ok_or_else(|| format!("Track '{}' not found", name))
+    }
+
+    /// Handle `seq ...`; errors are reported as messages
+    fn run_sequencer(&mut self, args: &[&str]) -> anyhow::Result<Result<(), String>> {
+        let load = |cli: &Self, text: &str| Sequencer::parse(text, |name| cli.find_preset_named(name).ok());
+        match args {
+            [] => self.print_sequencer(),
+            ["demo"] => {
+                self.sequencer = load(self, DEMO_SONG).expect("demo song is valid");
+                self.print_sequencer();
+            }
+            ["load", path] => {
+                let text = match std::fs::read_to_string(path) {
+                    Ok(text) => text,
+                    Err(e) => return Ok(Err(format!("Could not read '{}': {}", path, e))),
+                };
+                match load(self, &text) {
+                    Ok(sequencer) => self.sequencer = sequencer,
+                    Err(e) => return Ok(Err(format!("{}: {}", path, e))),
+                }
+                self.print_sequencer();
+            }
+            ["show"] => {
+                for i in 0..self.sequencer.patterns.len() {
+                    self.print_pattern(i);
                 }
             }
-            EnvelopeState::Sustain => {
-                self.level = self.sustain;
+            ["show", name] => match self.sequencer.find_pattern(name) {
+                Some(i) => self.print_pattern(i),
+                None => return Ok(Err(format!("Pattern '{}' not found", name))),
+            },
+            ["play", rest @ ..] if rest.len() <= 1 => {
+                let mode = match self.sequencer_mode(rest.first().copied()) {
+                    Ok(mode) => mode,
+                    Err(e) => return Ok(Err(e)),
+                };
+                println!("Playing {}...", if rest.is_empty() { "song" } else { "pattern" });
+                // Enter stops a loop at the prompt; scripts and piped input
+                // have no Enter to wait for, so a loop plays twice as it renders
+                let until_enter = self.sequencer.looping && self.interactive;
+                let passes = (self.sequencer.looping && !until_enter).then_some(2);
+                if passes.is_some() {
+                    println!("Looping twice, as input is not a terminal");
+                }
+                self.last_meter = Some(play_engine(self.output(), |sample_rate| {
+                    let mut engine = self.sequencer_engine(sample_rate);
+                    engine.start_sequencer(mode, passes);
+                    engine
+                }, until_enter)?);
+                println!("Done!");
             }
-            EnvelopeState::Release => {
-                self.level = self.sustain * (1.0 - (self.time / self.release));
-                if self.time >= self.release {
-                    self.state = EnvelopeState::Idle;
-                    self.level = 0.0;
+            ["render", path, rest @ ..] if rest.len() <= 1 => {
+                let mode = match self.sequencer_mode(rest.first().copied()) {
+                    Ok(mode) => mode,
+                    Err(e) => return Ok(Err(e)),
+                };
+                // A looping sequencer renders two passes so the loop point can be heard
+                let mut engine = self.sequencer_engine(self.sample_rate as f32);
+                engine.start_sequencer(mode, Some(2));
+                let frames = engine.render_pending(2.0);
+                write_wav(path, &frames, self.sample_rate)?;
+                let meter = engine.meter();
+                self.last_meter = Some(meter);
+                println!("Rendered {:.1}s to {}. Peak {:.1} dBFS, RMS {:.1} dBFS",
+                    frames.len() as f32 / self.sample_rate as f32, path, meter.peak_db(), meter.rms_db());
+            }
+            ["export", path, rest @ ..] if rest.len() <= 2 => {
+                if self.sequencer.song.is_empty() {
+                    return Ok(Err("Nothing to export. Try 'seq demo'.".to_string()));
+                }
+                let format = match split_midi_format(args) {
+                    Ok((remaining, format)) if remaining.len() == 2 => format,
+                    Ok(_) => return Ok(Err(SEQ_USAGE.to_string())),
+                    Err(e) => return Ok(Err(e)),
+                };
+                let mut sequencer = self.sequencer.clone();
+                sequencer.tuning = self.tuning.clone();
+                let midi = MidiFile::from_sequencer("Sequencer song", &sequencer);
+                midi.write(path, format)?;
+                println!("Exported {} tracks to {}", midi.tracks.len(), path);
+            }
+            ["mute", track] | ["solo", track] => {
+                let index = match self.find_track(track) {
+                    Ok(index) => index,
+                    Err(e) => return Ok(Err(e)),
+                };
+                let track = &mut self.sequencer.tracks[index];
+                if args[0] == "mute" {
+                    track.mute = !track.mute;
+                } else {
+                    track.solo = !track.solo;
+                }
+                self.print_sequencer();
+            }
+            ["tempo", bpm] => match bpm.parse::<f64>().ok().filter(|b| b.is_finite() && *b > 0.0) {
+                Some(bpm) => {
+                    self.sequencer.set_tempo(bpm);
+                    println!("Sequencer tempo: {:.0} bpm", bpm);
+                }
+                None => return Ok(Err(format!("Invalid tempo '{}'", bpm))),
+            },
+            ["loop", state @ ("on" | "off")] => {
+                self.sequencer.looping = *state == "on";
+                println!("Loop {}", state);
+            }
+            ["track", name, preset @ ..] if !preset.is_empty() => {
+                let preset = preset.join(" ");
+                let (preset_name, params) = match self.find_preset_named(&preset) {
+                    Ok(found) => found,
+                    Err(e) => return Ok(Err(lookup_message(e, "presets"))),
+                };
+                match self.sequencer.find_track(name) {
+                    Some(index) => {
+                        let track = &mut self.sequencer.tracks[index];
+                        track.preset_name = preset_name;
+                        track.preset = params;
+                    }
+                    None => {
+                        self.sequencer.add_track(name, &preset_name, params);
+                    }
+                }
+                self.print_sequencer();
+            }
+            ["pattern", name, rest @ ..] if rest.len() <= 1 => {
+                if self.sequencer.find_pattern(name).is_some() {
+                    return Ok(Err(format!("Pattern '{}' already exists", name)));
+                }
+                let steps = match rest.first().map(|n| n.parse::<usize>()) {
+                    None => DEFAULT_STEPS,
+                    Some(Ok(n)) if (1..=256).contains(&n) => n,
+                    Some(_) => return Ok(Err("Step count must be 1 to 256".to_string())),
+                };
+                self.sequencer.patterns.push(Pattern::new(name, steps));
+                let pattern = self.sequencer.patterns.len() - 1;
+                self.sequencer.song.push(SongEntry { pattern, repeats: 1 });
+                self.print_sequencer();
+            }
+            ["step", pattern, track, number, step] => {
+                let Some(pattern) = self.sequencer.find_pattern(pattern) else {
+                    return Ok(Err(format!("Pattern '{}' not found", pattern)));
+                };
+                let track = match self.find_track(track) {
+                    Ok(track) => track,
+                    Err(e) => return Ok(Err(e)),
+                };
+                let steps = self.sequencer.patterns[pattern].steps;
+                let number = match number.parse::<usize>() {
+                    Ok(n) if (1..=steps).contains(&n) => n - 1,
+                    _ => return Ok(Err(format!("Step number must be 1 to {}", steps))),
+                };
+                match Step::parse(step) {
+                    Ok(step) => self.sequencer.patterns[pattern].set_step(track, number, step),
+                    Err(e) => return Ok(Err(e)),
+                }
+                self.print_pattern(pattern);
+            }
+            ["song", entries @ ..] if !entries.is_empty() => {
+                let mut song = Vec::new();
+                for entry in entries {
+                    let (name, repeats) = entry.rsplit_once('*')
+                        .and_then(|(name, n)| n.parse::<u32>().ok().map(|n| (name, n.max(1))))
+                        .unwrap_or((entry, 1));
+                    match self.sequencer.find_pattern(name) {
+                        Some(pattern) => song.push(SongEntry { pattern, repeats }),
+                        None => return Ok(Err(format!("Pattern '{}' not found", name))),
+                    }
                 }
+                self.sequencer.song = song;
+                self.print_sequencer();
             }
+            _ => return Ok(Err(SEQ_USAGE.to_string())),
         }
-        
-        self.time += dt;
-        self.level
+        Ok(Ok(()))
     }
 }
 
-/// FM Synthesizer with envelope
-struct FMSynth {
-    oscillator: FMOscillator,
-    envelope: Envelope,
+/// A lookup error, pointing at the list command when there is nothing to suggest
+fn lookup_message(error: ResolveError, list: &str) -> String {
+    match &error {
+        ResolveError::NotFound { suggestions, .. } if suggestions.is_empty() => {
+            format!("{}. Use 'list {}' to see available options.", error, list)
+        }
+        _ => error.to_string(),
+    }
 }
 
-impl FMSynth {
-    fn new(sample_rate: f32, params: FMParams) -> Self {
-        Self {
-            oscillator: FMOscillator::new(sample_rate, params),
-            envelope: Envelope::new(sample_rate),
-        }
-    }
-
-    fn next_sample(&mut self) -> f32 {
-        let osc_out = self.oscillator.next_sample();
-        let env_out = self.envelope.process();
-        osc_out * env_out
-    }
-
-    fn note_on(&mut self) {
-        self.envelope.trigger();
-    }
-
-    fn note_off(&mut self) {
-        self.envelope.release();
-    }
-
-    fn set_params(&mut self, params: FMParams) {
-        self.oscillator.set_params(params);
-    }
-}
-
-/// Preset definitions
-fn get_presets() -> Vec<(&'static str, FMParams)> {
-    vec![
-        ("Bell", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 7.0,
-            amplitude: 0.3,
-        }),
-        ("Bass", FMParams {
-            carrier_freq: 110.0,
-            modulator_freq: 110.0,
-            modulation_index: 1.5,
-            amplitude: 0.5,
-        }),
-        ("Electric Piano", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 880.0,
-            modulation_index: 3.0,
-            amplitude: 0.4,
-        }),
-        ("Brass", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 2.5,
-            amplitude: 0.4,
-        }),
-        ("Organ", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 880.0,
-            modulation_index: 1.0,
-            amplitude: 0.4,
-        }),
-        ("Synth Lead", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 1320.0,
-            modulation_index: 4.0,
-            amplitude: 0.35,
-        }),
-        ("Marimba", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 3.5,
-            amplitude: 0.4,
-        }),
-        ("Strings", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 220.0,
-            modulation_index: 0.8,
-            amplitude: 0.3,
-        }),
-        ("Flute", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 440.0,
-            modulation_index: 0.5,
-            amplitude: 0.25,
-        }),
-        ("Metallic", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 567.0,
-            modulation_index: 9.0,
-            amplitude: 0.3,
-        }),
-        ("Glockenspiel", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 1760.0,
-            modulation_index: 2.5,
-            amplitude: 0.3,
-        }),
-        ("Wood Block", FMParams {
-            carrier_freq: 440.0,
-            modulator_freq: 300.0,
-            modulation_index: 12.0,
-            amplitude: 0.4,
-        }),
-    ]
-}
-
-/// Note frequencies
-fn note_freq(note: &str) -> f32 {
-    match note {
-        "C3" => 130.81, "C#3" => 138.59, "D3" => 146.83, "D#3" => 155.56, "E3" => 164.81,
-        "F3" => 174.61, "F#3" => 185.00, "G3" => 196.00, "G#3" => 207.65, "A3" => 220.00,
-        "A#3" => 233.08, "B3" => 246.94,
-        "C4" => 261.63, "C#4" => 277.18, "D4" => 293.66, "D#4" => 311.13, "E4" => 329.63,
-        "F4" => 349.23, "F#4" => 369.99, "G4" => 392.00, "G#4" => 415.30, "A4" => 440.00,
-        "A#4" => 466.16, "B4" => 493.88,
-        "C5" => 523.25, "C#5" => 554.37, "D5" => 587.33, "D#5" => 622.25, "E5" => 659.25,
-        "F5" => 698.46, "F#5" => 739.99, "G5" => 783.99, "G#5" => 830.61, "A5" => 880.00,
-        _ => 0.0, // Rest
-    }
-}
-
-/// Melody definitions
-fn get_melodies() -> Vec<(&'static str, Vec<(&'static str, u64)>)> {
-    vec![
-        ("Twinkle Twinkle", vec![
-            ("C4", 500), ("C4", 500), ("G4", 500), ("G4", 500),
-            ("A4", 500), ("A4", 500), ("G4", 1000),
-            ("F4", 500), ("F4", 500), ("E4", 500), ("E4", 500),
-            ("D4", 500), ("D4", 500), ("C4", 1000),
-        ]),
-        ("Happy Birthday", vec![
-            ("C4", 250), ("C4", 250), ("D4", 500), ("C4", 500),
-            ("F4", 500), ("E4", 1000),
-            ("C4", 250), ("C4", 250), ("D4", 500), ("C4", 500),
-            ("G4", 500), ("F4", 1000),
-        ]),
-        ("Ode to Joy", vec![
-            ("E4", 500), ("E4", 500), ("F4", 500), ("G4", 500),
-            ("G4", 500), ("F4", 500), ("E4", 500), ("D4", 500),
-            ("C4", 500), ("C4", 500), ("D4", 500), ("E4", 500),
-            ("E4", 750), ("D4", 250), ("D4", 1000),
-        ]),
-        ("Mary Had a Little Lamb", vec![
-            ("E4", 500), ("D4", 500), ("C4", 500), ("D4", 500),
-            ("E4", 500), ("E4", 500), ("E4", 1000),
-            ("D4", 500), ("D4", 500), ("D4", 1000),
-            ("E4", 500), ("G4", 500), ("G4", 1000),
-        ]),
-        ("Chromatic Scale", vec![
-            ("C4", 200), ("C#4", 200), ("D4", 200), ("D#4", 200),
-            ("E4", 200), ("F4", 200), ("F#4", 200), ("G4", 200),
-            ("G#4", 200), ("A4", 200), ("A#4", 200), ("B4", 200),
-            ("C5", 400),
-        ]),
-        ("Major Arpeggio", vec![
-            ("C4", 300), ("E4", 300), ("G4", 300), ("C5", 300),
-            ("G4", 300), ("E4", 300), ("C4", 600),
-        ]),
-        ("Minor Pentatonic", vec![
-            ("A3", 400), ("C4", 400), ("D4", 400), ("E4", 400),
-            ("G4", 400), ("A4", 400), ("G4", 400), ("E4", 400),
-            ("D4", 400), ("C4", 400), ("A3", 800),
-        ]),
-        ("Jazz Lick", vec![
-            ("C4", 200), ("E4", 200), ("G4", 200), ("A#4", 200),
-            ("A4", 400), ("F4", 200), ("D4", 400),
-            ("G4", 200), ("E4", 200), ("C4", 600),
-        ]),
-        ("Bach Invention", vec![
-            ("C4", 200), ("D4", 200), ("E4", 200), ("F4", 200),
-            ("D4", 200), ("E4", 200), ("C4", 400),
-            ("G4", 200), ("F4", 200), ("E4", 200), ("D4", 200),
-            ("B3", 200), ("C4", 600),
-        ]),
-        ("Synth Demo", vec![
-            ("C4", 150), ("E4", 150), ("G4", 150), ("C5", 150),
-            ("E5", 150), ("G5", 150), ("E5", 150), ("C5", 150),
-            ("G4", 150), ("E4", 150), ("C4", 300),
-            ("REST", 300),
-            ("F4", 150), ("A4", 150), ("C5", 150), ("F5", 150),
-            ("C5", 150), ("A4", 150), ("F4", 300),
-        ]),
-    ]
+const ARP_USAGE: &str = "\
+Usage: arp [off|<mode> [octaves <1-4>] [rate <beats>] [gate <percent>]]
+Modes: up, down, updown, random, played";
+
+const SEQ_USAGE: &str = "\
+Usage:
+  seq                              Show tracks, patterns and song
+  seq demo | load <file>           Load the demo song or a song file
+  seq show [pattern]               Show pattern steps
+  seq play [pattern]               Play the song, or loop one pattern
+  seq render <file.wav> [pattern]  Render the song or a pattern
+  seq export <file.mid> [type 0|1] Export the song as a MIDI file
+  seq mute|solo <track>            Toggle mute or solo
+  seq tempo <bpm> | loop on|off    Tempo and loop mode
+  seq track <name> <preset>        Add a track or change its preset
+  seq pattern <name> [steps]       Add an empty pattern to the song
+  seq step <pattern> <track> <n> <step>  Set a step, e.g. C4*0.8@50?75 or .
+  seq song <pattern[*n]> ...       Chain patterns into the song";
+
+/// Split a trailing `tempo <bpm>` option off a command
+fn split_tempo<'a>(parts: &'a [&'a str]) -> Result<(&'a [&'a str], Option<f64>), String> {
+    match parts {
+        [rest @ .., "tempo", bpm] => {
+            let bpm = bpm.parse::<f64>().ok()
+                .filter(|bpm| bpm.is_finite() && *bpm > 0.0)
+                .ok_or_else(|| format!("Invalid tempo '{}'", bpm))?;
+            Ok((rest, Some(bpm)))
+        }
+        _ => Ok((parts, None)),
+    }
 }
 
-/// CLI interface
-struct CLI {
-    presets: Vec<(&'static str, FMParams)>,
-    melodies: Vec<(&'static str, Vec<(&'static str, u64)>)>,
+/// Split a trailing `--json` option off a listing command
+fn split_json<'a>(parts: &'a [&'a str]) -> (&'a [&'a str], bool) {
+    match parts {
+        [rest @ .., "--json"] => (rest, true),
+        _ => (parts, false),
+    }
 }
 
-impl CLI {
-    fn new() -> Self {
-        Self {
-            presets: get_presets(),
-            melodies: get_melodies(),
+/// Which presets `list presets` shows
+#[derive(Clone, Copy, Default)]
+struct PresetFilter {
+    category: Option<Category>,
+    favorites: bool,
+}
+
+impl PresetFilter {
+    fn is_set(&self) -> bool {
+        self.category.is_some() || self.favorites
+    }
+}
+
+/// `bass` as `Bass`, for headings
+fn title_case(name: &str) -> String {
+    let mut chars = name.chars();
+    chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
+
+/// Split trailing `--category <name>` and `--favorites` options off a preset listing
+fn split_preset_filter<'a>(mut parts: &'a [&'a str]) -> Result<(&'a [&'a str], PresetFilter), String> {
+    let mut filter = PresetFilter::default();
+    loop {
+        match parts {
+            [rest @ .., "--favorites"] => {
+                filter.favorites = true;
+                parts = rest;
+            }
+            [rest @ .., "--category", name] => {
+                filter.category = Some(Category::lookup(name)?);
+                parts = rest;
+            }
+            _ => return Ok((parts, filter)),
         }
     }
+}
 
-    fn print_menu(&self) {
-        println!("\n=== FM Synthesizer CLI ===");
-        println!("Commands:");
-        println!("  list presets  - Show all available presets");
-        println!("  list melodies - Show all available melodies");
-        println!("  play <preset> <melody> - Play a melody with a preset");
-        println!("  demo - Play all presets with a scale");
-        println!("  help - Show this menu");
-        println!("  quit - Exit the program");
-        println!();
+/// Split a trailing `type 0|1` option for MIDI export
+fn split_midi_format<'a>(parts: &'a [&'a str]) -> Result<(&'a [&'a str], MidiFormat), String> {
+    match parts {
+        [rest @ .., "type", format] => MidiFormat::parse(format)
+            .map(|format| (rest, format))
+            .ok_or_else(|| format!("Invalid MIDI file type '{}', use 0 or 1", format)),
+        _ => Ok((parts, MidiFormat::default())),
     }
+}
+
+/// Output device whose name contains `name` (ignoring case), or the default
+#[cfg(not(target_arch = "wasm32"))]
+fn output_device(name: Option<&str>) -> anyhow::Result<cpal::Device> {
+    let host = cpal::default_host();
+    let Some(name) = name else {
+        return host.default_output_device().ok_or_else(|| anyhow::anyhow!("No output device available"));
+    };
+    let wanted = name.to_lowercase();
+    host.output_devices()?
+        .find(|device| device.name().is_ok_and(|n| n.to_lowercase().contains(&wanted)))
+        .ok_or_else(|| anyhow::anyhow!("Output device '{}' not found. Use 'list devices' to see available options.", name))
+}
 
-    fn list_presets(&self) {
-        println!("\nAvailable Presets:");
-        for (i, (name, _)) in self.presets.iter().enumerate() {
-            println!("  {}. {}", i + 1, name);
+/// A recording in progress: notes and audio from everything played live
+#[cfg(not(target_arch = "wasm32"))]
+struct Recorder {
+    recording: Arc<Mutex<Recording>>,
+    origin: OnceLock<Instant>,   // When audio first arrived; later gaps are kept as silence
+    path: PathBuf,               // Without extension; .mid and .wav are added
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Recorder {
+    /// Record to `path`, dropping a .mid or .wav extension if given
+    fn new(path: &str) -> Self {
+        let mut path = PathBuf::from(path);
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mid") || ext.eq_ignore_ascii_case("wav")) {
+            path.set_extension("");
         }
+        Self { recording: Arc::new(Mutex::new(Recording::new())), origin: OnceLock::new(), path }
     }
 
-    fn list_melodies(&self) {
-        println!("\nAvailable Melodies:");
-        for (i, (name, _)) in self.melodies.iter().enumerate() {
-            println!("  {}. {}", i + 1, name);
-        }
+    fn file(&self, extension: &str) -> PathBuf {
+        self.path.with_extension(extension)
     }
 
-    fn find_preset(&self, name: &str) -> Option<FMParams> {
-        // Try by number first
-        if let Ok(num) = name.parse::<usize>() {
-            if num > 0 && num <= self.presets.len() {
-                return Some(self.presets[num - 1].1.clone());
-            }
-        }
-        
-        // Try by name (case insensitive)
-        self.presets.iter()
-            .find(|(n, _)| n.to_lowercase() == name.to_lowercase())
-            .map(|(_, p)| p.clone())
+    /// Write the notes, with keys as `tuning` plays them, and the audio;
+    /// false when nothing was played
+    fn save(&self, tuning: &Tuning) -> anyhow::Result<bool> {
+        let mut recording = self.recording.lock().unwrap();
+        recording.release_all();
+        let Some(sample_rate) = recording.sample_rate().filter(|_| !recording.is_empty()) else {
+            return Ok(false);
+        };
+        let name = self.path.file_name().map_or("Recording".into(), |name| name.to_string_lossy());
+        recording.to_midi(&name, tuning).write(self.file("mid"), MidiFormat::default())?;
+        write_wav(self.file("wav"), recording.frames(), sample_rate as u32)?;
+        Ok(true)
     }
+}
 
-    fn find_melody(&self, name: &str) -> Option<Vec<(&'static str, u64)>> {
-        // Try by number first
-        if let Ok(num) = name.parse::<usize>() {
-            if num > 0 && num <= self.melodies.len() {
-                return Some(self.melodies[num - 1].1.clone());
-            }
+/// Where live audio goes
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy)]
+struct Output<'a> {
+    device: Option<&'a str>,           // None uses the default
+    recorder: Option<&'a Recorder>,    // Also captures the audio when set
+    post_roll: Duration,               // Playback kept going after the last note
+}
+
+/// An engine running on an output device; audio stops when this is dropped
+#[cfg(not(target_arch = "wasm32"))]
+struct LiveOutput {
+    stream: cpal::Stream,
+    engine: Arc<Mutex<Engine>>,
+    recording: Option<Arc<Mutex<Recording>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for LiveOutput {
+    fn drop(&mut self) {
+        // Notes still held when the stream closes end with it
+        if let Some(recording) = &self.recording {
+            let _ = self.stream.pause();
+            let mut recording = recording.lock().unwrap();
+            recording.release_all();
+            warn_if_full(&mut recording);
         }
-        
-        // Try by name (case insensitive)
-        self.melodies.iter()
-            .find(|(n, _)| n.to_lowercase().contains(&name.to_lowercase()))
-            .map(|(_, m)| m.clone())
     }
 }
 
+/// Report, once, that a recording has reached its length limit
 #[cfg(not(target_arch = "wasm32"))]
-fn play_melody(preset: FMParams, melody: Vec<(&'static str, u64)>) -> anyhow::Result<()> {
-    let host = cpal::default_host();
-    let device = host.default_output_device()
-        .expect("No output device available");
+fn warn_if_full(recording: &mut Recording) {
+    if recording.take_full_warning() {
+        eprintln!("Recording reached its {}-minute limit; nothing more is kept. Use 'record stop' to save it.",
+            MAX_RECORDING_SECONDS / 60.0);
+    }
+}
+
+/// Start an engine running on an output device; `build` creates it for the
+/// device's sample rate. When recording, its notes and audio are captured too.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_stream(output: Output, build: impl FnOnce(f32) -> Engine) -> anyhow::Result<LiveOutput> {
+    let device = output_device(output.device)?;
     
     let config = device.default_output_config()?;
     let sample_rate = config.sample_rate().0 as f32;
+    let channels = config.channels() as usize;
     
-    let synth = Arc::new(Mutex::new(FMSynth::new(sample_rate, preset.clone())));
-    let synth_clone = Arc::clone(&synth);
+    let recording = output.recorder.and_then(|recorder| {
+        let mut recording = recorder.recording.lock().unwrap();
+        if !recording.accepts(sample_rate) {
+            eprintln!("Not recording this: the output runs at {} Hz but the recording at {} Hz",
+                sample_rate, recording.sample_rate().unwrap_or_default());
+            return None;
+        }
+        let origin = recorder.origin.get_or_init(Instant::now);
+        recording.pad_to((origin.elapsed().as_secs_f64() * sample_rate as f64) as u64);
+        warn_if_full(&mut recording);
+        Some(Arc::clone(&recorder.recording))
+    });
+
+    let mut engine = build(sample_rate);
+    if recording.is_some() {
+        engine.log_events();
+    }
+    let engine = Arc::new(Mutex::new(engine));
+    let engine_clone = Arc::clone(&engine);
+    let recording_clone = recording.clone();
     
     let stream = match config.sample_format() {
         cpal::SampleFormat::F32 => device.build_output_stream(
             &config.into(),
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut synth = synth_clone.lock().unwrap();
-                for sample in data.iter_mut() {
-                    *sample = synth.next_sample();
+                let mut engine = engine_clone.lock().unwrap();
+                let mut recording = recording_clone.as_ref().map(|recording| recording.lock().unwrap());
+                // Events land in the recording at their offset into this buffer
+                let start = engine.position();
+                let base = recording.as_ref().map_or(0, |recording| recording.len());
+                for frame in data.chunks_mut(channels) {
+                    let (left, right) = engine.next_frame();
+                    if let Some(recording) = recording.as_mut() {
+                        recording.push_frame((left, right));
+                    }
+                    if frame.len() == 1 {
+                        frame[0] = (left + right) * 0.5;
+                    } else {
+                        frame[0] = left;
+                        frame[1] = right;
+                        for sample in frame.iter_mut().skip(2) {
+                            *sample = 0.0;
+                        }
+                    }
+                }
+                if let Some(recording) = recording.as_mut() {
+                    for event in engine.take_events() {
+                        recording.event(base + event.sample.saturating_sub(start), event.track, &event.kind);
+                    }
                 }
             },
             |err| eprintln!("Error in audio stream: {}", err),
@@ -448,112 +1212,777 @@ fn play_melody(preset: FMParams, melody: Vec<(&'static str, u64)>) -> anyhow::Re
     };
     
     stream.play()?;
+    Ok(LiveOutput { stream, engine, recording })
+}
+
+/// Run an engine on an output device. `build` creates it for the
+/// device's sample rate with everything queued; playback ends once the engine
+/// is idle, or when Enter is pressed if `until_enter` is set.
+#[cfg(not(target_arch = "wasm32"))]
+fn play_engine(output: Output, build: impl FnOnce(f32) -> Engine, until_enter: bool) -> anyhow::Result<MeterReading> {
+    let live = open_stream(output, build)?;
+    let engine = &live.engine;
     
-    for (note, duration) in melody {
-        let freq = note_freq(note);
-        if freq > 0.0 {
-            let mut params = preset.clone();
-            let freq_ratio = freq / 440.0;
-            params.carrier_freq *= freq_ratio;
-            params.modulator_freq *= freq_ratio;
-            
-            {
-                let mut synth = synth.lock().unwrap();
-                synth.set_params(params);
-                synth.note_on();
-            }
-            
-            std::thread::sleep(Duration::from_millis(duration * 80 / 100));
-            
-            {
-                let mut synth = synth.lock().unwrap();
-                synth.note_off();
-            }
-            
-            std::thread::sleep(Duration::from_millis(duration * 20 / 100));
-        } else {
-            std::thread::sleep(Duration::from_millis(duration));
-        }
+    // The audio callback fires the note events; just wait for it to finish
+    if until_enter {
+        println!("Looping. Press Enter to stop.");
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        engine.lock().unwrap().stop();
+    }
+    while engine.lock().unwrap().is_playing() {
+        std::thread::sleep(Duration::from_millis(10));
     }
     
-    std::thread::sleep(Duration::from_millis(500));
-    Ok(())
+    std::thread::sleep(output.post_roll);
+    let meter = engine.lock().unwrap().meter();
+    Ok(meter)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn play_melody(preset: FMParams, score: &Score, master: &MasterParams, output: Output) -> anyhow::Result<MeterReading> {
+    play_engine(output, |sample_rate| {
+        let mut engine = Engine::new(sample_rate, preset);
+        engine.set_master(master.clone());
+        engine.set_tempo(score.timing.tempo.initial() as f32);
+        engine.play(&score.to_sequence());
+        engine
+    }, false)
+}
+
+/// Render a melody offline through the same engine used for playback
+fn render_melody(preset: FMParams, score: &Score, master: &MasterParams, path: &str, sample_rate: u32) -> anyhow::Result<MeterReading> {
+    let mut engine = Engine::new(sample_rate as f32, preset);
+    engine.set_master(master.clone());
+    engine.set_tempo(score.timing.tempo.initial() as f32);
+    let frames = engine.render(&score.to_sequence(), 2.0);
+    write_wav(path, &frames, sample_rate)?;
+    Ok(engine.meter())
+}
+
+/// Command-line arguments; with no subcommand the interactive REPL starts
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Parser)]
+#[command(name = "fm_synth", version, about = "FM synthesizer with built-in presets and melodies")]
+struct Args {
+    /// Output device (part of its name, see `list devices`)
+    #[arg(long, global = true)]
+    device: Option<String>,
+
+    /// Sample rate for rendered WAV files [default: 44100]
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(8000..=192000))]
+    sample_rate: Option<u32>,
+
+    /// Config file to read instead of ~/.config/fm_synth/config
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Override a setting for this run, e.g. `--set a4=432` (see `config show`)
+    #[arg(long = "set", global = true, value_name = "SETTING=VALUE")]
+    settings: Vec<String>,
+
+    /// Record everything played to FILE.mid and FILE.wav, saved on exit
+    #[arg(long, global = true, value_name = "FILE")]
+    record: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Subcommand)]
+enum Command {
+    /// Play a melody through the output device
+    Play {
+        /// Preset name or number [default: the configured preset, or the first]
+        #[arg(short, long)]
+        preset: Option<String>,
+        /// Melody name or number, notation file or notation text
+        #[arg(short, long)]
+        melody: String,
+        /// Tempo in beats per minute instead of the melody's own
+        #[arg(short, long)]
+        tempo: Option<f64>,
+    },
+    /// Render a melody to a WAV file
+    Render {
+        /// Preset name or number [default: the configured preset, or the first]
+        #[arg(short, long)]
+        preset: Option<String>,
+        /// Melody name or number, notation file or notation text
+        #[arg(short, long)]
+        melody: String,
+        /// Tempo in beats per minute instead of the melody's own
+        #[arg(short, long)]
+        tempo: Option<f64>,
+        /// WAV file to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// List presets, melodies or output devices
+    List {
+        #[arg(value_enum)]
+        what: ListKind,
+        /// Only presets in this category, such as bass or keys
+        #[arg(long, value_parser = Category::lookup)]
+        category: Option<Category>,
+        /// Only favourite presets
+        #[arg(long)]
+        favorites: bool,
+        /// Print JSON instead of text, with every preset parameter
+        #[arg(long)]
+        json: bool,
+    },
+    /// Find presets by name, category, tag, author or description
+    Search {
+        /// Words that must all appear
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Mark a preset as a favourite, or list the favourites
+    Fav {
+        /// Preset name or number
+        preset: Option<String>,
+    },
+    /// Remove a preset from the favourites
+    Unfav {
+        /// Preset name or number
+        preset: String,
+    },
+    /// Play a scale through every preset
+    Demo,
+    /// Play a preset from the computer keyboard
+    Piano {
+        /// Preset name or number
+        #[arg(short, long)]
+        preset: Option<String>,
+    },
+    /// Full-screen preset editor with scope, voices and a playable keyboard
+    Tui {
+        /// Preset name or number to start on
+        #[arg(short, long)]
+        preset: Option<String>,
+    },
+    /// Show version and audio setup, or one preset's settings
+    Info {
+        /// Preset name or number
+        #[arg(short, long)]
+        preset: Option<String>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the settings from the config file, environment and flags
+    Config {
+        #[arg(value_enum, default_value = "show")]
+        action: ConfigAction,
+    },
+    /// Run a command script, one REPL command per line (`-` reads stdin)
+    Batch {
+        /// Script file, or `-` for stdin
+        script: String,
+        /// Carry on after a failing command instead of stopping
+        #[arg(short, long)]
+        keep_going: bool,
+    },
+    /// Start the interactive prompt (the default when stdin is a terminal)
+    Repl,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, ValueEnum)]
+enum ListKind {
+    Presets,
+    Melodies,
+    Devices,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, ValueEnum)]
+enum ConfigAction {
+    Show,
+}
+
+/// Positive, finite tempo for `--tempo`
+#[cfg(not(target_arch = "wasm32"))]
+fn check_tempo(tempo: Option<f64>) -> Result<Option<f64>, String> {
+    match tempo {
+        Some(bpm) if !(bpm.is_finite() && bpm > 0.0) => Err(format!("Invalid tempo '{}'", bpm)),
+        tempo => Ok(tempo),
+    }
 }
 
+/// Run one subcommand; lookup errors exit with `EXIT_USAGE`, failures with `EXIT_FAILURE`
 #[cfg(not(target_arch = "wasm32"))]
-fn main() -> anyhow::Result<()> {
+fn run(cli: &mut CLI, command: Command) -> anyhow::Result<Result<(), String>> {
+    match command {
+        Command::Play { preset, melody, tempo } => match check_tempo(tempo) {
+            Ok(tempo) => cli.play(&preset.unwrap_or_else(|| cli.default_preset()), &melody, tempo),
+            Err(e) => Ok(Err(e)),
+        },
+        Command::Render { preset, melody, tempo, output } => match check_tempo(tempo) {
+            Ok(tempo) => cli.render(&preset.unwrap_or_else(|| cli.default_preset()), &melody, &output, tempo),
+            Err(e) => Ok(Err(e)),
+        },
+        Command::List { what, category, favorites, json } => {
+            let filter = PresetFilter { category, favorites };
+            match what {
+                ListKind::Presets => cli.list_presets(json, filter),
+                _ if filter.is_set() => return Ok(Err("--category and --favorites only apply to presets".to_string())),
+                ListKind::Melodies => cli.list_melodies(json),
+                ListKind::Devices => cli.list_devices(json),
+            }
+            Ok(Ok(()))
+        }
+        Command::Search { query, json } => {
+            cli.search_presets(&query.join(" "), json);
+            Ok(Ok(()))
+        }
+        Command::Fav { preset: Some(preset) } => cli.set_favorite(&preset, true),
+        Command::Fav { preset: None } => {
+            cli.list_presets(false, PresetFilter { favorites: true, ..PresetFilter::default() });
+            Ok(Ok(()))
+        }
+        Command::Unfav { preset } => cli.set_favorite(&preset, false),
+        Command::Demo => cli.demo().map(Ok),
+        Command::Piano { preset } => piano::run(cli, preset.as_deref()),
+        Command::Tui { preset } => tui::run(cli, preset.as_deref()),
+        Command::Info { preset: Some(preset), json } => Ok(cli.print_preset(&preset, json)),
+        Command::Config { action: ConfigAction::Show } => {
+            cli.config.show();
+            Ok(Ok(()))
+        }
+        Command::Info { preset: None, json } => {
+            cli.print_info(json);
+            Ok(Ok(()))
+        }
+        Command::Batch { script, keep_going } => {
+            let text = if script == "-" {
+                io::read_to_string(io::stdin())?
+            } else {
+                match std::fs::read_to_string(&script) {
+                    Ok(text) => text,
+                    Err(e) => return Ok(Err(format!("Could not read '{}': {}", script, e))),
+                }
+            };
+            run_batch(cli, &text, keep_going)
+        }
+        Command::Repl => repl(cli).map(Ok),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> ExitCode {
+    let args = Args::parse();
     let mut cli = CLI::new();
-    cli.print_menu();
-    
-    loop {
-        print!("> ");
-        io::stdout().flush()?;
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim();
-        
-        let parts: Vec<&str> = input.split_whitespace().collect();
-        if parts.is_empty() {
-            continue;
-        }
-        
-        match parts[0] {
-            "list" => {
+    let config = Config::load(args.config.as_deref()).and_then(|mut config| {
+        for setting in &args.settings {
+            config.set_flag(setting)?;
+        }
+        if let Some(device) = &args.device {
+            config.set("device", device, Source::Flag)?;
+        }
+        if let Some(rate) = args.sample_rate {
+            config.set("sample_rate", &rate.to_string(), Source::Flag)?;
+        }
+        Ok(config)
+    });
+    match config {
+        Ok(config) => {
+            cli.configure(config);
+            cli.load_library();
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+    if let Some(path) = args.record {
+        cli.recorder = Some(Recorder::new(&path));
+    }
+
+    // Piped input runs as a script rather than echoing prompts
+    let default = if io::stdin().is_terminal() {
+        Command::Repl
+    } else {
+        Command::Batch { script: "-".to_string(), keep_going: false }
+    };
+    let mut result = run(&mut cli, args.command.unwrap_or(default));
+    // A recording still going when the program ends is kept, even after an error
+    if cli.recorder.is_some() {
+        if let Err(e) = cli.stop_recording() {
+            if result.is_ok() {
+                result = Err(e);
+            } else {
+                eprintln!("Error: {:#}", e);
+            }
+        }
+    }
+    match result {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(e)) => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_USAGE)
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+/// What to do after a command
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(PartialEq)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Run one REPL or script command. The inner error is a message about the
+/// command itself (usage, unknown names); the outer one a failure to carry it out.
+#[cfg(not(target_arch = "wasm32"))]
+fn execute(cli: &mut CLI, parts: &[&str]) -> anyhow::Result<Result<Flow, String>> {
+    let Some(&command) = parts.first() else {
+        return Ok(Ok(Flow::Continue));
+    };
+    match command {
+        "list" => match split_json(parts) {
+            (parts, json) if parts.get(1) == Some(&"presets") => match split_preset_filter(parts) {
+                Ok(([_, _], filter)) => cli.list_presets(json, filter),
+                Ok(_) => return Ok(Err("Usage: list presets [--category <category>] [--favorites] [--json]".to_string())),
+                Err(e) => return Ok(Err(e)),
+            },
+            ([_, "melodies"], json) => cli.list_melodies(json),
+            ([_, "devices"], json) => cli.list_devices(json),
+            ([_], _) => return Ok(Err("Usage: list <presets|melodies|devices> [--json]".to_string())),
+            _ => return Ok(Err("Unknown list command. Use 'list presets', 'list melodies' or 'list devices'".to_string())),
+        },
+        "play" => {
+            let (parts, tempo) = match split_tempo(parts) {
+                Ok(split) => split,
+                Err(e) => return Ok(Err(e)),
+            };
+            // A lone melody plays with the default preset
+            let (preset, melody) = match parts {
+                [_, melody] => (cli.default_preset(), melody.to_string()),
+                [_, preset, melody @ ..] if !melody.is_empty() => (preset.to_string(), melody.join(" ")),
+                _ => return Ok(Err("Usage: play [preset] <melody> [tempo <bpm>]\n\
+                    Example: play bell twinkle\n\
+                    Example: play 1 3 tempo 90\n\
+                    Example: play bell \"tempo=100 C4:1/2 D4 E4 r [C4 E4 G4]:2\"".to_string())),
+            };
+            if let Err(e) = cli.play(&preset, &melody, tempo)? {
+                return Ok(Err(e));
+            }
+        }
+        "render" => {
+            let (parts, tempo) = match split_tempo(parts) {
+                Ok(split) => split,
+                Err(e) => return Ok(Err(e)),
+            };
+            if parts.len() < 4 {
+                return Ok(Err("Usage: render <preset> <melody> <file.wav> [tempo <bpm>]\n\
+                    Example: render bell twinkle twinkle.wav".to_string()));
+            }
+            let melody_name = parts[2..parts.len() - 1].join(" ");
+            match cli.render(parts[1], &melody_name, parts[parts.len() - 1], tempo) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Ok(Err(e)),
+                Err(e) => return Err(e.context("Render failed")),
+            }
+        }
+        "export-midi" => {
+            let split = split_tempo(parts)
+                .and_then(|(parts, tempo)| split_midi_format(parts).map(|(parts, format)| (parts, format, tempo)));
+            let (parts, format, tempo) = match split {
+                Ok(split) => split,
+                Err(e) => return Ok(Err(e)),
+            };
+            if parts.len() < 3 {
+                return Ok(Err("Usage: export-midi <melody> <file.mid> [type 0|1] [tempo <bpm>]\n\
+                    Example: export-midi twinkle twinkle.mid".to_string()));
+            }
+            let melody_name = parts[1..parts.len() - 1].join(" ");
+            let path = parts[parts.len() - 1];
+            let mut score = match cli.find_score(&melody_name) {
+                Ok(score) => score,
+                Err(e) => return Ok(Err(e)),
+            };
+            if let Some(bpm) = tempo {
+                score.set_tempo(bpm);
+            }
+            let name = match cli.find_melody(&melody_name) {
+                Ok(melody) => melody.name,
+                Err(_) => std::path::Path::new(&melody_name).file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .filter(|_| std::path::Path::new(&melody_name).is_file())
+                    .unwrap_or("Melody"),
+            };
+            MidiFile::from_score(name, &score, &cli.tuning).write(path, format)
+                .map_err(|e| anyhow::anyhow!("Export failed: {}", e))?;
+            println!("Exported {} notes to {}", score.notes.len(), path);
+        }
+        "fx" => match parts.len() {
+            1 => cli.print_effects(),
+            2 if parts[1] == "preset" => {
+                cli.session_effects = None;
+                println!("Using each preset's own effects.");
+            }
+            3 => match cli.set_effect(parts[1], parts[2]) {
+                Ok(()) => cli.print_effects(),
+                Err(e) => return Ok(Err(e)),
+            },
+            _ => return Ok(Err("Usage: fx [chorus|delay|reverb <mix>] | fx preset".to_string())),
+        },
+        "master" => {
+            if parts.len() > 1 {
+                if let Err(e) = cli.set_master(&parts[1..]) {
+                    return Ok(Err(e));
+                }
+            }
+            cli.print_master();
+        }
+        "meter" => cli.print_meter(),
+        "tuning" => {
+            if parts.get(1) == Some(&"list") {
+                cli.list_tunings();
+            } else {
                 if parts.len() > 1 {
-                    match parts[1] {
-                        "presets" => cli.list_presets(),
-                        "melodies" => cli.list_melodies(),
-                        _ => println!("Unknown list command. Use 'list presets' or 'list melodies'"),
+                    if let Err(e) = cli.set_tuning(&parts[1..]) {
+                        return Ok(Err(e));
                     }
-                } else {
-                    println!("Usage: list <presets|melodies>");
                 }
+                cli.print_tuning();
             }
-            "play" => {
-                if parts.len() >= 3 {
-                    let preset_name = parts[1];
-                    let melody_name = parts[2..].join(" ");
-                    
-                    match (cli.find_preset(preset_name), cli.find_melody(&melody_name)) {
-                        (Some(preset), Some(melody)) => {
-                            println!("Playing '{}' melody with '{}' preset...", melody_name, preset_name);
-                            play_melody(preset, melody)?;
-                            println!("Done!");
-                        }
-                        (None, _) => println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name),
-                        (_, None) => println!("Melody '{}' not found. Use 'list melodies' to see available options.", melody_name),
+        }
+        "arp" => {
+            if parts.len() > 1 {
+                if let Err(e) = cli.set_arpeggiator(&parts[1..]) {
+                    return Ok(Err(e));
+                }
+            }
+            cli.print_arpeggiator();
+        }
+        "seq" => {
+            if let Err(e) = cli.run_sequencer(&parts[1..])? {
+                return Ok(Err(e));
+            }
+        }
+        "demo" => cli.demo()?,
+        "piano" => {
+            let preset = (parts.len() > 1).then(|| parts[1..].join(" "));
+            if let Err(e) = piano::run(cli, preset.as_deref())? {
+                return Ok(Err(e));
+            }
+        }
+        "tui" => {
+            let preset = (parts.len() > 1).then(|| parts[1..].join(" "));
+            if let Err(e) = tui::run(cli, preset.as_deref())? {
+                return Ok(Err(e));
+            }
+        }
+        "record" => match &parts[1..] {
+            [] => cli.print_recording(),
+            ["stop"] => {
+                if let Err(e) = cli.stop_recording()? {
+                    return Ok(Err(e));
+                }
+            }
+            ["start", path @ ..] if !path.is_empty() => {
+                if let Err(e) = cli.start_recording(&path.join(" ")) {
+                    return Ok(Err(e));
+                }
+            }
+            _ => return Ok(Err("Usage: record [start <file>|stop]".to_string())),
+        },
+        "search" => match split_json(parts) {
+            ([_], _) => return Ok(Err("Usage: search <words...> [--json]\n\
+                Example: search bright bell".to_string())),
+            (parts, json) => cli.search_presets(&parts[1..].join(" "), json),
+        },
+        "fav" | "unfav" => {
+            let favorite = command == "fav";
+            match &parts[1..] {
+                [] if favorite => cli.list_presets(false, PresetFilter { favorites: true, ..PresetFilter::default() }),
+                [] => return Ok(Err("Usage: unfav <preset>".to_string())),
+                name => {
+                    if let Err(e) = cli.set_favorite(&name.join(" "), favorite)? {
+                        return Ok(Err(e));
                     }
-                } else {
-                    println!("Usage: play <preset> <melody>");
-                    println!("Example: play bell twinkle");
-                    println!("Example: play 1 3");
                 }
             }
-            "demo" => {
-                println!("Playing demo with all presets...");
-                let scale = vec![
-                    ("C4", 300), ("D4", 300), ("E4", 300), ("F4", 300),
-                    ("G4", 300), ("A4", 300), ("B4", 300), ("C5", 600),
-                ];
-                for (name, preset) in &cli.presets {
-                    println!("  Playing: {}", name);
-                    play_melody(preset.clone(), scale.clone())?;
+        }
+        "load-preset" => match &parts[1..] {
+            [] => return Ok(Err("Usage: load-preset <file.preset>".to_string())),
+            path => match cli.load_preset(&path.join(" ")) {
+                Ok(index) => {
+                    let preset = &cli.presets[index];
+                    println!("Loaded preset {}. {} ({})", index + 1, preset.name, preset.category.name());
+                }
+                Err(e) => return Ok(Err(e)),
+            },
+        },
+        "config" => match &parts[1..] {
+            [] | ["show"] => cli.config.show(),
+            _ => return Ok(Err("Usage: config [show]".to_string())),
+        },
+        "info" => {
+            let (parts, json) = split_json(parts);
+            if parts.len() > 1 {
+                if let Err(e) = cli.print_preset(&parts[1..].join(" "), json) {
+                    return Ok(Err(e));
                 }
-                println!("Demo complete!");
+            } else {
+                cli.print_info(json);
             }
-            "help" => cli.print_menu(),
-            "quit" | "exit" => {
-                println!("Goodbye!");
-                break;
+        }
+        "wait" => match parts[1..] {
+            [seconds] => match seconds.parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0) {
+                Some(seconds) => std::thread::sleep(Duration::from_secs_f64(seconds)),
+                None => return Ok(Err(format!("Invalid wait '{}', use seconds", seconds))),
+            },
+            _ => return Ok(Err("Usage: wait <seconds>".to_string())),
+        },
+        "echo" => println!("{}", parts[1..].join(" ")),
+        "clear" => {
+            print!("\x1B[2J\x1B[1;1H");
+            io::stdout().flush()?;
+        }
+        "help" => cli.print_menu(),
+        "quit" | "exit" => return Ok(Ok(Flow::Quit)),
+        _ => return Ok(Err("Unknown command. Type 'help' for available commands.".to_string())),
+    }
+    Ok(Ok(Flow::Continue))
+}
+
+/// Commands offered by tab completion
+#[cfg(not(target_arch = "wasm32"))]
+const COMMANDS: &[&str] = &[
+    "list", "search", "fav", "unfav", "load-preset", "play", "render", "export-midi", "fx", "master",
+    "meter", "tuning", "arp", "seq", "piano", "tui", "record", "config", "demo", "info", "wait", "echo",
+    "clear", "help", "quit",
+];
+
+/// Tab completion of commands, their options, and preset and melody names
+#[cfg(not(target_arch = "wasm32"))]
+struct ReplHelper {
+    presets: Vec<String>,    // Lowercase, spaces as dashes so they stay one word
+    melodies: Vec<String>,   // Lowercase; a melody runs to the end of the line
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ReplHelper {
+    fn new(cli: &CLI) -> Self {
+        Self {
+            presets: cli.presets.iter().map(|preset| preset.name.to_lowercase().replace(' ', "-")).collect(),
+            melodies: cli.melodies.iter().map(|melody| melody.name.to_lowercase()).collect(),
+        }
+    }
+
+    /// Candidates for the word at `index` after `words`; melodies may take the rest of the line
+    fn candidates(&self, words: &[&str], index: usize) -> (Vec<String>, bool) {
+        let owned = |list: &[&str]| list.iter().map(|s| s.to_string()).collect();
+        let command = words.first().copied().unwrap_or("");
+        match (command, index) {
+            (_, 0) => (owned(COMMANDS), false),
+            ("play" | "render", 1) | ("info", 1) | ("piano" | "tui", 1) | ("fav" | "unfav", 1) => (self.presets.clone(), false),
+            ("play" | "render", _) | ("export-midi", _) => (self.melodies.clone(), true),
+            ("list", 1) => (owned(&["presets", "melodies", "devices"]), false),
+            ("list", _) if words.get(index - 1) == Some(&"--category") => {
+                (Category::ALL.iter().map(|category| category.name().to_string()).collect(), false)
             }
-            _ => println!("Unknown command. Type 'help' for available commands."),
+            ("list", _) if words.get(1) == Some(&"presets") => (owned(&["--category", "--favorites", "--json"]), false),
+            ("record", 1) => (owned(&["start", "stop"]), false),
+            ("config", 1) => (owned(&["show"]), false),
+            ("fx", 1) => (owned(&["chorus", "delay", "reverb", "preset"]), false),
+            ("master", 1) => (owned(&["gain", "limiter", "softclip"]), false),
+            ("master", 2) => (owned(&["on", "off"]), false),
+            ("tuning", 1) => (owned(&["a4", "scale", "kbm", "list", "reset"]), false),
+            ("tuning", 2) if words.get(1) == Some(&"scale") => {
+                (Scale::builtin_names().chain(["12tet", "19edo", "31edo"]).map(String::from).collect(), false)
+            }
+            ("arp", 1) => {
+                let mut modes: Vec<String> = ArpMode::ALL.iter().map(|mode| mode.name().to_string()).collect();
+                modes.push("off".to_string());
+                (modes, false)
+            }
+            ("arp", _) => (owned(&["octaves", "rate", "gate"]), false),
+            ("seq", 1) => (owned(&[
+                "demo", "load", "show", "play", "render", "export", "mute", "solo",
+                "tempo", "loop", "track", "pattern", "step", "song",
+            ]), false),
+            ("seq", 2) if words.get(1) == Some(&"loop") => (owned(&["on", "off"]), false),
+            ("seq", 3) if words.get(1) == Some(&"track") => (self.presets.clone(), false),
+            _ => (Vec::new(), false),
         }
     }
-    
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        // Start offsets of each word before the cursor
+        let mut starts = Vec::new();
+        let mut in_word = false;
+        for (i, c) in before.char_indices() {
+            if !c.is_whitespace() && !in_word {
+                starts.push(i);
+            }
+            in_word = !c.is_whitespace();
+        }
+        if !in_word {
+            starts.push(pos);
+        }
+        let index = starts.len() - 1;
+        let words: Vec<&str> = before.split_whitespace().collect();
+
+        let (candidates, rest_of_line) = self.candidates(&words, index);
+        // A melody name starts at its first word and may contain spaces
+        let start = match rest_of_line {
+            true => starts[if words.first() == Some(&"export-midi") { 1 } else { 2 }.min(index)],
+            false => starts[index],
+        };
+        let partial = before[start..].to_lowercase();
+        let matches = candidates.into_iter()
+            .filter(|candidate| candidate.starts_with(&partial))
+            .map(|candidate| Pair { display: candidate.clone(), replacement: candidate })
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Highlighter for ReplHelper {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Validator for ReplHelper {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Helper for ReplHelper {}
+
+/// `$XDG_STATE_HOME/fm_synth/history`, falling back to `~/.local/state`
+#[cfg(not(target_arch = "wasm32"))]
+fn history_path() -> Option<PathBuf> {
+    config::xdg_path("XDG_STATE_HOME", ".local/state", "history")
+}
+
+/// `$XDG_STATE_HOME/fm_synth/favorites`, one preset name per line
+#[cfg(not(target_arch = "wasm32"))]
+fn favorites_path() -> Option<PathBuf> {
+    config::xdg_path("XDG_STATE_HOME", ".local/state", "favorites")
+}
+
+/// Interactive prompt with line editing, history and tab completion,
+/// reading commands until `quit` or end of input
+#[cfg(not(target_arch = "wasm32"))]
+fn repl(cli: &mut CLI) -> anyhow::Result<()> {
+    let mut editor: Editor<ReplHelper, FileHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper::new(cli)));
+    cli.interactive = io::stdin().is_terminal();
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing file just means no history yet
+        let _ = editor.load_history(path);
+    }
+
+    cli.print_menu();
+    loop {
+        let input = match editor.readline("> ") {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if !input.trim().is_empty() {
+            editor.add_history_entry(input.as_str())?;
+        }
+
+        let words = match split_command(&input) {
+            Ok(words) => words,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let parts: Vec<&str> = words.iter().map(String::as_str).collect();
+        match execute(cli, &parts) {
+            // Complete the names of presets loaded during the session too
+            Ok(Ok(Flow::Continue)) if parts.first() == Some(&"load-preset") => {
+                editor.set_helper(Some(ReplHelper::new(cli)));
+            }
+            Ok(Ok(Flow::Continue)) => {}
+            Ok(Ok(Flow::Quit)) => break,
+            Ok(Err(e)) => println!("{}", e),
+            Err(e) => println!("Error: {:#}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        let saved = path.parent().map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(ReadlineError::from)
+            .and_then(|()| editor.save_history(path));
+        if let Err(e) = saved {
+            eprintln!("Could not save history to {}: {}", path.display(), e);
+        }
+    }
+    println!("Goodbye!");
     Ok(())
 }
 
+/// Run a command script (see script.rs), reporting problems with their line
+/// numbers. Stops at the first error unless `keep_going` is set.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_batch(cli: &mut CLI, text: &str, keep_going: bool) -> anyhow::Result<Result<(), String>> {
+    let commands = match script::expand(text) {
+        Ok(commands) => commands,
+        Err(e) => return Ok(Err(e.to_string())),
+    };
+
+    let mut errors = 0;
+    for command in commands {
+        let words = match split_command(&command.text) {
+            Ok(words) => words,
+            Err(e) if keep_going => {
+                eprintln!("line {}: {}", command.line, e);
+                errors += 1;
+                continue;
+            }
+            Err(e) => return Ok(Err(format!("line {}: {}", command.line, e))),
+        };
+        let parts: Vec<&str> = words.iter().map(String::as_str).collect();
+        match execute(cli, &parts) {
+            Ok(Ok(Flow::Continue)) => {}
+            Ok(Ok(Flow::Quit)) => break,
+            Ok(Err(e)) if keep_going => {
+                eprintln!("line {}: {}", command.line, e);
+                errors += 1;
+            }
+            Ok(Err(e)) => return Ok(Err(format!("line {}: {}", command.line, e))),
+            Err(e) if keep_going => {
+                eprintln!("line {}: {:#}", command.line, e);
+                errors += 1;
+            }
+            Err(e) => return Err(e.context(format!("line {}", command.line))),
+        }
+    }
+    match errors {
+        0 => Ok(Ok(())),
+        n => Ok(Err(format!("{} command(s) failed", n))),
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 fn main() {
     panic!("This binary is not meant to be run in WebAssembly. Use the web interface instead.");
@@ -0,0 +1,205 @@
+// src/master.rs - Master gain, soft clipper, look-ahead limiter and level meter
+
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+/// Master gains accepted from commands and settings, in dB
+pub const GAIN_RANGE_DB: RangeInclusive<f32> = -60.0..=24.0;
+
+/// Master output settings, held per session
+#[derive(Clone, Debug)]
+pub struct MasterParams {
+    pub gain_db: f32,       // Gain before clipping and limiting
+    pub soft_clip: bool,    // Saturate smoothly before the limiter
+    pub drive: f32,         // Soft clip drive (1.0 = gentle)
+    pub limiter: bool,
+    pub ceiling_db: f32,    // Limiter output ceiling in dBFS
+    pub lookahead_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for MasterParams {
+    fn default() -> Self {
+        Self {
+            gain_db: 0.0,
+            soft_clip: false,
+            drive: 1.0,
+            limiter: true,
+            ceiling_db: -0.3,
+            lookahead_ms: 5.0,
+            release_ms: 100.0,
+        }
+    }
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    if gain > 0.0 {
+        20.0 * gain.log10()
+    } else {
+        f32::NEG_INFINITY
+    }
+}
+
+/// Levels measured at the master output
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeterReading {
+    pub peak: f32,            // Highest absolute sample after limiting
+    pub rms: f32,             // RMS over everything measured
+    pub overs: usize,         // Samples that would have exceeded the ceiling unprocessed
+    pub max_reduction: f32,   // Deepest limiter gain reduction in dB
+}
+
+impl MeterReading {
+    pub fn peak_db(&self) -> f32 {
+        gain_to_db(self.peak)
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        gain_to_db(self.rms)
+    }
+}
+
+#[derive(Default)]
+struct Meter {
+    peak: f32,
+    sum_squares: f64,
+    samples: u64,
+    overs: usize,
+    max_reduction: f32,
+}
+
+impl Meter {
+    fn reading(&self) -> MeterReading {
+        let rms = if self.samples > 0 {
+            (self.sum_squares / self.samples as f64).sqrt() as f32
+        } else {
+            0.0
+        };
+        MeterReading {
+            peak: self.peak,
+            rms,
+            overs: self.overs,
+            max_reduction: self.max_reduction,
+        }
+    }
+}
+
+/// Final stage between the effects bus and the device or file
+pub struct MasterBus {
+    params: MasterParams,
+    sample_rate: f32,
+    delay: VecDeque<(f32, f32)>,   // Look-ahead delay line
+    minimum: VecDeque<(u64, f32)>, // (frame, gain it needs), rising from the window's minimum
+    frames: u64,                   // Frames limited so far
+    gain: f32,                     // Current limiter gain
+    attack: f32,                   // Gain drop per frame while ramping down
+    meter: Meter,
+}
+
+impl MasterBus {
+    pub fn new(sample_rate: f32, params: MasterParams) -> Self {
+        let mut bus = Self {
+            params: MasterParams::default(),
+            sample_rate,
+            delay: VecDeque::new(),
+            minimum: VecDeque::new(),
+            frames: 0,
+            gain: 1.0,
+            attack: 0.0,
+            meter: Meter::default(),
+        };
+        bus.set_params(params);
+        bus
+    }
+
+    pub fn set_params(&mut self, params: MasterParams) {
+        let lookahead = if params.limiter {
+            (params.lookahead_ms.max(0.0) * self.sample_rate / 1000.0) as usize
+        } else {
+            0
+        };
+        self.delay = VecDeque::from(vec![(0.0, 0.0); lookahead]);
+        self.minimum.clear();
+        self.params = params;
+    }
+
+    pub fn meter(&self) -> MeterReading {
+        self.meter.reading()
+    }
+
+    pub fn reset_meter(&mut self) {
+        self.meter = Meter::default();
+    }
+
+    fn saturate(&self, x: f32) -> f32 {
+        let drive = self.params.drive.max(0.1);
+        (x * drive).tanh() / drive.tanh()
+    }
+
+    pub fn process(&mut self, frame: (f32, f32)) -> (f32, f32) {
+        let gain = db_to_gain(self.params.gain_db);
+        let mut frame = (frame.0 * gain, frame.1 * gain);
+        if self.params.soft_clip {
+            frame = (self.saturate(frame.0), self.saturate(frame.1));
+        }
+
+        let ceiling = db_to_gain(self.params.ceiling_db);
+        let level = frame.0.abs().max(frame.1.abs());
+        if level > ceiling {
+            self.meter.overs += 1;
+        }
+
+        let out = if self.params.limiter {
+            self.limit(frame, level, ceiling)
+        } else {
+            frame
+        };
+
+        self.meter.peak = self.meter.peak.max(out.0.abs()).max(out.1.abs());
+        self.meter.sum_squares += ((out.0 * out.0 + out.1 * out.1) * 0.5) as f64;
+        self.meter.samples += 1;
+        out
+    }
+
+    /// Delay the signal by the look-ahead time so gain reduction is already
+    /// in place when a peak reaches the output
+    fn limit(&mut self, frame: (f32, f32), level: f32, ceiling: f32) -> (f32, f32) {
+        let target = if level > ceiling { ceiling / level } else { 1.0 };
+        let lookahead = self.delay.len() as u64;
+        self.delay.push_back(frame);
+        let delayed = self.delay.pop_front().unwrap_or(frame);
+
+        // Running minimum over the frames from the outgoing one to the new one
+        while self.minimum.back().is_some_and(|&(_, t)| t >= target) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.frames, target));
+        while self.minimum.front().is_some_and(|&(f, _)| f + lookahead < self.frames) {
+            self.minimum.pop_front();
+        }
+        self.frames += 1;
+
+        // A new minimum arrives with the incoming frame, lookahead + 1 gain
+        // updates before it is output; ramp down linearly to reach it in time
+        let required = self.minimum.front().map_or(target, |&(_, t)| t);
+        if required < self.gain {
+            self.attack = self.attack.max((self.gain - required) / (lookahead + 1) as f32);
+            self.gain = (self.gain - self.attack).max(required);
+        } else {
+            self.attack = 0.0;
+            let release = (-1.0 / (self.params.release_ms.max(1.0) * 0.001 * self.sample_rate)).exp();
+            self.gain = required + (self.gain - required) * release;
+        }
+        self.meter.max_reduction = self.meter.max_reduction.max(-gain_to_db(self.gain));
+
+        // Safety net for anything the smoothing could not catch
+        (
+            (delayed.0 * self.gain).clamp(-ceiling, ceiling),
+            (delayed.1 * self.gain).clamp(-ceiling, ceiling),
+        )
+    }
+}
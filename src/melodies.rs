@@ -0,0 +1,150 @@
+// src/melodies.rs - Built-in melodies
+
+use crate::arpeggiator::{ArpMode, Arpeggiator};
+use crate::score::Articulation;
+use crate::timing::{TimeSignature, Timing, PPQ};
+
+// Note values in ticks
+const S: u32 = PPQ / 4;       // Sixteenth
+const E: u32 = PPQ / 2;       // Eighth
+const DE: u32 = PPQ * 3 / 4;  // Dotted eighth
+const Q: u32 = PPQ;           // Quarter
+const DQ: u32 = PPQ * 3 / 2;  // Dotted quarter
+const H: u32 = PPQ * 2;       // Half
+
+/// A melody as (note, ticks) pairs; `r` is a rest, `C4 E4 G4` a chord, and
+/// notes may carry articulation marks and ties (see `score::split_marks`)
+#[derive(Clone, Debug)]
+pub struct Melody {
+    pub name: &'static str,
+    pub timing: Timing,
+    pub articulation: Articulation,   // For notes without their own mark
+    pub arpeggiator: Option<Arpeggiator>, // Plays chords as arpeggios
+    pub notes: Vec<(&'static str, u32)>,
+}
+
+/// Melody definitions
+pub fn get_melodies() -> Vec<Melody> {
+    vec![
+        Melody {
+            name: "Twinkle Twinkle",
+            timing: Timing::new(120.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", Q), ("C4", Q), ("G4", Q), ("G4", Q),
+                ("A4", Q), ("A4", Q), ("G4", H),
+                ("F4", Q), ("F4", Q), ("E4", Q), ("E4", Q),
+                ("D4", Q), ("D4", Q), ("C4", H),
+            ],
+        },
+        Melody {
+            name: "Happy Birthday",
+            timing: Timing::new(120.0, TimeSignature::new(3, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", E), ("C4", E), ("D4", Q), ("C4", Q),
+                ("F4", Q), ("E4", H),
+                ("C4", E), ("C4", E), ("D4", Q), ("C4", Q),
+                ("G4", Q), ("F4", H),
+            ],
+        },
+        Melody {
+            name: "Ode to Joy",
+            timing: Timing::new(120.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Legato,
+            arpeggiator: None,
+            notes: vec![
+                ("E4", Q), ("E4", Q), ("F4", Q), ("G4", Q),
+                ("G4", Q), ("F4", Q), ("E4", Q), ("D4", Q),
+                ("C4", Q), ("C4", Q), ("D4", Q), ("E4", Q),
+                ("E4", DQ), ("D4", E), ("D4", H),
+            ],
+        },
+        Melody {
+            name: "Mary Had a Little Lamb",
+            timing: Timing::new(120.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("E4", Q), ("D4", Q), ("C4", Q), ("D4", Q),
+                ("E4", Q), ("E4", Q), ("E4", H),
+                ("D4", Q), ("D4", Q), ("D4", H),
+                ("E4", Q), ("G4", Q), ("G4", H),
+            ],
+        },
+        Melody {
+            name: "Chromatic Scale",
+            timing: Timing::new(150.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", E), ("C#4", E), ("D4", E), ("D#4", E),
+                ("E4", E), ("F4", E), ("F#4", E), ("G4", E),
+                ("G#4", E), ("A4", E), ("A#4", E), ("B4", E),
+                ("C5", Q),
+            ],
+        },
+        Melody {
+            name: "Major Arpeggio",
+            timing: Timing::new(100.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", E), ("E4", E), ("G4", E), ("C5", E),
+                ("G4", E), ("E4", E), ("C4", Q),
+            ],
+        },
+        Melody {
+            name: "Minor Pentatonic",
+            timing: Timing::new(150.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("A3", Q), ("C4", Q), ("D4", Q), ("E4", Q),
+                ("G4", Q), ("A4", Q), ("G4", Q), ("E4", Q),
+                ("D4", Q), ("C4", Q), ("A3", H),
+            ],
+        },
+        Melody {
+            name: "Jazz Lick",
+            timing: Timing::new(150.0, TimeSignature::new(4, 4)).with_swing(0.62),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", E), ("E4", E), ("G4", E), ("A#4", E),
+                ("A4_", Q), ("F4", E), ("D4_", Q),
+                ("G4", E), ("E4", E), ("C4", DQ),
+            ],
+        },
+        Melody {
+            name: "Bach Invention",
+            timing: Timing::new(75.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Normal,
+            arpeggiator: None,
+            notes: vec![
+                ("C4", S), ("D4", S), ("E4", S), ("F4", S),
+                ("D4", S), ("E4", S), ("C4", E),
+                ("G4", S), ("F4", S), ("E4", S), ("D4", S),
+                ("B3", S), ("C4", DE),
+            ],
+        },
+        Melody {
+            name: "Synth Demo",
+            timing: Timing::new(100.0, TimeSignature::new(4, 4)),
+            articulation: Articulation::Staccato,
+            arpeggiator: Some(Arpeggiator { octaves: 2, ..Arpeggiator::new(ArpMode::UpDown) }),
+            notes: vec![
+                ("C4", S), ("E4", S), ("G4", S), ("C5", S),
+                ("E5", S), ("G5", S), ("E5", S), ("C5", S),
+                ("G4", S), ("E4", S), ("C4_", E),
+                ("r", E),
+                ("F4", S), ("A4", S), ("C5", S), ("F5", S),
+                ("C5", S), ("A4", S), ("F4_", E),
+                ("r", E),
+                ("C4 E4 G4^", H), ("F4 A4 C5^", Q), ("G4 B4 D5^", Q), ("C4 E4 G4 C5^", H),
+            ],
+        },
+    ]
+}
@@ -0,0 +1,242 @@
+// src/midi.rs - Standard MIDI File (.mid) export of scores and sequencer songs
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::score::Score;
+use crate::sequencer::{PlayMode, Sequencer};
+use crate::timing::{TempoChange, TimeSignature, PPQ};
+use crate::tuning::Tuning;
+
+/// SMF type 0 keeps everything on one track; type 1 has a tempo track
+/// followed by one track per part
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MidiFormat {
+    Single,
+    #[default]
+    MultiTrack,
+}
+
+impl MidiFormat {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "0" => Some(MidiFormat::Single),
+            "1" => Some(MidiFormat::MultiTrack),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> u16 {
+        match self {
+            MidiFormat::Single => 0,
+            MidiFormat::MultiTrack => 1,
+        }
+    }
+}
+
+/// A note on the file's tick grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiNote {
+    pub tick: u64,
+    pub ticks: u64,
+    pub key: u8,
+    pub velocity: u8,   // 1-127
+}
+
+/// One part, played on its own channel
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiTrack {
+    pub name: String,
+    pub channel: u8,   // 0-15
+    pub notes: Vec<MidiNote>,
+}
+
+/// Tracks with the tempo map and time signature, at `PPQ` ticks per quarter note
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiFile {
+    pub name: String,
+    pub tempo: Vec<TempoChange>,
+    pub time_signature: TimeSignature,
+    pub tracks: Vec<MidiTrack>,
+}
+
+/// The MIDI key that plays a frequency under `tuning`, so exports keep
+/// their notes whatever the reference pitch or keyboard map
+pub fn freq_to_key(freq: f32, tuning: &Tuning) -> u8 {
+    tuning.nearest_key(freq).unwrap_or(0).clamp(0, 127) as u8
+}
+
+/// MIDI velocity (1-127) for a level from 0.0 to 1.0
+pub(crate) fn midi_velocity(velocity: f32) -> u8 {
+    (velocity.clamp(0.0, 1.0) * 127.0).round().max(1.0) as u8
+}
+
+/// Variable-length quantity: 7 bits per byte, high bit set on all but the last
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Events of one track chunk as (tick, bytes), written in tick order
+struct TrackChunk {
+    events: Vec<(u64, Vec<u8>)>,
+}
+
+impl TrackChunk {
+    fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    fn meta(&mut self, tick: u64, kind: u8, data: &[u8]) {
+        let mut bytes = vec![0xFF, kind];
+        write_vlq(&mut bytes, data.len() as u32);
+        bytes.extend_from_slice(data);
+        self.events.push((tick, bytes));
+    }
+
+    fn name(&mut self, name: &str) {
+        self.meta(0, 0x03, name.as_bytes());
+    }
+
+    fn conductor(&mut self, file: &MidiFile) {
+        let ts = file.time_signature;
+        let unit_power = ts.unit.trailing_zeros() as u8;
+        self.meta(0, 0x58, &[ts.beats.min(255) as u8, unit_power, 24, 8]);
+        for change in &file.tempo {
+            let micros = (60_000_000.0 / change.bpm).round().clamp(1.0, 0xFF_FFFF as f64) as u32;
+            self.meta(change.tick, 0x51, &micros.to_be_bytes()[1..]);
+        }
+    }
+
+    fn notes(&mut self, track: &MidiTrack) {
+        let channel = track.channel & 0x0F;
+        for note in &track.notes {
+            self.events.push((note.tick, vec![0x90 | channel, note.key & 0x7F, note.velocity.clamp(1, 127)]));
+            self.events.push((note.tick + note.ticks, vec![0x80 | channel, note.key & 0x7F, 0]));
+        }
+    }
+
+    fn encode(mut self) -> Vec<u8> {
+        // Note-offs before note-ons at the same tick so repeated keys retrigger
+        self.events.sort_by_key(|(tick, bytes)| (*tick, bytes[0] & 0xF0 != 0x80));
+        let mut data = Vec::new();
+        let mut last = 0;
+        for (tick, bytes) in &self.events {
+            write_vlq(&mut data, (tick - last).min(0x0FFF_FFFF) as u32);
+            data.extend_from_slice(bytes);
+            last = *tick;
+        }
+        write_vlq(&mut data, 0);
+        data.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+        let mut out = Vec::with_capacity(data.len() + 8);
+        out.extend_from_slice(b"MTrk");
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend(data);
+        out
+    }
+}
+
+impl MidiFile {
+    /// One track from a score played in `tuning`; swing is written into the
+    /// note positions
+    pub fn from_score(name: &str, score: &Score, tuning: &Tuning) -> Self {
+        let swing = |tick: u64| score.timing.swing.apply(tick as f64).round() as u64;
+        let notes = score.notes.iter()
+            .map(|note| {
+                let (start, end) = (swing(note.tick), swing(note.tick + note.ticks));
+                MidiNote {
+                    tick: start,
+                    ticks: end.saturating_sub(start).max(1),
+                    key: freq_to_key(note.freq, tuning),
+                    velocity: midi_velocity(note.velocity),
+                }
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            tempo: score.timing.tempo.changes().to_vec(),
+            time_signature: score.timing.time_signature,
+            tracks: vec![MidiTrack { name: name.to_string(), channel: 0, notes }],
+        }
+    }
+
+    /// The sequencer's song played once through, one track per sequencer track
+    pub fn from_sequencer(name: &str, sequencer: &Sequencer) -> Self {
+        let mut sequencer = sequencer.clone();
+        let bpm = sequencer.tempo();
+        let (triggers, _) = sequencer.schedule(PlayMode::Song, 1);
+        let to_tick = |seconds: f64| (seconds * bpm / 60.0 * PPQ as f64).round() as u64;
+
+        let mut tracks: Vec<MidiTrack> = sequencer.tracks.iter()
+            .enumerate()
+            .map(|(i, track)| MidiTrack { name: track.name.clone(), channel: (i % 16) as u8, notes: Vec::new() })
+            .collect();
+        for trigger in triggers {
+            let tick = to_tick(trigger.time);
+            tracks[trigger.track].notes.push(MidiNote {
+                tick,
+                ticks: to_tick(trigger.time + trigger.length).saturating_sub(tick).max(1),
+                key: freq_to_key(trigger.freq, &sequencer.tuning),
+                velocity: midi_velocity(trigger.velocity),
+            });
+        }
+        Self {
+            name: name.to_string(),
+            tempo: vec![TempoChange { tick: 0, bpm }],
+            time_signature: sequencer.timing.time_signature,
+            tracks,
+        }
+    }
+
+    /// Encode as a Standard MIDI File
+    pub fn encode(&self, format: MidiFormat) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        match format {
+            MidiFormat::Single => {
+                let mut chunk = TrackChunk::new();
+                chunk.name(&self.name);
+                chunk.conductor(self);
+                for track in &self.tracks {
+                    chunk.notes(track);
+                }
+                chunks.push(chunk.encode());
+            }
+            MidiFormat::MultiTrack => {
+                let mut conductor = TrackChunk::new();
+                conductor.name(&self.name);
+                conductor.conductor(self);
+                chunks.push(conductor.encode());
+                for track in &self.tracks {
+                    let mut chunk = TrackChunk::new();
+                    chunk.name(&track.name);
+                    chunk.notes(track);
+                    chunks.push(chunk.encode());
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"MThd");
+        out.extend_from_slice(&6u32.to_be_bytes());
+        out.extend_from_slice(&format.number().to_be_bytes());
+        out.extend_from_slice(&(chunks.len() as u16).to_be_bytes());
+        out.extend_from_slice(&(PPQ as u16).to_be_bytes());
+        for chunk in chunks {
+            out.extend(chunk);
+        }
+        out
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, format: MidiFormat) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&self.encode(format))?;
+        file.flush()
+    }
+}
@@ -0,0 +1,173 @@
+// src/modulation.rs - Modulation matrix, LFOs and modulation sources
+
+use std::f32::consts::PI;
+
+/// Control signals that can drive a modulation route
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModSource {
+    AmpEnvelope,   // Amplitude envelope level (0.0 - 1.0)
+    ModEnvelope,   // Dedicated modulation envelope level (0.0 - 1.0)
+    Lfo1,          // First LFO (-1.0 - 1.0)
+    Lfo2,          // Second LFO (-1.0 - 1.0)
+    Velocity,      // Note-on velocity (0.0 - 1.0)
+    KeyTrack,      // Octaves above (or below) middle C
+    ModWheel,      // Mod wheel position (0.0 - 1.0)
+    Aftertouch,    // Channel pressure (0.0 - 1.0)
+}
+
+/// Parameters a modulation route can act on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModDestination {
+    Pitch,           // Semitones
+    ModulationIndex, // Added to the patch's modulation index
+    CarrierLevel,    // Relative change of carrier output level
+    ModulatorLevel,  // Relative change of modulator output level
+    Pan,             // -1.0 (left) to 1.0 (right)
+    FilterCutoff,    // Octaves
+}
+
+/// A single source -> destination connection with its depth
+#[derive(Clone, Debug)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub depth: f32,
+}
+
+impl ModRoute {
+    pub fn new(source: ModSource, destination: ModDestination, depth: f32) -> Self {
+        Self { source, destination, depth }
+    }
+}
+
+/// LFO waveform
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+/// LFO settings stored in a patch
+#[derive(Clone, Debug)]
+pub struct LfoParams {
+    pub rate: f32,          // Rate in Hz
+    pub shape: LfoShape,
+}
+
+impl Default for LfoParams {
+    fn default() -> Self {
+        Self {
+            rate: 5.0,
+            shape: LfoShape::Sine,
+        }
+    }
+}
+
+/// Free-running low frequency oscillator
+pub struct Lfo {
+    params: LfoParams,
+    phase: f32,
+}
+
+impl Lfo {
+    pub fn new(params: LfoParams) -> Self {
+        Self { params, phase: 0.0 }
+    }
+
+    /// Advance the LFO by `dt` seconds and return its bipolar output
+    pub fn advance(&mut self, dt: f32) -> f32 {
+        let value = match self.params.shape {
+            LfoShape::Sine => (2.0 * PI * self.phase).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            LfoShape::Saw => 2.0 * self.phase - 1.0,
+            LfoShape::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+        };
+
+        self.phase += self.params.rate * dt;
+        self.phase -= self.phase.floor();
+
+        value
+    }
+
+    pub fn set_params(&mut self, params: LfoParams) {
+        self.params = params;
+    }
+}
+
+/// Snapshot of every modulation source, taken once per block
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ModSources {
+    pub amp_envelope: f32,
+    pub mod_envelope: f32,
+    pub lfo1: f32,
+    pub lfo2: f32,
+    pub velocity: f32,
+    pub key_track: f32,
+    pub mod_wheel: f32,
+    pub aftertouch: f32,
+}
+
+impl ModSources {
+    pub fn get(&self, source: ModSource) -> f32 {
+        match source {
+            ModSource::AmpEnvelope => self.amp_envelope,
+            ModSource::ModEnvelope => self.mod_envelope,
+            ModSource::Lfo1 => self.lfo1,
+            ModSource::Lfo2 => self.lfo2,
+            ModSource::Velocity => self.velocity,
+            ModSource::KeyTrack => self.key_track,
+            ModSource::ModWheel => self.mod_wheel,
+            ModSource::Aftertouch => self.aftertouch,
+        }
+    }
+}
+
+/// Summed modulation amount for each destination
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ModOutputs {
+    pub pitch: f32,
+    pub modulation_index: f32,
+    pub carrier_level: f32,
+    pub modulator_level: f32,
+    pub pan: f32,
+    pub filter_cutoff: f32,
+}
+
+impl ModOutputs {
+    fn add(&mut self, destination: ModDestination, amount: f32) {
+        match destination {
+            ModDestination::Pitch => self.pitch += amount,
+            ModDestination::ModulationIndex => self.modulation_index += amount,
+            ModDestination::CarrierLevel => self.carrier_level += amount,
+            ModDestination::ModulatorLevel => self.modulator_level += amount,
+            ModDestination::Pan => self.pan += amount,
+            ModDestination::FilterCutoff => self.filter_cutoff += amount,
+        }
+    }
+
+    /// Frequency multiplier for the pitch offset
+    pub fn pitch_ratio(&self) -> f32 {
+        2.0_f32.powf(self.pitch / 12.0)
+    }
+
+    /// Gain multiplier for the carrier, never negative
+    pub fn carrier_gain(&self) -> f32 {
+        (1.0 + self.carrier_level).max(0.0)
+    }
+
+    /// Gain multiplier for the modulator, never negative
+    pub fn modulator_gain(&self) -> f32 {
+        (1.0 + self.modulator_level).max(0.0)
+    }
+}
+
+/// Evaluate all routes against the current source values
+pub fn evaluate(routes: &[ModRoute], sources: &ModSources) -> ModOutputs {
+    let mut outputs = ModOutputs::default();
+    for route in routes {
+        outputs.add(route.destination, sources.get(route.source) * route.depth);
+    }
+    outputs
+}
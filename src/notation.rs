@@ -0,0 +1,324 @@
+// src/notation.rs - Compact text notation for melodies
+//
+// Tokens are separated by whitespace:
+//
+//   C4  F#3  Bb5      note name, MIDI number or cents offset (see pitch.rs)
+//   # text            comment to the end of the line (at the start of a token)
+//   C4:1/2  E4:1.5    duration in quarter-note beats, kept for the notes that follow
+//   r  r:2  rest      rest, in any case
+//   C4:1~ C4:1/2      `~` ties a note into the next one of the same pitch
+//   C4. C4_ C4^ C4@70 staccato, tenuto, legato or a 70% gate for one note
+//   [C4 E4 G4]:2      chord
+//   arp=up            arpeggiate the chords that follow: up, down, updown,
+//                     random, played (as written) or off; `arp-octaves=2`,
+//                     `arp-rate=1/4` (beats per note) and `arp-gate=50` adjust it
+//   legato staccato   articulation for the notes that follow (also
+//   tenuto normal     `gate=70` for a 70% gate)
+//   tempo=96          tempo in beats per minute (default 120); may change mid-melody
+//   time=3/4          time signature
+//   swing=0.6         swing eighth notes (0.5 straight, 0.67 triplet feel)
+//   pp p mp mf f ff   dynamics
+//   |                 bar line, ignored
+
+use std::fmt;
+
+use crate::arpeggiator::{ArpMode, Arpeggiator};
+use crate::score::{split_marks, Articulation, Score, ScoreWriter};
+use crate::timing::{Swing, TimeSignature, Timing, PPQ};
+use crate::tuning::Tuning;
+
+/// Parse failure with a 1-based source position
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Character scanner tracking line and column
+struct Scanner<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Skip whitespace, bar lines and comments
+    fn skip_blank(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                '|' => {
+                    self.bump();
+                }
+                c if c.is_whitespace() => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Read up to the next whitespace or bracket
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == '[' || c == ']' {
+                break;
+            }
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+}
+
+/// A length in beats: `1.5` or `1/2`
+pub fn parse_beats(text: &str) -> Option<f64> {
+    let beats = match text.split_once('/') {
+        Some((num, den)) => num.parse::<f64>().ok()? / den.parse::<f64>().ok()?,
+        None => text.parse::<f64>().ok()?,
+    };
+    (beats.is_finite() && beats > 0.0).then_some(beats)
+}
+
+fn velocity_for(dynamic: &str) -> Option<f32> {
+    match dynamic {
+        "pp" => Some(0.2),
+        "p" => Some(0.35),
+        "mp" => Some(0.5),
+        "mf" => Some(0.65),
+        "f" => Some(0.8),
+        "ff" => Some(1.0),
+        _ => None,
+    }
+}
+
+struct Parser<'a> {
+    scanner: Scanner<'a>,
+    tuning: &'a Tuning,
+    ticks: u64,                       // Current duration
+    velocity: f32,
+    articulation: Articulation,       // For notes without their own mark
+    arpeggiator: Arpeggiator,         // Settings kept while the arpeggiator is off
+    arpeggiating: bool,
+    tie_origin: Option<(usize, usize)>, // Position of the note tied into the next step
+    writer: ScoreWriter,
+}
+
+impl<'a> Parser<'a> {
+    fn error(line: usize, column: usize, message: impl Into<String>) -> ParseError {
+        ParseError { line, column, message: message.into() }
+    }
+
+    /// Read the `:duration` and marks that follow a note, rest or chord
+    fn suffix(&mut self, text: &str, line: usize, column: usize) -> Result<(u64, Articulation, bool), ParseError> {
+        let (text, marks) = split_marks(text).map_err(|e| Self::error(line, column, e))?;
+        if let Some(duration) = text.strip_prefix(':') {
+            self.ticks = parse_beats(duration)
+                .map(|beats| (beats * PPQ as f64).round() as u64)
+                .filter(|&ticks| ticks > 0)
+                .ok_or_else(|| Self::error(line, column, format!("invalid duration '{}'", duration)))?;
+        } else if !text.is_empty() {
+            return Err(Self::error(line, column, format!("unexpected '{}'", text)));
+        }
+        Ok((self.ticks, marks.articulation.unwrap_or(self.articulation), marks.tie))
+    }
+
+    fn push_step(&mut self, pitches: &[f32], suffix: (u64, Articulation, bool), line: usize, column: usize) -> Result<(), ParseError> {
+        let (ticks, articulation, tie) = suffix;
+        if tie && pitches.is_empty() {
+            return Err(Self::error(line, column, "a rest cannot be tied"));
+        }
+        self.writer.step(pitches, ticks, self.velocity, articulation, tie).map_err(|e| {
+            let (line, column) = self.tie_origin.unwrap_or((line, column));
+            Self::error(line, column, e.to_string())
+        })?;
+        self.tie_origin = tie.then_some((line, column));
+        Ok(())
+    }
+
+    fn chord(&mut self, line: usize, column: usize) -> Result<(), ParseError> {
+        self.scanner.bump(); // '['
+        let mut pitches = Vec::new();
+        loop {
+            self.scanner.skip_blank();
+            let (note_line, note_column) = (self.scanner.line, self.scanner.column);
+            match self.scanner.peek() {
+                Some(']') => {
+                    self.scanner.bump();
+                    break;
+                }
+                None => return Err(Self::error(line, column, "unclosed chord, expected ']'")),
+                _ => {
+                    let name = self.scanner.word();
+                    let freq = self.tuning.freq(&name).map_err(|e| {
+                        Self::error(note_line, note_column, format!("bad note '{}': {}", name, e))
+                    })?;
+                    pitches.push(freq);
+                }
+            }
+        }
+        if pitches.is_empty() {
+            return Err(Self::error(line, column, "empty chord"));
+        }
+
+        let (suffix_line, suffix_column) = (self.scanner.line, self.scanner.column);
+        let suffix = self.scanner.word();
+        let suffix = self.suffix(&suffix, suffix_line, suffix_column)?;
+        self.push_step(&pitches, suffix, line, column)
+    }
+
+    /// `=mode`, `-octaves=n`, `-rate=beats` or `-gate=percent` after `arp`
+    fn arp_setting(&mut self, setting: &str, line: usize, column: usize) -> Result<(), ParseError> {
+        let invalid = |what: &str| Self::error(line, column, format!("invalid arpeggiator {} in 'arp{}'", what, setting));
+        if let Some(mode) = setting.strip_prefix('=') {
+            if mode.eq_ignore_ascii_case("off") {
+                self.arpeggiating = false;
+            } else {
+                self.arpeggiator.mode = ArpMode::parse(mode).ok_or_else(|| invalid("mode"))?;
+                self.arpeggiating = true;
+            }
+        } else if let Some(octaves) = setting.strip_prefix("-octaves=") {
+            self.arpeggiator.octaves = octaves.parse().ok()
+                .filter(|n| (1..=4).contains(n))
+                .ok_or_else(|| invalid("octaves (1 to 4)"))?;
+        } else if let Some(rate) = setting.strip_prefix("-rate=") {
+            self.arpeggiator.set_rate_beats(parse_beats(rate).ok_or_else(|| invalid("rate"))?);
+        } else if let Some(gate) = setting.strip_prefix("-gate=") {
+            self.arpeggiator.gate = Articulation::parse(gate).ok_or_else(|| invalid("gate (1 to 100)"))?.gate();
+        } else {
+            return Err(Self::error(line, column, format!("unknown setting 'arp{}'", setting)));
+        }
+        self.writer.set_arpeggiator(self.arpeggiating.then(|| self.arpeggiator.clone()));
+        Ok(())
+    }
+
+    fn token(&mut self, word: &str, line: usize, column: usize) -> Result<(), ParseError> {
+        if let Some(velocity) = velocity_for(word) {
+            self.velocity = velocity;
+            return Ok(());
+        }
+        if let Some(articulation) = Articulation::parse(word).filter(|_| word.chars().all(char::is_alphabetic)) {
+            self.articulation = articulation;
+            return Ok(());
+        }
+        if let Some(gate) = word.strip_prefix("gate=") {
+            self.articulation = Articulation::parse(gate)
+                .ok_or_else(|| Self::error(line, column, format!("invalid gate '{}', use 1 to 100", gate)))?;
+            return Ok(());
+        }
+        if let Some(setting) = word.strip_prefix("arp") {
+            return self.arp_setting(setting, line, column);
+        }
+        if let Some(bpm) = word.strip_prefix("tempo=") {
+            let bpm = bpm.parse::<f64>().ok()
+                .filter(|bpm| *bpm > 0.0 && bpm.is_finite())
+                .ok_or_else(|| Self::error(line, column, format!("invalid tempo '{}'", bpm)))?;
+            let tick = self.writer.tick();
+            self.writer.timing_mut().tempo.set_tempo(tick, bpm);
+            return Ok(());
+        }
+        if let Some(signature) = word.strip_prefix("time=") {
+            self.writer.timing_mut().time_signature = TimeSignature::parse(signature)
+                .ok_or_else(|| Self::error(line, column, format!("invalid time signature '{}'", signature)))?;
+            return Ok(());
+        }
+        if let Some(amount) = word.strip_prefix("swing=") {
+            self.writer.timing_mut().swing = amount.parse::<f64>().ok()
+                .filter(|amount| (0.5..=0.75).contains(amount))
+                .map(Swing::new)
+                .ok_or_else(|| Self::error(line, column, format!("invalid swing '{}', use 0.5 to 0.75", amount)))?;
+            return Ok(());
+        }
+
+        // Note or rest, then an optional duration and marks
+        let (name, suffix) = match word.find(':') {
+            Some(split) => word.split_at(split),
+            None => {
+                let (name, _) = split_marks(word).map_err(|e| Self::error(line, column, e))?;
+                word.split_at(name.len())
+            }
+        };
+        let pitches = if name.eq_ignore_ascii_case("r") || name.eq_ignore_ascii_case("rest") {
+            Vec::new()
+        } else {
+            vec![self.tuning.freq(name)
+                .map_err(|e| Self::error(line, column, format!("bad note '{}': {}", name, e)))?]
+        };
+        let suffix = self.suffix(suffix, line, column + name.chars().count())?;
+        self.push_step(&pitches, suffix, line, column)
+    }
+
+    fn parse(mut self) -> Result<Score, ParseError> {
+        loop {
+            self.scanner.skip_blank();
+            let (line, column) = (self.scanner.line, self.scanner.column);
+            match self.scanner.peek() {
+                None => break,
+                Some('[') => self.chord(line, column)?,
+                Some(']') => return Err(Self::error(line, column, "unexpected ']'")),
+                Some(_) => {
+                    let word = self.scanner.word();
+                    self.token(&word, line, column)?;
+                }
+            }
+        }
+        let origin = self.tie_origin;
+        self.writer.finish(self.tuning).map_err(|e| {
+            let (line, column) = origin.unwrap_or((1, 1));
+            Self::error(line, column, e.to_string())
+        })
+    }
+}
+
+/// Parse notation text into a score, pitched by `tuning`
+pub fn parse(text: &str, tuning: &Tuning) -> Result<Score, ParseError> {
+    Parser {
+        scanner: Scanner::new(text),
+        tuning,
+        ticks: PPQ as u64,
+        velocity: 1.0,
+        articulation: Articulation::Normal,
+        arpeggiator: Arpeggiator::default(),
+        arpeggiating: false,
+        tie_origin: None,
+        writer: ScoreWriter::new(Timing::default()),
+    }
+    .parse()
+}
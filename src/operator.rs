@@ -0,0 +1,73 @@
+// src/operator.rs - Operator frequency modes and keyboard scaling
+
+/// Break point for keyboard scaling and key tracking (middle C)
+pub const MIDDLE_C: f32 = 261.63;
+
+/// Octaves between `freq` and middle C, negative below it
+pub fn key_octaves(freq: f32) -> f32 {
+    (freq / MIDDLE_C).log2()
+}
+
+/// How an operator derives its frequency from the played note
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperatorFreq {
+    /// Multiple of the note frequency: `coarse * (1.0 + fine)`
+    Ratio { coarse: f32, fine: f32 },
+    /// Constant frequency in Hz, independent of the key played
+    Fixed(f32),
+}
+
+/// Frequency and keyboard scaling settings for one operator
+#[derive(Clone, Debug)]
+pub struct Operator {
+    pub freq: OperatorFreq,
+    pub detune: f32,          // Cents
+    pub level_scaling: f32,   // dB per octave away from middle C (negative = quieter above)
+    pub rate_scaling: f32,    // Envelope speed-up per octave above middle C (0.0 = none)
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self::ratio(1.0)
+    }
+}
+
+impl Operator {
+    pub fn ratio(coarse: f32) -> Self {
+        Self {
+            freq: OperatorFreq::Ratio { coarse, fine: 0.0 },
+            detune: 0.0,
+            level_scaling: 0.0,
+            rate_scaling: 0.0,
+        }
+    }
+
+    pub fn fixed(hz: f32) -> Self {
+        Self {
+            freq: OperatorFreq::Fixed(hz),
+            ..Self::ratio(1.0)
+        }
+    }
+
+    /// Operator frequency for a note at `note_freq`, with `pitch_ratio`
+    /// (bend, vibrato) applied to keyboard-following operators only
+    pub fn frequency(&self, note_freq: f32, pitch_ratio: f32) -> f32 {
+        let detune = 2.0_f32.powf(self.detune / 1200.0);
+        match self.freq {
+            OperatorFreq::Ratio { coarse, fine } => {
+                note_freq * coarse * (1.0 + fine) * pitch_ratio * detune
+            }
+            OperatorFreq::Fixed(hz) => hz * detune,
+        }
+    }
+
+    /// Gain multiplier from keyboard level scaling
+    pub fn level_gain(&self, note_freq: f32) -> f32 {
+        10.0_f32.powf(self.level_scaling * key_octaves(note_freq) / 20.0)
+    }
+
+    /// Envelope speed multiplier from keyboard rate scaling
+    pub fn rate_multiplier(&self, note_freq: f32) -> f32 {
+        2.0_f32.powf(self.rate_scaling * key_octaves(note_freq))
+    }
+}
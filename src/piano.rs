@@ -0,0 +1,215 @@
+// src/piano.rs - Computer-keyboard piano: QWERTY rows play the running engine
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use ratatui::crossterm::{execute, terminal};
+
+use fm_synth::engine::Engine;
+use fm_synth::keyboard::KeyboardState;
+use fm_synth::pitch::midi_name;
+use fm_synth::tuning::Tuning;
+
+use crate::{open_stream, CLI};
+
+/// Without key-release events, a key counts as let go once it has not been
+/// pressed or auto-repeated for this many seconds
+const HOLD_WITHOUT_RELEASE: f64 = 0.6;
+
+/// How often the piano checks for keys when none arrive
+const POLL: Duration = Duration::from_millis(20);
+
+/// Keyboard map printed when the piano starts
+const LAYOUT: &str = concat!(
+    "   2 3   5 6 7   9 0          s d   g h j   l ;\n",
+    "  q w e r t y u i o p        z x c v b n m , . /",
+);
+
+/// Note keys, octave and velocity keys, sending notes to a running engine
+pub struct LiveKeys {
+    pub keyboard: KeyboardState,
+    pub releases: bool,   // Whether the terminal reports key releases
+    started: Instant,
+    pub tuning: Tuning,
+    engine: Arc<Mutex<Engine>>,
+}
+
+impl LiveKeys {
+    pub fn new(engine: Arc<Mutex<Engine>>, tuning: Tuning) -> Self {
+        Self { keyboard: KeyboardState::new(), releases: false, started: Instant::now(), tuning, engine }
+    }
+
+    /// Ask the terminal to report key releases, as terminals with the kitty
+    /// keyboard protocol can; raw mode must already be on
+    pub fn request_releases(&mut self) {
+        self.releases = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(io::stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)).is_ok();
+    }
+
+    /// Undo `request_releases`
+    pub fn restore(&mut self) {
+        if self.releases {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    fn note_on(&self, note: i32) {
+        if let Some(freq) = self.tuning.midi_freq(note as f32) {
+            self.engine.lock().unwrap().note_on(freq, self.keyboard.velocity);
+        }
+    }
+
+    fn note_off(&self, note: i32) {
+        if let Some(freq) = self.tuning.midi_freq(note as f32) {
+            self.engine.lock().unwrap().note_off(freq);
+        }
+    }
+
+    /// Play, release, or change octave (`-` `=`) or velocity (`[` `]`);
+    /// false for keys that do none of these
+    pub fn key(&mut self, key: &KeyEvent) -> bool {
+        let KeyCode::Char(c) = key.code else {
+            return false;
+        };
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return false;
+        }
+        if key.kind == KeyEventKind::Release {
+            if let Some(note) = self.keyboard.release(c) {
+                self.note_off(note);
+            }
+            return true;
+        }
+        match c {
+            '-' => self.keyboard.shift_octave(-1),
+            '=' => self.keyboard.shift_octave(1),
+            '[' => self.keyboard.change_velocity(-1),
+            ']' => self.keyboard.change_velocity(1),
+            c => {
+                let now = self.now();
+                match self.keyboard.press(c, now) {
+                    Some(note) => self.note_on(note),
+                    None => return self.keyboard.note(c).is_some(),
+                }
+            }
+        }
+        true
+    }
+
+    /// Let go of keys the terminal will never report releasing
+    pub fn expire(&mut self) {
+        if self.releases {
+            return;
+        }
+        let now = self.now();
+        for note in self.keyboard.expire(now, HOLD_WITHOUT_RELEASE) {
+            self.note_off(note);
+        }
+    }
+
+    pub fn silence(&mut self) {
+        for note in self.keyboard.release_all() {
+            self.note_off(note);
+        }
+        self.engine.lock().unwrap().synth_mut().all_notes_off();
+    }
+}
+
+/// One-line summary of the preset, range, velocity and held notes
+fn status(name: &str, keys: &LiveKeys) -> String {
+    let (low, high) = keys.keyboard.range();
+    let held: Vec<String> = keys.keyboard.held_notes().map(midi_name).collect();
+    format!("{}  octave {:+} ({}-{})  velocity {:.0}%  {}",
+        name, keys.keyboard.octave, midi_name(low), midi_name(high),
+        keys.keyboard.velocity * 100.0, held.join(" "))
+}
+
+fn play(cli: &CLI, keys: &mut LiveKeys, mut current: usize) -> io::Result<()> {
+    let mut out = io::stdout();
+    // Raw mode needs explicit carriage returns
+    write!(out, "{}\r\n\r\n", LAYOUT.replace('\n', "\r\n"))?;
+    write!(out, "-/= or Left/Right octave, [/] or Up/Down velocity, PgUp/PgDn preset, Space silence, Esc to leave\r\n")?;
+    if !keys.releases {
+        write!(out, "This terminal does not report key releases, so notes end shortly after keys stop repeating\r\n")?;
+    }
+
+    let mut shown = String::new();
+    loop {
+        let line = status(&cli.presets[current].name, keys);
+        if line != shown {
+            write!(out, "\r\x1b[2K{}", line)?;
+            out.flush()?;
+            shown = line;
+        }
+
+        if event::poll(POLL)? {
+            let Event::Key(key) = event::read()? else { continue };
+            if keys.key(&key) || key.kind == KeyEventKind::Release {
+                continue;
+            }
+            match key.code {
+                KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                KeyCode::Left => keys.keyboard.shift_octave(-1),
+                KeyCode::Right => keys.keyboard.shift_octave(1),
+                KeyCode::Down => keys.keyboard.change_velocity(-1),
+                KeyCode::Up => keys.keyboard.change_velocity(1),
+                KeyCode::Char(' ') => keys.silence(),
+                KeyCode::PageUp | KeyCode::PageDown => {
+                    let by = if key.code == KeyCode::PageUp { -1 } else { 1 };
+                    current = (current as isize + by).rem_euclid(cli.presets.len() as isize) as usize;
+                    keys.silence();
+                    let params = cli.with_session_effects(cli.presets[current].params.clone());
+                    keys.engine.lock().unwrap().set_params(params);
+                }
+                _ => {}
+            }
+        }
+        keys.expire();
+    }
+    write!(out, "\r\n")?;
+    Ok(())
+}
+
+/// Play a preset from the computer keyboard until Esc
+pub fn run(cli: &mut CLI, preset: Option<&str>) -> anyhow::Result<Result<(), String>> {
+    if !io::stdin().is_terminal() {
+        return Ok(Err("Piano mode needs an interactive terminal".to_string()));
+    }
+    let preset = preset.map_or_else(|| cli.default_preset(), String::from);
+    let current = match cli.find_preset_index(&preset) {
+        Ok(index) => index,
+        Err(e) => return Ok(Err(crate::lookup_message(e, "presets"))),
+    };
+
+    let params = cli.with_session_effects(cli.presets[current].params.clone());
+    let master = cli.master.clone();
+    let live = open_stream(cli.output(), |sample_rate| {
+        let mut engine = Engine::new(sample_rate, params);
+        engine.set_master(master);
+        engine
+    })?;
+    let engine = Arc::clone(&live.engine);
+    let mut keys = LiveKeys::new(engine, cli.tuning.clone());
+
+    terminal::enable_raw_mode()?;
+    keys.request_releases();
+    let result = play(cli, &mut keys, current);
+    keys.restore();
+    terminal::disable_raw_mode()?;
+    result?;
+
+    // Let the last notes ring out before the stream closes
+    keys.silence();
+    std::thread::sleep(cli.config.post_roll());
+    Ok(Ok(()))
+}
@@ -0,0 +1,116 @@
+// src/pitch.rs - Note names and MIDI note numbers
+//
+// Accepted spellings:
+//
+//   C4  c4  C-1       letter and octave -11 to 19 (C4 is middle C, MIDI 60)
+//   F#3  Bb5  Fx2     sharp `#`, flat `b`, double sharp `x`
+//   C##4  Dbb4        doubled accidentals; `♯` and `♭` also work
+//   A4+25c  E3-13.7c  cents offset
+//   60  69-10c        MIDI note number, with optional cents
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Octaves a note name may have: MIDI's -1 to 9 and ten more either side,
+/// which keeps every key well inside `i32`
+pub const OCTAVES: RangeInclusive<i32> = -11..=19;
+
+/// Why a pitch could not be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum PitchError {
+    Empty,
+    UnknownLetter(char),
+    MissingOctave(String),
+    InvalidOctave(String),
+    InvalidCents(String),
+    MidiOutOfRange(i32),
+    Unmapped(i32),
+}
+
+impl fmt::Display for PitchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PitchError::Empty => write!(f, "empty note name"),
+            PitchError::UnknownLetter(c) => write!(f, "'{}' is not a note letter (A-G)", c),
+            PitchError::MissingOctave(note) => write!(f, "'{}' needs an octave, e.g. {}4", note, note),
+            PitchError::InvalidOctave(text) => write!(f, "invalid octave '{}', expected {} to {}",
+                text, OCTAVES.start(), OCTAVES.end()),
+            PitchError::InvalidCents(text) => write!(f, "invalid cents offset '{}', expected e.g. +25c", text),
+            PitchError::MidiOutOfRange(n) => write!(f, "MIDI note {} is outside 0-127", n),
+            PitchError::Unmapped(n) => write!(f, "MIDI note {} is not mapped by the current tuning", n),
+        }
+    }
+}
+
+impl std::error::Error for PitchError {}
+
+/// Split a trailing `+25c` / `-13.7c` offset off a pitch
+fn split_cents(text: &str) -> Result<(&str, f32), PitchError> {
+    let Some(body) = text.strip_suffix(['c', 'C']).filter(|b| b.ends_with(|c: char| c.is_ascii_digit())) else {
+        return Ok((text, 0.0));
+    };
+    // The sign of the offset is the last '+' or '-' that has something before it
+    let sign = body.rfind(['+', '-']).filter(|&i| i > 0)
+        .ok_or_else(|| PitchError::InvalidCents(text.to_string()))?;
+    let cents = body[sign..].parse::<f32>().ok()
+        .filter(|c| c.is_finite())
+        .ok_or_else(|| PitchError::InvalidCents(text[sign..].to_string()))?;
+    Ok((&body[..sign], cents))
+}
+
+/// Fractional MIDI note number for a note name or MIDI number
+pub fn parse_midi(text: &str) -> Result<f32, PitchError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(PitchError::Empty);
+    }
+    let (name, cents) = split_cents(text)?;
+
+    if name.chars().all(|c| c.is_ascii_digit()) {
+        let number: i32 = name.parse().map_err(|_| PitchError::MidiOutOfRange(i32::MAX))?;
+        if number > 127 {
+            return Err(PitchError::MidiOutOfRange(number));
+        }
+        return Ok(number as f32 + cents / 100.0);
+    }
+
+    let mut chars = name.chars();
+    let letter = chars.next().ok_or(PitchError::Empty)?;
+    let semitone = match letter.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return Err(PitchError::UnknownLetter(letter)),
+    };
+
+    let mut rest = chars.as_str();
+    let mut accidental: i32 = 0;
+    while let Some(c) = rest.chars().next() {
+        accidental = accidental.saturating_add(match c {
+            '#' | '♯' => 1,
+            'b' | '♭' => -1,
+            'x' => 2,
+            _ => break,
+        });
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if rest.is_empty() {
+        return Err(PitchError::MissingOctave(name.to_string()));
+    }
+    let octave: i32 = rest.parse().ok()
+        .filter(|octave| OCTAVES.contains(octave))
+        .ok_or_else(|| PitchError::InvalidOctave(rest.to_string()))?;
+    let midi = (12 * (octave + 1) + semitone).saturating_add(accidental);
+    Ok(midi as f32 + cents / 100.0)
+}
+
+/// Name of a MIDI note with sharps, such as `C#4` for 61
+pub fn midi_name(midi: i32) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[midi.rem_euclid(12) as usize], midi.div_euclid(12) - 1)
+}
@@ -0,0 +1,626 @@
+// src/presets.rs - Preset library: built-in patches, categories and tags, and preset files
+//
+// A preset file starts from another preset and changes it, one statement
+// per line (`#` at the start of a word starts a comment, so `C# Lead` is
+// kept whole):
+//
+//   name Deep Bass                 required
+//   base Bass                      preset to start from (name or number), before any changes
+//   category bass                  see `Category`; the base's, or fx without one
+//   tags dark sub                  replace the base's tags
+//   author Jane Doe
+//   description Bass with more bite
+//   modulation_index 2.5           any parameter, by its path in `FMParams`
+//   filter.mode low_pass           enum values in snake_case, as in JSON output
+//   amp_envelope.release 0.2       times above 0 seconds, sustain 0 to 1
+//   modulator.ratio 2              or `.fine`, `.fixed <hz>`, `.detune`, ...
+//   route lfo1 pitch 0.1           add a modulation route; `route clear` removes them
+
+use std::fmt::Debug;
+
+use crate::effects::{ChorusParams, DelayParams, EffectsParams, ReverbParams};
+use crate::filter::{FilterMode, FilterParams};
+use crate::glide::{GlideCurve, GlideMode, PortamentoParams};
+use crate::modulation::{LfoShape, ModDestination, ModRoute, ModSource};
+use crate::notation::ParseError;
+use crate::operator::{Operator, OperatorFreq};
+use crate::script::strip_comment;
+use crate::synth::{EnvelopeParams, FMParams, UnisonParams, VoiceMode};
+
+/// Author of the built-in presets
+pub const BUILT_IN_AUTHOR: &str = "fm_synth";
+
+/// Kind of sound, for browsing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Category {
+    Keys,
+    Organ,
+    Bass,
+    Lead,
+    Pad,
+    Brass,
+    Woodwind,
+    Bells,
+    Percussion,
+    Fx,
+}
+
+impl Category {
+    pub const ALL: [Category; 10] = [
+        Category::Keys, Category::Organ, Category::Bass, Category::Lead, Category::Pad,
+        Category::Brass, Category::Woodwind, Category::Bells, Category::Percussion, Category::Fx,
+    ];
+
+    /// A category by name, ignoring case and a plural `s` (`pads`)
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.to_lowercase();
+        Self::ALL.into_iter().find(|category| {
+            let name = category.name();
+            text == name || text.strip_suffix('s') == Some(name) || text.strip_suffix("es") == Some(name)
+        })
+    }
+
+    /// A category by name as `parse` finds it, or an error listing the categories
+    pub fn lookup(text: &str) -> Result<Self, String> {
+        Self::parse(text).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(|category| category.name()).collect();
+            format!("Unknown category '{}'. Categories are: {}", text, names.join(", "))
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Keys => "keys",
+            Category::Organ => "organ",
+            Category::Bass => "bass",
+            Category::Lead => "lead",
+            Category::Pad => "pad",
+            Category::Brass => "brass",
+            Category::Woodwind => "woodwind",
+            Category::Bells => "bells",
+            Category::Percussion => "percussion",
+            Category::Fx => "fx",
+        }
+    }
+}
+
+/// A patch and what a browser shows about it
+#[derive(Clone, Debug)]
+pub struct Preset {
+    pub name: String,
+    pub category: Category,
+    pub tags: Vec<String>,
+    pub author: String,
+    pub description: String,
+    pub params: FMParams,
+}
+
+impl Preset {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Whether every word of `query` appears in the name, category, tags,
+    /// author or description, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let text = format!("{} {} {} {} {}", self.name, self.category.name(), self.tags.join(" "),
+            self.author, self.description).to_lowercase();
+        query.split_whitespace().all(|word| text.contains(&word.to_lowercase()))
+    }
+
+    /// Parse the preset file format described at the top of this file,
+    /// looking up base presets with `find_base`
+    pub fn parse(text: &str, find_base: impl Fn(&str) -> Option<Preset>) -> Result<Self, ParseError> {
+        let mut name = None;
+        let mut preset = Preset {
+            name: String::new(),
+            category: Category::Fx,
+            tags: Vec::new(),
+            author: String::new(),
+            description: String::new(),
+            params: FMParams::default(),
+        };
+        let mut changed = false;   // A base must come before any changes
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| ParseError { line, column: 1, message };
+            let content = strip_comment(raw).trim();
+            if content.is_empty() {
+                continue;
+            }
+            let (keyword, rest) = content.split_once(char::is_whitespace)
+                .map_or((content, ""), |(keyword, rest)| (keyword, rest.trim()));
+
+            match keyword {
+                "name" if !rest.is_empty() => name = Some(rest.to_string()),
+                "name" => return Err(error("usage: name <preset name>".to_string())),
+                "base" if changed => return Err(error("base must come once, before any changes".to_string())),
+                "base" => {
+                    let base = find_base(rest).ok_or_else(|| error(format!("unknown base preset '{}'", rest)))?;
+                    // The sound and its kind carry over, but not who made it or why
+                    preset = Preset { author: String::new(), description: String::new(), ..base };
+                }
+                "category" => {
+                    preset.category = Category::parse(rest).ok_or_else(|| {
+                        let names: Vec<&str> = Category::ALL.iter().map(|c| c.name()).collect();
+                        error(format!("unknown category '{}', use one of {}", rest, names.join(", ")))
+                    })?;
+                }
+                "tags" => preset.tags = rest.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_lowercase)
+                    .collect(),
+                "author" => preset.author = rest.to_string(),
+                "description" => preset.description = rest.to_string(),
+                "route" => add_route(&mut preset.params, rest).map_err(error)?,
+                path => set_param(&mut preset.params, path, rest).map_err(error)?,
+            }
+            changed |= keyword != "name";
+        }
+
+        preset.name = name.ok_or_else(|| ParseError { line: 1, column: 1, message: "missing 'name'".to_string() })?;
+        Ok(preset)
+    }
+}
+
+/// Add `preset` to the list, replacing one with the same name (ignoring
+/// case); returns its index
+pub fn add_preset(presets: &mut Vec<Preset>, preset: Preset) -> usize {
+    match presets.iter().position(|p| p.name.eq_ignore_ascii_case(&preset.name)) {
+        Some(index) => {
+            presets[index] = preset;
+            index
+        }
+        None => {
+            presets.push(preset);
+            presets.len() - 1
+        }
+    }
+}
+
+/// Indices of the presets matching every word of `query`
+pub fn search(presets: &[Preset], query: &str) -> Vec<usize> {
+    (0..presets.len()).filter(|&i| presets[i].matches(query)).collect()
+}
+
+/// snake_case name of a fieldless enum variant (`LowPass` is `low_pass`)
+pub fn variant_name(value: &impl Debug) -> String {
+    let mut name = String::new();
+    for (i, c) in format!("{:?}", value).chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+/// The variant of `options` named `text` in snake_case
+fn variant<T: Debug + Copy>(text: &str, options: &[T]) -> Result<T, String> {
+    let wanted = text.to_lowercase().replace('-', "_");
+    options.iter().copied().find(|option| variant_name(option) == wanted).ok_or_else(|| {
+        let names: Vec<String> = options.iter().map(variant_name).collect();
+        format!("invalid value '{}', use one of {}", text, names.join(", "))
+    })
+}
+
+fn number(text: &str) -> Result<f32, String> {
+    text.parse::<f32>().ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| format!("invalid number '{}'", text))
+}
+
+fn switch(text: &str) -> Result<bool, String> {
+    match text {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(format!("expected 'on' or 'off', got '{}'", text)),
+    }
+}
+
+const FILTER_MODES: [FilterMode; 5] =
+    [FilterMode::Off, FilterMode::LowPass, FilterMode::HighPass, FilterMode::BandPass, FilterMode::Notch];
+const LFO_SHAPES: [LfoShape; 4] = [LfoShape::Sine, LfoShape::Triangle, LfoShape::Saw, LfoShape::Square];
+const MOD_SOURCES: [ModSource; 8] = [
+    ModSource::AmpEnvelope, ModSource::ModEnvelope, ModSource::Lfo1, ModSource::Lfo2,
+    ModSource::Velocity, ModSource::KeyTrack, ModSource::ModWheel, ModSource::Aftertouch,
+];
+const MOD_DESTINATIONS: [ModDestination; 6] = [
+    ModDestination::Pitch, ModDestination::ModulationIndex, ModDestination::CarrierLevel,
+    ModDestination::ModulatorLevel, ModDestination::Pan, ModDestination::FilterCutoff,
+];
+
+fn set_operator(op: &mut Operator, field: &str, value: &str) -> Result<(), String> {
+    match field {
+        "ratio" => {
+            let fine = match op.freq {
+                OperatorFreq::Ratio { fine, .. } => fine,
+                OperatorFreq::Fixed(_) => 0.0,
+            };
+            op.freq = OperatorFreq::Ratio { coarse: number(value)?, fine };
+        }
+        "fine" => match &mut op.freq {
+            OperatorFreq::Ratio { fine, .. } => *fine = number(value)?,
+            OperatorFreq::Fixed(_) => return Err("'fine' needs a ratio operator".to_string()),
+        },
+        "fixed" => op.freq = OperatorFreq::Fixed(number(value)?),
+        "detune" => op.detune = number(value)?,
+        "level_scaling" => op.level_scaling = number(value)?,
+        "rate_scaling" => op.rate_scaling = number(value)?,
+        _ => return Err(format!("unknown operator setting '{}'", field)),
+    }
+    Ok(())
+}
+
+fn set_envelope(env: &mut EnvelopeParams, field: &str, value: &str) -> Result<(), String> {
+    let amount = number(value)?;
+    let time = || (amount > 0.0).then_some(amount)
+        .ok_or_else(|| format!("{} must be above 0 seconds, got '{}'", field, value));
+    match field {
+        "attack" => env.attack = time()?,
+        "decay" => env.decay = time()?,
+        "sustain" if (0.0..=1.0).contains(&amount) => env.sustain = amount,
+        "sustain" => return Err(format!("sustain must be 0 to 1, got '{}'", value)),
+        "release" => env.release = time()?,
+        _ => return Err(format!("unknown envelope setting '{}'", field)),
+    }
+    Ok(())
+}
+
+/// Set one parameter by its path, such as `filter.cutoff`
+pub fn set_param(params: &mut FMParams, path: &str, value: &str) -> Result<(), String> {
+    let unknown = || Err(format!("unknown setting '{}'", path));
+    match path.split('.').collect::<Vec<_>>().as_slice() {
+        ["modulation_index"] => params.modulation_index = number(value)?,
+        ["amplitude"] => params.amplitude = number(value)?,
+        ["velocity_sensitivity"] => params.velocity_sensitivity = number(value)?,
+        ["pitch_bend_range"] => params.pitch_bend_range = number(value)?,
+        ["voice_mode"] => params.voice_mode = variant(value, &[VoiceMode::Poly, VoiceMode::Mono, VoiceMode::Legato])?,
+        ["carrier", field] => set_operator(&mut params.carrier, field, value)?,
+        ["modulator", field] => set_operator(&mut params.modulator, field, value)?,
+        ["amp_envelope", field] => set_envelope(&mut params.amp_envelope, field, value)?,
+        ["mod_envelope", field] => set_envelope(&mut params.mod_envelope, field, value)?,
+        [lfo @ ("lfo1" | "lfo2"), field] => {
+            let lfo = if *lfo == "lfo1" { &mut params.lfo1 } else { &mut params.lfo2 };
+            match *field {
+                "rate" => lfo.rate = number(value)?,
+                "shape" => lfo.shape = variant(value, &LFO_SHAPES)?,
+                _ => return unknown(),
+            }
+        }
+        ["portamento", "time"] => params.portamento.time = number(value)?,
+        ["portamento", "mode"] => {
+            params.portamento.mode = variant(value, &[GlideMode::ConstantTime, GlideMode::ConstantRate])?;
+        }
+        ["portamento", "curve"] => {
+            params.portamento.curve = variant(value, &[GlideCurve::Linear, GlideCurve::Exponential])?;
+        }
+        ["unison", "voices"] => {
+            params.unison.voices = value.parse().ok()
+                .filter(|voices| (1..=8).contains(voices))
+                .ok_or_else(|| format!("invalid unison voices '{}', use 1 to 8", value))?;
+        }
+        ["unison", "detune"] => params.unison.detune = number(value)?,
+        ["unison", "spread"] => params.unison.spread = number(value)?,
+        ["unison", "random_phase"] => params.unison.random_phase = switch(value)?,
+        ["filter", "mode"] => params.filter.mode = variant(value, &FILTER_MODES)?,
+        ["filter", "cutoff"] => params.filter.cutoff = number(value)?,
+        ["filter", "resonance"] => params.filter.resonance = number(value)?,
+        ["filter", "key_track"] => params.filter.key_track = number(value)?,
+        ["filter", "env_amount"] => params.filter.env_amount = number(value)?,
+        ["effects", "chorus", "mix"] => params.effects.chorus.mix = number(value)?,
+        ["effects", "chorus", "rate"] => params.effects.chorus.rate = number(value)?,
+        ["effects", "chorus", "depth"] => params.effects.chorus.depth = number(value)?,
+        ["effects", "delay", "mix"] => params.effects.delay.mix = number(value)?,
+        ["effects", "delay", "beats"] => params.effects.delay.beats = number(value)?,
+        ["effects", "delay", "feedback"] => params.effects.delay.feedback = number(value)?,
+        ["effects", "delay", "ping_pong"] => params.effects.delay.ping_pong = switch(value)?,
+        ["effects", "reverb", "mix"] => params.effects.reverb.mix = number(value)?,
+        ["effects", "reverb", "room_size"] => params.effects.reverb.room_size = number(value)?,
+        ["effects", "reverb", "damping"] => params.effects.reverb.damping = number(value)?,
+        ["effects", "reverb", "width"] => params.effects.reverb.width = number(value)?,
+        _ => return unknown(),
+    }
+    Ok(())
+}
+
+/// `<source> <destination> <depth>` adds a modulation route; `clear` removes them all
+fn add_route(params: &mut FMParams, text: &str) -> Result<(), String> {
+    match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["clear"] => params.mod_routes.clear(),
+        [source, destination, depth] => params.mod_routes.push(ModRoute::new(
+            variant(source, &MOD_SOURCES)?,
+            variant(destination, &MOD_DESTINATIONS)?,
+            number(depth)?,
+        )),
+        _ => return Err("usage: route <source> <destination> <depth> | route clear".to_string()),
+    }
+    Ok(())
+}
+
+fn builtin(name: &str, category: Category, tags: &[&str], description: &str, params: FMParams) -> Preset {
+    Preset {
+        name: name.to_string(),
+        category,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        author: BUILT_IN_AUTHOR.to_string(),
+        description: description.to_string(),
+        params,
+    }
+}
+
+/// Built-in presets
+pub fn get_presets() -> Vec<Preset> {
+    vec![
+        builtin("Bell", Category::Bells, &["bright", "ringing", "reverb"],
+            "Classic FM bell with a long, reverberant ring",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator::ratio(1.0),
+                modulation_index: 7.0,
+                amplitude: 0.3,
+                mod_routes: vec![
+                    // Low notes ring with more partials than high ones
+                    ModRoute::new(ModSource::KeyTrack, ModDestination::ModulationIndex, -1.5),
+                ],
+                effects: EffectsParams {
+                    reverb: ReverbParams {
+                        mix: 0.3,
+                        room_size: 0.8,
+                        ..ReverbParams::default()
+                    },
+                    ..EffectsParams::default()
+                },
+                ..FMParams::default()
+            }),
+        builtin("Bass", Category::Bass, &["mono", "glide", "round"],
+            "Bass two octaves below the written note that glides between overlapping notes",
+            FMParams {
+                carrier: Operator::ratio(0.25),
+                modulator: Operator::ratio(0.25),
+                modulation_index: 1.5,
+                amplitude: 0.5,
+                voice_mode: VoiceMode::Legato,
+                portamento: PortamentoParams {
+                    time: 0.05,
+                    mode: GlideMode::ConstantRate,
+                    curve: GlideCurve::Linear,
+                },
+                ..FMParams::default()
+            }),
+        builtin("Electric Piano", Category::Keys, &["tine", "chorus", "velocity"],
+            "Tine electric piano that brightens when played harder",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator {
+                    // Softer tines towards the top of the keyboard
+                    level_scaling: -3.0,
+                    ..Operator::ratio(2.0)
+                },
+                modulation_index: 3.0,
+                amplitude: 0.4,
+                mod_routes: vec![
+                    // Harder hits sound brighter
+                    ModRoute::new(ModSource::Velocity, ModDestination::ModulationIndex, 2.0),
+                ],
+                effects: EffectsParams {
+                    chorus: ChorusParams {
+                        mix: 0.35,
+                        ..ChorusParams::default()
+                    },
+                    ..EffectsParams::default()
+                },
+                ..FMParams::default()
+            }),
+        builtin("Brass", Category::Brass, &["swell", "filter", "aftertouch"],
+            "Brass section swell with a filter that opens on each note",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator::ratio(1.0),
+                modulation_index: 2.5,
+                amplitude: 0.4,
+                mod_envelope: EnvelopeParams {
+                    attack: 0.08,
+                    decay: 0.3,
+                    sustain: 0.6,
+                    release: 0.3,
+                },
+                mod_routes: vec![
+                    ModRoute::new(ModSource::ModEnvelope, ModDestination::ModulationIndex, 2.0),
+                    ModRoute::new(ModSource::Aftertouch, ModDestination::ModulationIndex, 1.5),
+                ],
+                // Classic brass swell: the filter opens with the modulation envelope
+                filter: FilterParams {
+                    mode: FilterMode::LowPass,
+                    cutoff: 900.0,
+                    resonance: 0.2,
+                    key_track: 0.5,
+                    env_amount: 2.5,
+                },
+                ..FMParams::default()
+            }),
+        builtin("Organ", Category::Organ, &["plain", "sustained"],
+            "Two-operator organ that holds while keys are down",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator::ratio(2.0),
+                modulation_index: 1.0,
+                amplitude: 0.4,
+                ..FMParams::default()
+            }),
+        builtin("Synth Lead", Category::Lead, &["mono", "glide", "unison", "delay"],
+            "Detuned mono lead with glide and a dotted-eighth delay",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator {
+                    detune: 7.0,
+                    ..Operator::ratio(3.0)
+                },
+                modulation_index: 4.0,
+                amplitude: 0.35,
+                mod_routes: vec![
+                    ModRoute::new(ModSource::ModWheel, ModDestination::ModulationIndex, 3.0),
+                ],
+                voice_mode: VoiceMode::Mono,
+                portamento: PortamentoParams {
+                    time: 0.08,
+                    mode: GlideMode::ConstantTime,
+                    curve: GlideCurve::Exponential,
+                },
+                pitch_bend_range: 12.0,
+                unison: UnisonParams {
+                    voices: 3,
+                    detune: 12.0,
+                    spread: 0.4,
+                    random_phase: true,
+                },
+                effects: EffectsParams {
+                    delay: DelayParams {
+                        mix: 0.25,
+                        beats: 0.75,
+                        feedback: 0.4,
+                        ..DelayParams::default()
+                    },
+                    ..EffectsParams::default()
+                },
+                ..FMParams::default()
+            }),
+        builtin("Marimba", Category::Percussion, &["mallet", "wooden", "short"],
+            "Mallet tone whose higher bars die away faster",
+            FMParams {
+                carrier: Operator {
+                    // Higher bars die away faster
+                    rate_scaling: 0.5,
+                    ..Operator::ratio(1.0)
+                },
+                modulator: Operator::ratio(1.0),
+                modulation_index: 3.5,
+                amplitude: 0.4,
+                ..FMParams::default()
+            }),
+        builtin("Strings", Category::Pad, &["warm", "wide", "vibrato", "unison"],
+            "Wide string ensemble with vibrato and a slow stereo drift",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator::ratio(0.5),
+                modulation_index: 0.8,
+                amplitude: 0.3,
+                mod_routes: vec![
+                    // Gentle vibrato plus a slow stereo drift
+                    ModRoute::new(ModSource::Lfo1, ModDestination::Pitch, 0.12),
+                    ModRoute::new(ModSource::Lfo2, ModDestination::Pan, 0.4),
+                ],
+                unison: UnisonParams {
+                    voices: 4,
+                    detune: 18.0,
+                    spread: 0.7,
+                    random_phase: true,
+                },
+                filter: FilterParams {
+                    mode: FilterMode::LowPass,
+                    cutoff: 2500.0,
+                    resonance: 0.1,
+                    key_track: 0.5,
+                    env_amount: 0.0,
+                },
+                effects: EffectsParams {
+                    chorus: ChorusParams {
+                        mix: 0.3,
+                        depth: 4.0,
+                        ..ChorusParams::default()
+                    },
+                    reverb: ReverbParams {
+                        mix: 0.35,
+                        ..ReverbParams::default()
+                    },
+                    ..EffectsParams::default()
+                },
+                ..FMParams::default()
+            }),
+        builtin("Flute", Category::Woodwind, &["soft", "vibrato", "breathy"],
+            "Soft flute with gentle pitch and level vibrato",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator::ratio(1.0),
+                modulation_index: 0.5,
+                amplitude: 0.25,
+                mod_routes: vec![
+                    ModRoute::new(ModSource::Lfo1, ModDestination::Pitch, 0.08),
+                    ModRoute::new(ModSource::Lfo1, ModDestination::CarrierLevel, 0.1),
+                ],
+                filter: FilterParams {
+                    mode: FilterMode::LowPass,
+                    cutoff: 3000.0,
+                    resonance: 0.0,
+                    key_track: 1.0,
+                    env_amount: 0.0,
+                },
+                effects: EffectsParams {
+                    reverb: ReverbParams {
+                        mix: 0.25,
+                        room_size: 0.6,
+                        ..ReverbParams::default()
+                    },
+                    ..EffectsParams::default()
+                },
+                ..FMParams::default()
+            }),
+        builtin("Metallic", Category::Bells, &["inharmonic", "clangy", "bright"],
+            "Inharmonic clang from an off-ratio modulator",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator {
+                    freq: OperatorFreq::Ratio { coarse: 1.0, fine: 0.289 },
+                    ..Operator::default()
+                },
+                modulation_index: 9.0,
+                amplitude: 0.3,
+                // Tame the aliasing of the high index
+                filter: FilterParams {
+                    mode: FilterMode::LowPass,
+                    cutoff: 5000.0,
+                    resonance: 0.3,
+                    key_track: 1.0,
+                    env_amount: 0.0,
+                },
+                ..FMParams::default()
+            }),
+        builtin("Glockenspiel", Category::Bells, &["bright", "short", "high"],
+            "Bright, short glockenspiel bars",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator::ratio(4.0),
+                modulation_index: 2.5,
+                amplitude: 0.3,
+                effects: EffectsParams {
+                    reverb: ReverbParams {
+                        mix: 0.25,
+                        ..ReverbParams::default()
+                    },
+                    ..EffectsParams::default()
+                },
+                ..FMParams::default()
+            }),
+        builtin("Wood Block", Category::Percussion, &["knock", "short", "fixed-pitch"],
+            "Wooden knock with the same resonance on every key",
+            FMParams {
+                carrier: Operator::ratio(1.0),
+                modulator: Operator {
+                    // Fixed resonance gives the same knock on every key
+                    rate_scaling: 0.3,
+                    ..Operator::fixed(300.0)
+                },
+                modulation_index: 12.0,
+                amplitude: 0.4,
+                filter: FilterParams {
+                    mode: FilterMode::BandPass,
+                    cutoff: 1200.0,
+                    resonance: 0.4,
+                    key_track: 0.0,
+                    env_amount: 1.0,
+                },
+                ..FMParams::default()
+            }),
+    ]
+}
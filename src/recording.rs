@@ -0,0 +1,185 @@
+// src/recording.rs - Live playback captured as audio and notes on one timeline
+
+use crate::midi::{freq_to_key, midi_velocity, MidiFile, MidiNote, MidiTrack};
+use crate::sequence::EventKind;
+use crate::timing::{TempoChange, TimeSignature, PPQ};
+use crate::tuning::Tuning;
+
+/// Tempo of the grid recorded notes are written on; performances have no
+/// tempo of their own, so beats are simply half-seconds
+pub const RECORDING_BPM: f64 = 120.0;
+
+/// Longest recording kept; the audio is held in memory until it is saved,
+/// about 20 MB a minute at 44.1 kHz
+pub const MAX_RECORDING_SECONDS: f64 = 15.0 * 60.0;
+
+/// A finished note, in frames from the start of the recording
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordedNote {
+    pub track: usize,
+    pub start: u64,
+    pub length: u64,
+    pub freq: f32,
+    pub velocity: f32,
+}
+
+/// A note still sounding
+#[derive(Clone, Copy, Debug)]
+struct OpenNote {
+    track: usize,
+    start: u64,
+    freq: f32,
+    velocity: f32,
+}
+
+/// Output frames and the notes that made them, filled in as playback runs
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    sample_rate: Option<f32>,   // Set by the first audio recorded
+    frames: Vec<(f32, f32)>,
+    notes: Vec<RecordedNote>,
+    open: Vec<OpenNote>,
+    warned: bool,               // Whether filling up has been reported
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sample_rate(&self) -> Option<f32> {
+        self.sample_rate
+    }
+
+    /// Frames recorded so far; also the position of the next one
+    pub fn len(&self) -> u64 {
+        self.frames.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.sample_rate.map_or(0.0, |rate| self.len() as f64 / rate as f64)
+    }
+
+    pub fn frames(&self) -> &[(f32, f32)] {
+        &self.frames
+    }
+
+    /// Finished notes, in the order they ended
+    pub fn notes(&self) -> &[RecordedNote] {
+        &self.notes
+    }
+
+    /// Whether audio at `sample_rate` can join the recording; the first
+    /// audio sets the rate and later audio must match it
+    pub fn accepts(&mut self, sample_rate: f32) -> bool {
+        *self.sample_rate.get_or_insert(sample_rate) == sample_rate
+    }
+
+    /// Frames the recording can hold, `MAX_RECORDING_SECONDS` at its sample rate
+    pub fn capacity(&self) -> u64 {
+        self.sample_rate.map_or(u64::MAX, |rate| (MAX_RECORDING_SECONDS * rate as f64) as u64)
+    }
+
+    /// Whether the recording has reached `MAX_RECORDING_SECONDS`; later audio and notes are dropped
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// True the first time this is called on a full recording, so the limit is reported once
+    pub fn take_full_warning(&mut self) -> bool {
+        let warn = self.is_full() && !self.warned;
+        self.warned |= warn;
+        warn
+    }
+
+    /// Extend with silence up to `frame`, to keep a gap between performances
+    pub fn pad_to(&mut self, frame: u64) {
+        let frame = frame.min(self.capacity());
+        if frame > self.len() {
+            self.frames.resize(frame as usize, (0.0, 0.0));
+        }
+    }
+
+    pub fn push_frame(&mut self, frame: (f32, f32)) {
+        if !self.is_full() {
+            self.frames.push(frame);
+        }
+    }
+
+    /// Note event at `frame`; a note-on for a key already sounding on the
+    /// same track ends the earlier note there
+    pub fn event(&mut self, frame: u64, track: usize, kind: &EventKind) {
+        // Notes past the end of a full recording are dropped, and those
+        // sounding when it filled end there
+        let frame = frame.min(self.capacity());
+        match *kind {
+            EventKind::NoteOn { .. } if frame >= self.capacity() => {}
+            EventKind::NoteOn { freq, velocity } => {
+                self.close(frame, |note| note.track == track && note.freq == freq);
+                self.open.push(OpenNote { track, start: frame, freq, velocity });
+            }
+            EventKind::NoteOff { freq } => self.close(frame, |note| note.track == track && note.freq == freq),
+        }
+    }
+
+    /// End every sounding note at the current end of the recording
+    pub fn release_all(&mut self) {
+        let end = self.len();
+        self.close(end, |_| true);
+    }
+
+    fn close(&mut self, frame: u64, matches: impl Fn(&OpenNote) -> bool) {
+        let (closed, open): (Vec<OpenNote>, Vec<OpenNote>) = self.open.iter().partition(|note| matches(note));
+        self.open = open;
+        self.notes.extend(closed.into_iter().map(|note| RecordedNote {
+            track: note.track,
+            start: note.start,
+            length: frame.saturating_sub(note.start),
+            freq: note.freq,
+            velocity: note.velocity,
+        }));
+    }
+
+    /// The notes as a MIDI file at `RECORDING_BPM`, one track per engine
+    /// track that played, with keys as `tuning` plays them; notes still
+    /// sounding end where the recording does
+    pub fn to_midi(&self, name: &str, tuning: &Tuning) -> MidiFile {
+        let end = self.len();
+        let sounding = self.open.iter().map(|note| RecordedNote {
+            track: note.track,
+            start: note.start,
+            length: end.saturating_sub(note.start),
+            freq: note.freq,
+            velocity: note.velocity,
+        });
+        let mut notes: Vec<RecordedNote> = self.notes.iter().copied().chain(sounding).collect();
+        notes.sort_by_key(|note| note.start);
+
+        let rate = self.sample_rate.unwrap_or(44100.0) as f64;
+        let to_tick = |frame: u64| (frame as f64 / rate * RECORDING_BPM / 60.0 * PPQ as f64).round() as u64;
+
+        let track_count = notes.iter().map(|note| note.track + 1).max().unwrap_or(1);
+        let mut tracks: Vec<MidiTrack> = (0..track_count)
+            .map(|i| MidiTrack { name: format!("Track {}", i + 1), channel: (i % 16) as u8, notes: Vec::new() })
+            .collect();
+        for note in notes {
+            let tick = to_tick(note.start);
+            tracks[note.track].notes.push(MidiNote {
+                tick,
+                ticks: to_tick(note.start + note.length).saturating_sub(tick).max(1),
+                key: freq_to_key(note.freq, tuning),
+                velocity: midi_velocity(note.velocity),
+            });
+        }
+        MidiFile {
+            name: name.to_string(),
+            tempo: vec![TempoChange { tick: 0, bpm: RECORDING_BPM }],
+            time_signature: TimeSignature::default(),
+            tracks,
+        }
+    }
+}
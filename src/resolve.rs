@@ -0,0 +1,199 @@
+// src/resolve.rs - Preset and melody lookup by number or name, and quoting in commands
+
+use std::fmt;
+
+/// Why a name did not pick out exactly one entry
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResolveError {
+    NotFound { kind: &'static str, query: String, suggestions: Vec<String> },
+    Ambiguous { kind: &'static str, query: String, candidates: Vec<String> },
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::NotFound { kind, query, suggestions } => {
+                write!(f, "{} '{}' not found", kind, query)?;
+                match suggestions.as_slice() {
+                    [] => Ok(()),
+                    [one] => write!(f, ". Did you mean '{}'?", one),
+                    many => write!(f, ". Did you mean one of: {}?", many.join(", ")),
+                }
+            }
+            ResolveError::Ambiguous { kind, query, candidates } => {
+                write!(f, "{} '{}' is ambiguous; it matches {}", kind, query, candidates.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Lowercase with `-` and `_` as spaces and runs of spaces collapsed
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .replace(['-', '_'], " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Text without one pair of surrounding double quotes
+pub fn unquote(text: &str) -> &str {
+    text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text)
+}
+
+/// Split a command line on whitespace, keeping `"quoted text"` together as
+/// one word (quotes included, so quoted notation stays recognisable)
+pub fn split_command(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unclosed quote".to_string());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Edit distance counting insertions, deletions, substitutions and swaps of
+/// neighbouring characters
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Closest distance from a query to a name, its words, or its start
+fn closeness(query: &str, name: &str) -> usize {
+    let start: String = name.chars().take(query.chars().count()).collect();
+    name.split(' ')
+        .chain([name, start.as_str()])
+        .map(|part| distance(query, part))
+        .min()
+        .unwrap_or(usize::MAX)
+}
+
+/// Typos allowed for a query of this length
+fn tolerance(query: &str) -> usize {
+    (query.chars().count() + 2) / 4
+}
+
+/// Find the one entry `query` names: a 1-based number, the exact name, the
+/// start of the name or of one of its words, or failing those a close
+/// misspelling. Case, dashes and underscores are ignored.
+pub fn resolve<'a>(
+    kind: &'static str,
+    query: &str,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<usize, ResolveError> {
+    find(kind, query, names, true)
+}
+
+/// Like `resolve`, but without accepting or suggesting misspellings, for
+/// arguments that may be something other than a name
+pub fn resolve_exact<'a>(
+    kind: &'static str,
+    query: &str,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<usize, ResolveError> {
+    find(kind, query, names, false)
+}
+
+fn find<'a>(
+    kind: &'static str,
+    query: &str,
+    names: impl IntoIterator<Item = &'a str>,
+    fuzzy: bool,
+) -> Result<usize, ResolveError> {
+    let query = unquote(query).trim();
+    let names: Vec<&str> = names.into_iter().collect();
+    if let Ok(number) = query.parse::<usize>() {
+        if (1..=names.len()).contains(&number) {
+            return Ok(number - 1);
+        }
+    }
+
+    let wanted = normalize(query);
+    let normalized: Vec<String> = names.iter().map(|name| normalize(name)).collect();
+    let pick = |matches: Vec<usize>| -> Option<Result<usize, ResolveError>> {
+        match matches.as_slice() {
+            [] => None,
+            [one] => Some(Ok(*one)),
+            many => Some(Err(ResolveError::Ambiguous {
+                kind,
+                query: query.to_string(),
+                candidates: many.iter().map(|&i| names[i].to_string()).collect(),
+            })),
+        }
+    };
+    let matching = |test: &dyn Fn(&str) -> bool| -> Vec<usize> {
+        (0..names.len()).filter(|&i| test(&normalized[i])).collect()
+    };
+
+    let not_found = || ResolveError::NotFound { kind, query: query.to_string(), suggestions: Vec::new() };
+    if wanted.is_empty() || query.chars().all(|c| c.is_ascii_digit()) {
+        return Err(not_found());
+    }
+    if let Some(result) = pick(matching(&|name| name == wanted)) {
+        return result;
+    }
+    if let Some(result) = pick(matching(&|name| name.starts_with(&wanted))) {
+        return result;
+    }
+    let word_start = |name: &str| name.split(' ').any(|word| word.starts_with(&wanted))
+        || name.contains(&format!(" {}", wanted));
+    if let Some(result) = pick(matching(&word_start)) {
+        return result;
+    }
+    if !fuzzy {
+        return Err(not_found());
+    }
+    if let Some(result) = pick(matching(&|name| closeness(&wanted, name) <= tolerance(&wanted))) {
+        return result;
+    }
+
+    // Nothing close enough to accept; suggest the nearest few
+    let mut near: Vec<(usize, usize)> = (0..names.len())
+        .map(|i| (closeness(&wanted, &normalized[i]), i))
+        .filter(|&(d, _)| d <= tolerance(&wanted) + 1)
+        .collect();
+    near.sort();
+    Err(ResolveError::NotFound {
+        kind,
+        query: query.to_string(),
+        suggestions: near.iter().take(3).map(|&(_, i)| names[i].to_string()).collect(),
+    })
+}
@@ -0,0 +1,115 @@
+// src/scope.rs - Recent output samples for oscilloscope, spectrum and level displays
+
+use std::f32::consts::PI;
+
+/// Ring buffer of the most recent mono output samples
+#[derive(Clone, Debug)]
+pub struct Scope {
+    samples: Vec<f32>,
+    pos: usize,   // Where the next sample goes, so also the oldest sample
+}
+
+impl Scope {
+    pub fn new(len: usize) -> Self {
+        Self { samples: vec![0.0; len.max(1)], pos: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.samples[self.pos] = sample;
+        self.pos = (self.pos + 1) % self.samples.len();
+    }
+
+    /// The last `count` samples, oldest first
+    pub fn latest(&self, count: usize) -> Vec<f32> {
+        let count = count.min(self.samples.len());
+        let start = (self.pos + self.samples.len() - count) % self.samples.len();
+        (0..count).map(|i| self.samples[(start + i) % self.samples.len()]).collect()
+    }
+
+    /// `count` samples starting at a rising zero crossing, so a periodic
+    /// wave holds still from one display frame to the next
+    pub fn triggered(&self, count: usize) -> Vec<f32> {
+        let recent = self.latest(count * 2);
+        let search = recent.len().saturating_sub(count);
+        let start = (1..search)
+            .find(|&i| recent[i - 1] < 0.0 && recent[i] >= 0.0)
+            .unwrap_or(search);
+        recent[start..].iter().take(count).copied().collect()
+    }
+
+    /// Peak and RMS of the last `count` samples
+    pub fn levels(&self, count: usize) -> (f32, f32) {
+        let recent = self.latest(count);
+        if recent.is_empty() {
+            return (0.0, 0.0);
+        }
+        let peak = recent.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        let rms = (recent.iter().map(|s| s * s).sum::<f32>() / recent.len() as f32).sqrt();
+        (peak, rms)
+    }
+}
+
+/// In-place radix-2 FFT of (re, im) pairs; the length must be a power of two
+fn fft(data: &mut [(f32, f32)]) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = data[start + k + len / 2];
+                let odd = (re * cos - im * sin, re * sin + im * cos);
+                let even = data[start + k];
+                data[start + k] = (even.0 + odd.0, even.1 + odd.1);
+                data[start + k + len / 2] = (even.0 - odd.0, even.1 - odd.1);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Magnitude spectrum as (frequency in Hz, level in dBFS) from DC to Nyquist,
+/// Hann windowed; only the first power-of-two run of `samples` is used
+pub fn spectrum(samples: &[f32], sample_rate: f32) -> Vec<(f32, f32)> {
+    if samples.len() < 2 {
+        return Vec::new();
+    }
+    let n = 1 << (usize::BITS - 1 - samples.len().leading_zeros());
+    let mut data: Vec<(f32, f32)> = samples[..n].iter()
+        .enumerate()
+        .map(|(i, &s)| (s * (0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()), 0.0))
+        .collect();
+    fft(&mut data);
+
+    // The Hann window halves the amplitude of a sine; double it back
+    let scale = 4.0 / n as f32;
+    data[..n / 2].iter()
+        .enumerate()
+        .map(|(i, (re, im))| {
+            let magnitude = (re * re + im * im).sqrt() * scale;
+            (i as f32 * sample_rate / n as f32, 20.0 * magnitude.max(1e-6).log10())
+        })
+        .collect()
+}
@@ -0,0 +1,322 @@
+// src/score.rs - Notes placed in musical time, converted to timed events for playback
+
+use crate::arpeggiator::Arpeggiator;
+use crate::melodies::Melody;
+use crate::pitch::PitchError;
+use crate::rng::Rng;
+use crate::sequence::{EventKind, Sequence};
+use crate::timing::Timing;
+use crate::tuning::Tuning;
+
+/// How much of its slot a note sounds before release
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Articulation {
+    #[default]
+    Normal,     // 80%
+    Legato,     // Full length, running straight into the next note
+    Staccato,   // 50%
+    Tenuto,     // 95%
+    Gate(f64),  // Custom share of the slot, 0.01 to 1.0
+}
+
+impl Articulation {
+    pub fn gate(&self) -> f64 {
+        match self {
+            Articulation::Normal => 0.8,
+            Articulation::Legato => 1.0,
+            Articulation::Staccato => 0.5,
+            Articulation::Tenuto => 0.95,
+            Articulation::Gate(gate) => gate.clamp(0.01, 1.0),
+        }
+    }
+
+    /// Sounding length of a note filling `ticks`
+    pub fn sounding(&self, ticks: u64) -> u64 {
+        ((ticks as f64 * self.gate()).round() as u64).clamp(1, ticks.max(1))
+    }
+
+    /// Parse `normal`, `legato`, `staccato`, `tenuto` or a gate percentage such as `70%`
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "normal" => Some(Articulation::Normal),
+            "legato" => Some(Articulation::Legato),
+            "staccato" => Some(Articulation::Staccato),
+            "tenuto" => Some(Articulation::Tenuto),
+            text => {
+                let percent = text.strip_suffix('%').unwrap_or(text).parse::<f64>().ok()?;
+                (percent > 0.0 && percent <= 100.0).then_some(Articulation::Gate(percent / 100.0))
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Articulation::Normal => "normal".to_string(),
+            Articulation::Legato => "legato".to_string(),
+            Articulation::Staccato => "staccato".to_string(),
+            Articulation::Tenuto => "tenuto".to_string(),
+            Articulation::Gate(gate) => format!("{:.0}%", gate * 100.0),
+        }
+    }
+}
+
+/// Marks at the end of a note: articulation and tie
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Marks {
+    pub articulation: Option<Articulation>,
+    pub tie: bool,
+}
+
+/// Split trailing marks off a note: `.` staccato, `_` tenuto, `^` legato,
+/// `@70` a 70% gate, then `~` to tie into the next note
+pub fn split_marks(text: &str) -> Result<(&str, Marks), String> {
+    let (text, tie) = match text.strip_suffix('~') {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+    let mut marks = Marks { articulation: None, tie };
+    let text = if let Some(rest) = text.strip_suffix('.') {
+        marks.articulation = Some(Articulation::Staccato);
+        rest
+    } else if let Some(rest) = text.strip_suffix('_') {
+        marks.articulation = Some(Articulation::Tenuto);
+        rest
+    } else if let Some(rest) = text.strip_suffix('^') {
+        marks.articulation = Some(Articulation::Legato);
+        rest
+    } else if let Some((rest, gate)) = text.rsplit_once('@') {
+        let gate = gate.parse::<f64>().ok()
+            .filter(|gate| *gate > 0.0 && *gate <= 100.0)
+            .ok_or_else(|| format!("invalid gate '@{}', use 1 to 100", gate))?;
+        marks.articulation = Some(Articulation::Gate(gate / 100.0));
+        rest
+    } else {
+        text
+    };
+    Ok((text, marks))
+}
+
+/// A note starting at `tick` and sounding for `ticks`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoreNote {
+    pub tick: u64,
+    pub ticks: u64,
+    pub freq: f32,
+    pub velocity: f32,
+}
+
+/// Notes on the tick grid with the timing that places them in seconds
+#[derive(Clone, Debug, Default)]
+pub struct Score {
+    pub notes: Vec<ScoreNote>,
+    pub length: u64,   // Ticks, including trailing rests
+    pub timing: Timing,
+}
+
+/// Why tied notes could not be joined
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TieError {
+    PitchChanged,   // A tie did not continue into the same pitch
+    Unfinished,     // The score ended inside a tie
+}
+
+impl std::fmt::Display for TieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TieError::PitchChanged => write!(f, "tie must continue into the same pitch"),
+            TieError::Unfinished => write!(f, "tie at the end of the melody"),
+        }
+    }
+}
+
+impl std::error::Error for TieError {}
+
+/// Appends notes, rests and chords one slot at a time, joining ties
+pub struct ScoreWriter {
+    score: Score,
+    tied: Vec<usize>,                           // Notes held over into the next slot
+    arpeggiator: Option<Arpeggiator>,           // For chords that follow
+    chords: Vec<(Vec<usize>, Arpeggiator)>,     // Chords to arpeggiate once their ties are known
+}
+
+impl ScoreWriter {
+    pub fn new(timing: Timing) -> Self {
+        Self { score: Score::new(timing), tied: Vec::new(), arpeggiator: None, chords: Vec::new() }
+    }
+
+    /// Arpeggiate the chords that follow, or play them as chords with None
+    pub fn set_arpeggiator(&mut self, arpeggiator: Option<Arpeggiator>) {
+        self.arpeggiator = arpeggiator;
+    }
+
+    /// Start of the next slot
+    pub fn tick(&self) -> u64 {
+        self.score.length
+    }
+
+    pub fn timing_mut(&mut self) -> &mut Timing {
+        &mut self.score.timing
+    }
+
+    /// Add a slot of `ticks` holding `pitches` (empty for a rest)
+    pub fn step(
+        &mut self,
+        pitches: &[f32],
+        ticks: u64,
+        velocity: f32,
+        articulation: Articulation,
+        tie: bool,
+    ) -> Result<(), TieError> {
+        let notes = &mut self.score.notes;
+        if self.tied.iter().any(|&note| !pitches.contains(&notes[note].freq)) {
+            return Err(TieError::PitchChanged);
+        }
+
+        let tick = self.score.length;
+        let end = if tie { tick + ticks } else { tick + articulation.sounding(ticks) };
+        let mut held = Vec::new();
+        let mut chord = Vec::new();
+        for &freq in pitches {
+            let note = match self.tied.iter().find(|&&note| notes[note].freq == freq) {
+                Some(&note) => note,
+                None => {
+                    notes.push(ScoreNote { tick, ticks: 0, freq, velocity });
+                    notes.len() - 1
+                }
+            };
+            notes[note].ticks = end - notes[note].tick;
+            if tie {
+                held.push(note);
+            }
+            chord.push(note);
+        }
+        // A chord continued by a tie is already queued from its first slot
+        if let Some(arpeggiator) = &self.arpeggiator {
+            if chord.len() > 1 && self.tied.is_empty() {
+                self.chords.push((chord, arpeggiator.clone()));
+            }
+        }
+        self.tied = held;
+        self.score.length += ticks;
+        Ok(())
+    }
+
+    /// The finished score, with chords arpeggiated through `tuning`
+    pub fn finish(mut self, tuning: &Tuning) -> Result<Score, TieError> {
+        if !self.tied.is_empty() {
+            return Err(TieError::Unfinished);
+        }
+        let chords = std::mem::take(&mut self.chords);
+        self.score.replace_chords(chords, tuning);
+        Ok(self.score)
+    }
+}
+
+/// Problem building a score from a built-in melody
+#[derive(Clone, Debug, PartialEq)]
+pub enum MelodyError {
+    Pitch(PitchError),
+    Marks(String),
+    Tie(TieError),
+}
+
+impl std::fmt::Display for MelodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MelodyError::Pitch(e) => write!(f, "{}", e),
+            MelodyError::Marks(e) => write!(f, "{}", e),
+            MelodyError::Tie(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MelodyError {}
+
+impl Score {
+    pub fn new(timing: Timing) -> Self {
+        Self { notes: Vec::new(), length: 0, timing }
+    }
+
+    /// Build a score from a built-in melody; `r` or `rest` is a rest, notes
+    /// separated by spaces are a chord, and notes may carry marks such as
+    /// `C4.` or `E4~`
+    pub fn from_melody(melody: &Melody, tuning: &Tuning) -> Result<Self, MelodyError> {
+        let mut writer = ScoreWriter::new(melody.timing.clone());
+        writer.set_arpeggiator(melody.arpeggiator.clone());
+        for &(note, ticks) in &melody.notes {
+            let (name, marks) = split_marks(note).map_err(MelodyError::Marks)?;
+            let pitches = if name.eq_ignore_ascii_case("r") || name.eq_ignore_ascii_case("rest") {
+                Vec::new()
+            } else {
+                name.split_whitespace()
+                    .map(|name| tuning.freq(name))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(MelodyError::Pitch)?
+            };
+            let articulation = marks.articulation.unwrap_or(melody.articulation);
+            writer.step(&pitches, ticks as u64, 1.0, articulation, marks.tie)
+                .map_err(MelodyError::Tie)?;
+        }
+        writer.finish(tuning).map_err(MelodyError::Tie)
+    }
+
+    /// Replace each chord's notes (indices into `notes`) with its arpeggio
+    fn replace_chords(&mut self, chords: Vec<(Vec<usize>, Arpeggiator)>, tuning: &Tuning) {
+        if chords.is_empty() {
+            return;
+        }
+        let mut rng = Rng::default();
+        let mut arpeggios = Vec::new();
+        let mut replaced = vec![false; self.notes.len()];
+        for (chord, arpeggiator) in chords {
+            let notes: Vec<ScoreNote> = chord.iter().map(|&i| self.notes[i].clone()).collect();
+            arpeggios.extend(arpeggiator.expand(&notes, tuning, &mut rng));
+            for i in chord {
+                replaced[i] = true;
+            }
+        }
+        let mut kept = replaced.iter();
+        self.notes.retain(|_| !kept.next().copied().unwrap_or(false));
+        self.notes.extend(arpeggios);
+        self.notes.sort_by_key(|note| note.tick);
+    }
+
+    /// Arpeggiate every chord (notes starting on the same tick), stepping
+    /// octaves through `tuning`
+    pub fn arpeggiate(&mut self, arpeggiator: &Arpeggiator, tuning: &Tuning) {
+        let mut chords: Vec<(Vec<usize>, Arpeggiator)> = Vec::new();
+        let mut start = 0;
+        for i in 1..=self.notes.len() {
+            if i == self.notes.len() || self.notes[i].tick != self.notes[start].tick {
+                if i - start > 1 {
+                    chords.push(((start..i).collect(), arpeggiator.clone()));
+                }
+                start = i;
+            }
+        }
+        self.replace_chords(chords, tuning);
+    }
+
+    /// Play at `bpm`, scaling any later tempo changes along with it
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.timing.tempo = self.timing.tempo.scaled_to(bpm);
+    }
+
+    /// Length in seconds
+    pub fn duration(&self) -> f64 {
+        self.timing.seconds(self.length as f64)
+    }
+
+    /// Note events in seconds, with tempo changes and swing applied
+    pub fn to_sequence(&self) -> Sequence {
+        let mut sequence = Sequence::new();
+        for note in &self.notes {
+            let (freq, velocity) = (note.freq, note.velocity);
+            sequence.push(self.timing.seconds(note.tick as f64), EventKind::NoteOn { freq, velocity });
+            sequence.push(self.timing.seconds((note.tick + note.ticks) as f64), EventKind::NoteOff { freq });
+        }
+        sequence.length = self.duration();
+        sequence.sort();
+        sequence
+    }
+}
@@ -0,0 +1,195 @@
+// src/script.rs - Command scripts for batch runs: comments, variables and repeat blocks
+//
+// One REPL command per line, plus:
+//
+//   # text                 comment, on its own line or after a command; `#`
+//                          starts one only at the start of a word outside
+//                          quotes, so notes such as `C#4` are kept
+//   set name value         define a variable; `$name` or `${name}` expands it
+//                          in later lines (`$$` is a literal `$`)
+//   repeat 4 [var]         repeat the lines up to the matching `end`, setting
+//   ...                    `var` to 1, 2, ... on each pass
+//   end
+//
+// `wait` and the other commands are left to whoever runs the script.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Most commands a script may expand to, so runaway repeats fail early
+pub const MAX_COMMANDS: usize = 100_000;
+
+/// Script problem with a 1-based line number
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+fn error(line: usize, message: impl Into<String>) -> ScriptError {
+    ScriptError { line, message: message.into() }
+}
+
+/// A command with variables expanded, and the line it came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptCommand {
+    pub line: usize,
+    pub text: String,
+}
+
+enum Node {
+    Command { line: usize, text: String },
+    Set { line: usize, name: String, value: String },
+    Repeat { line: usize, count: String, var: Option<String>, body: Vec<Node> },
+}
+
+fn is_name(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The line without its trailing comment, if any. `#` starts a comment
+/// only at the start of a word outside quotes, so `C#4` is kept.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut word_start = true;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if word_start && !quoted => return &line[..i],
+            _ => {}
+        }
+        word_start = c.is_whitespace();
+    }
+    line
+}
+
+/// Build the block structure, checking `set`, `repeat` and `end` lines
+fn parse_nodes(text: &str) -> Result<Vec<Node>, ScriptError> {
+    // Open blocks as (line, count, var, body), innermost last
+    let mut stack: Vec<(usize, String, Option<String>, Vec<Node>)> = Vec::new();
+    let mut top = Vec::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        // Comments go before variables expand, so values may hold `#`
+        let content = strip_comment(raw).trim();
+        if content.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = content.split_whitespace().collect();
+        let node = match words[0] {
+            "set" => {
+                let name = words.get(1).filter(|name| is_name(name))
+                    .ok_or_else(|| error(line, "usage: set <name> <value>"))?;
+                let value = content[3..].trim_start()[name.len()..].trim().to_string();
+                Node::Set { line, name: name.to_string(), value }
+            }
+            "repeat" => {
+                let (count, var) = match words[1..] {
+                    [count] => (count, None),
+                    [count, var] if is_name(var) => (count, Some(var.to_string())),
+                    _ => return Err(error(line, "usage: repeat <count> [variable]")),
+                };
+                stack.push((line, count.to_string(), var, Vec::new()));
+                continue;
+            }
+            "end" if words.len() == 1 => {
+                let (line, count, var, body) = stack.pop()
+                    .ok_or_else(|| error(line, "'end' without 'repeat'"))?;
+                Node::Repeat { line, count, var, body }
+            }
+            _ => Node::Command { line, text: content.to_string() },
+        };
+        match stack.last_mut() {
+            Some((_, _, _, body)) => body.push(node),
+            None => top.push(node),
+        }
+    }
+
+    match stack.last() {
+        Some((line, ..)) => Err(error(*line, "'repeat' without 'end'")),
+        None => Ok(top),
+    }
+}
+
+/// Replace `$name` and `${name}` with variable values
+fn substitute(text: &str, vars: &HashMap<String, String>, line: usize) -> Result<String, ScriptError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}').ok_or_else(|| error(line, "unclosed '${'"))?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            return Err(error(line, "'$' must be followed by a variable name (use '$$' for a literal '$')"));
+        }
+        let value = vars.get(name).ok_or_else(|| error(line, format!("undefined variable '{}'", name)))?;
+        out.push_str(value);
+        rest = remaining;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn run_nodes(
+    nodes: &[Node],
+    vars: &mut HashMap<String, String>,
+    out: &mut Vec<ScriptCommand>,
+) -> Result<(), ScriptError> {
+    for node in nodes {
+        match node {
+            Node::Command { line, text } => {
+                if out.len() >= MAX_COMMANDS {
+                    return Err(error(*line, format!("script expands to more than {} commands", MAX_COMMANDS)));
+                }
+                out.push(ScriptCommand { line: *line, text: substitute(text, vars, *line)? });
+            }
+            Node::Set { line, name, value } => {
+                let value = substitute(value, vars, *line)?;
+                vars.insert(name.clone(), value);
+            }
+            Node::Repeat { line, count, var, body } => {
+                let count_text = substitute(count, vars, *line)?;
+                let count: u32 = count_text.parse()
+                    .map_err(|_| error(*line, format!("invalid repeat count '{}'", count_text)))?;
+                for pass in 1..=count {
+                    if let Some(var) = var {
+                        vars.insert(var.clone(), pass.to_string());
+                    }
+                    run_nodes(body, vars, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expand a script into the commands it runs, in order
+pub fn expand(text: &str) -> Result<Vec<ScriptCommand>, ScriptError> {
+    let nodes = parse_nodes(text)?;
+    let mut commands = Vec::new();
+    run_nodes(&nodes, &mut HashMap::new(), &mut commands)?;
+    Ok(commands)
+}
@@ -0,0 +1,50 @@
+// src/sequence.rs - Timed note events shared by live playback and rendering
+
+/// What happens at an event's time
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    NoteOn { freq: f32, velocity: f32 },
+    NoteOff { freq: f32 },
+}
+
+/// An event at `time` seconds from the start of the sequence
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub time: f64,
+    pub kind: EventKind,
+}
+
+/// Ordered list of events with a total length
+#[derive(Clone, Debug, Default)]
+pub struct Sequence {
+    pub events: Vec<Event>,
+    pub length: f64,   // Seconds, including trailing rests
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, time: f64, kind: EventKind) {
+        self.events.push(Event { time, kind });
+    }
+
+    /// Sort events by time. At the same instant a note-off for a pitch that
+    /// restarts comes first, so repeated notes retrigger; other note-offs
+    /// come after the note-ons, so legato lines stay connected.
+    pub fn sort(&mut self) {
+        let starts: Vec<(f64, f32)> = self.events.iter()
+            .filter_map(|e| match e.kind {
+                EventKind::NoteOn { freq, .. } => Some((e.time, freq)),
+                EventKind::NoteOff { .. } => None,
+            })
+            .collect();
+        let rank = |e: &Event| match e.kind {
+            EventKind::NoteOff { freq } if starts.contains(&(e.time, freq)) => 0,
+            EventKind::NoteOn { .. } => 1,
+            EventKind::NoteOff { .. } => 2,
+        };
+        self.events.sort_by(|a, b| a.time.total_cmp(&b.time).then_with(|| rank(a).cmp(&rank(b))));
+    }
+}
@@ -0,0 +1,502 @@
+// src/sequencer.rs - Multi-track step sequencer with patterns, song chaining and loop mode
+//
+// Text format, one statement per line (`#` at the start of a word starts a
+// comment, so notes such as `C#4` are kept):
+//
+//   tempo 120              beats per minute
+//   swing 0.55             swing for pairs of steps (0.5 straight)
+//   steps 16               steps in each pattern that follows
+//   step 1/4               step length in beats (1/4 = sixteenth notes)
+//   track drums Wood Block add a track playing a preset (name or number)
+//   pattern verse          start a pattern; lanes follow as `<track> <steps>`
+//   drums C4 . . C4*0.6 | C4 . C4?50 .
+//   song verse*2 chorus    chain patterns, with optional repeats
+//   loop on                repeat the song or pattern until stopped
+//
+// A step is `.` (empty) or a note with optional `*velocity` (0-1),
+// `@gate` (percent of a step, above 100 to hold across steps) and
+// `?probability` (percent), e.g. `Eb2*0.8@50?75`. `|` is ignored.
+
+use crate::notation::ParseError;
+use crate::pitch::parse_midi;
+use crate::rng::Rng;
+use crate::script::strip_comment;
+use crate::synth::FMParams;
+use crate::timing::{Swing, Timing, PPQ};
+use crate::tuning::Tuning;
+
+pub const DEFAULT_STEPS: usize = 16;
+
+/// One step of a lane
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub note: f32,          // MIDI note number, tuned when triggered
+    pub velocity: f32,
+    pub gate: f32,          // Share of the step held; above 1.0 overlaps later steps
+    pub probability: f32,   // Chance the step plays each time round
+}
+
+impl Step {
+    pub fn new(note: f32) -> Self {
+        Self { note, velocity: 1.0, gate: 0.8, probability: 1.0 }
+    }
+
+    /// Parse `.` or `C4*0.8@50?75`
+    pub fn parse(text: &str) -> Result<Option<Self>, String> {
+        if text == "." || text == "-" {
+            return Ok(None);
+        }
+        let split = text.find(['*', '@', '?']).unwrap_or(text.len());
+        let (note, mut params) = text.split_at(split);
+        let mut step = Self::new(parse_midi(note).map_err(|e| format!("bad note '{}': {}", note, e))?);
+
+        while let Some(kind) = params.chars().next() {
+            let rest = &params[1..];
+            let end = rest.find(['*', '@', '?']).unwrap_or(rest.len());
+            let value: f32 = rest[..end].parse().ok()
+                .filter(|v: &f32| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| format!("invalid '{}{}' in step '{}'", kind, &rest[..end], text))?;
+            match kind {
+                '*' => step.velocity = value.min(1.0),
+                '@' => step.gate = (value / 100.0).clamp(0.01, 16.0),
+                _ => step.probability = (value / 100.0).min(1.0),
+            }
+            params = &rest[end..];
+        }
+        Ok(Some(step))
+    }
+}
+
+/// A voice of the sequencer with its own synth patch
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub name: String,
+    pub preset_name: String,
+    pub preset: FMParams,
+    pub mute: bool,
+    pub solo: bool,
+}
+
+/// Steps for every track, played together
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pub name: String,
+    pub steps: usize,
+    pub lanes: Vec<Vec<Option<Step>>>,   // Indexed by track, then step
+}
+
+impl Pattern {
+    pub fn new(name: &str, steps: usize) -> Self {
+        Self { name: name.to_string(), steps, lanes: Vec::new() }
+    }
+
+    pub fn step(&self, track: usize, step: usize) -> Option<&Step> {
+        self.lanes.get(track)?.get(step)?.as_ref()
+    }
+
+    pub fn set_step(&mut self, track: usize, step: usize, value: Option<Step>) {
+        if self.lanes.len() <= track {
+            self.lanes.resize(track + 1, Vec::new());
+        }
+        let lane = &mut self.lanes[track];
+        if lane.len() < self.steps {
+            lane.resize(self.steps, None);
+        }
+        if step < lane.len() {
+            lane[step] = value;
+        }
+    }
+}
+
+/// A pattern played a number of times in the song
+#[derive(Clone, Debug, PartialEq)]
+pub struct SongEntry {
+    pub pattern: usize,
+    pub repeats: u32,
+}
+
+/// What the sequencer plays when started
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayMode {
+    Pattern(usize),
+    Song,
+}
+
+/// A note the sequencer wants played
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trigger {
+    pub track: usize,
+    pub time: f64,     // Seconds on the engine clock
+    pub length: f64,   // Seconds until release
+    pub freq: f32,
+    pub velocity: f32,
+}
+
+/// Where playback is
+#[derive(Clone, Debug)]
+struct Position {
+    mode: PlayMode,
+    entry: usize,       // Song entry
+    repeat: u32,        // Repeat of the current entry
+    step: usize,        // Step within the pattern
+    count: u64,         // Steps played so far, for swing
+    passes: u32,        // Completed passes through the song or pattern
+    next_time: f64,     // When the step at `step` starts
+}
+
+#[derive(Clone)]
+pub struct Sequencer {
+    pub tracks: Vec<Track>,
+    pub patterns: Vec<Pattern>,
+    pub song: Vec<SongEntry>,
+    pub timing: Timing,
+    pub step_ticks: u64,        // Length of a step
+    pub looping: bool,
+    pub tuning: Tuning,
+    max_passes: Option<u32>,    // Stop a looping playback after this many passes
+    position: Option<Position>,
+    end_time: f64,              // When the most recent step ends
+    rng: Rng,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self {
+            tracks: Vec::new(),
+            patterns: Vec::new(),
+            song: Vec::new(),
+            timing: Timing::default(),
+            step_ticks: PPQ as u64 / 4,
+            looping: false,
+            tuning: Tuning::default(),
+            max_passes: None,
+            position: None,
+            end_time: 0.0,
+            rng: Rng::default(),
+        }
+    }
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.timing.tempo.initial()
+    }
+
+    /// Change tempo, including during playback
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.timing.tempo = self.timing.tempo.scaled_to(bpm);
+    }
+
+    pub fn add_track(&mut self, name: &str, preset_name: &str, preset: FMParams) -> usize {
+        self.tracks.push(Track {
+            name: name.to_string(),
+            preset_name: preset_name.to_string(),
+            preset,
+            mute: false,
+            solo: false,
+        });
+        self.tracks.len() - 1
+    }
+
+    /// Track by name (case insensitive) or 1-based number
+    pub fn find_track(&self, name: &str) -> Option<usize> {
+        find_named(self.tracks.iter().map(|t| t.name.as_str()), name)
+    }
+
+    /// Pattern by name (case insensitive) or 1-based number
+    pub fn find_pattern(&self, name: &str) -> Option<usize> {
+        find_named(self.patterns.iter().map(|p| p.name.as_str()), name)
+    }
+
+    /// False for muted tracks and for unsoloed tracks while any track is soloed
+    pub fn is_audible(&self, track: usize) -> bool {
+        let any_solo = self.tracks.iter().any(|t| t.solo);
+        self.tracks.get(track).is_some_and(|t| !t.mute && (t.solo || !any_solo))
+    }
+
+    /// Start at `time` seconds on the engine clock. A looping sequencer plays
+    /// `passes` times, or until stopped when None; otherwise once.
+    pub fn start(&mut self, mode: PlayMode, time: f64, passes: Option<u32>) {
+        self.max_passes = if self.looping { passes } else { Some(1) };
+        self.position = Some(Position {
+            mode,
+            entry: 0,
+            repeat: 0,
+            step: 0,
+            count: 0,
+            passes: 0,
+            next_time: time,
+        });
+    }
+
+    pub fn stop(&mut self) {
+        self.position = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.position.is_some()
+    }
+
+    /// Pattern currently playing
+    pub fn current_pattern(&self) -> Option<usize> {
+        let position = self.position.as_ref()?;
+        match position.mode {
+            PlayMode::Pattern(pattern) => Some(pattern),
+            PlayMode::Song => self.song.get(position.entry).map(|e| e.pattern),
+        }
+    }
+
+    /// Step currently playing within its pattern
+    pub fn current_step(&self) -> Option<usize> {
+        self.position.as_ref().map(|p| p.step)
+    }
+
+    /// Length in seconds of step number `count`, with swing
+    fn step_seconds(&self, count: u64) -> f64 {
+        let swing = Swing { unit: (self.step_ticks * 2) as u32, ..self.timing.swing };
+        let start = swing.apply((count * self.step_ticks) as f64);
+        let end = swing.apply(((count + 1) * self.step_ticks) as f64);
+        (end - start) / PPQ as f64 * 60.0 / self.tempo()
+    }
+
+    /// Collect the triggers of every step starting at or before `time`
+    pub fn poll(&mut self, time: f64, triggers: &mut Vec<Trigger>) {
+        while let Some(position) = self.position.clone() {
+            if position.next_time > time {
+                break;
+            }
+            let Some(pattern) = self.current_pattern().and_then(|p| self.patterns.get(p)) else {
+                self.position = None;
+                break;
+            };
+
+            let step_seconds = self.step_seconds(position.count);
+            for track in 0..self.tracks.len() {
+                let Some(step) = pattern.step(track, position.step) else { continue };
+                if !self.is_audible(track) || self.rng.next_f32() >= step.probability {
+                    continue;
+                }
+                let Some(freq) = self.tuning.midi_freq(step.note) else { continue };
+                triggers.push(Trigger {
+                    track,
+                    time: position.next_time,
+                    length: step_seconds * step.gate as f64,
+                    freq,
+                    velocity: step.velocity,
+                });
+            }
+
+            let steps = pattern.steps;
+            self.advance(steps, position.next_time + step_seconds);
+        }
+    }
+
+    /// Move to the next step, wrapping patterns, song entries and passes
+    fn advance(&mut self, steps: usize, next_time: f64) {
+        let song_len = self.song.len();
+        let song = self.song.clone();
+        let max_passes = self.max_passes;
+        self.end_time = next_time;
+        let Some(position) = self.position.as_mut() else { return };
+
+        position.next_time = next_time;
+        position.count += 1;
+        position.step += 1;
+        if position.step < steps {
+            return;
+        }
+        position.step = 0;
+
+        let pass_done = match position.mode {
+            PlayMode::Pattern(_) => true,
+            PlayMode::Song => {
+                position.repeat += 1;
+                if position.repeat >= song[position.entry].repeats.max(1) {
+                    position.repeat = 0;
+                    position.entry += 1;
+                }
+                if position.entry >= song_len {
+                    position.entry = 0;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        if pass_done {
+            position.passes += 1;
+            if max_passes.is_some_and(|max| position.passes >= max) {
+                self.position = None;
+            }
+        }
+    }
+
+    /// Every trigger of a full playback from time 0, for schedulers that
+    /// cannot poll, with the total length in seconds. A looping sequencer
+    /// plays `passes` times.
+    pub fn schedule(&mut self, mode: PlayMode, passes: u32) -> (Vec<Trigger>, f64) {
+        self.start(mode, 0.0, Some(passes.max(1)));
+        let mut triggers = Vec::new();
+        while let Some(position) = &self.position {
+            let time = position.next_time;
+            self.poll(time, &mut triggers);
+        }
+        let length = triggers.iter().map(|t| t.time + t.length).fold(self.end_time, f64::max);
+        (triggers, length)
+    }
+
+    /// Parse the text format described at the top of this file, looking up
+    /// presets with `find_preset`
+    pub fn parse(
+        text: &str,
+        find_preset: impl Fn(&str) -> Option<(String, FMParams)>,
+    ) -> Result<Self, ParseError> {
+        let mut sequencer = Self::new();
+        let mut steps = DEFAULT_STEPS;
+        let mut pattern: Option<usize> = None;
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| ParseError { line, column: 1, message };
+            let content = strip_comment(raw).trim();
+            let Some((keyword, rest)) = split_word(content) else { continue };
+
+            match keyword {
+                "tempo" => {
+                    let bpm = rest.parse::<f64>().ok()
+                        .filter(|bpm| bpm.is_finite() && *bpm > 0.0)
+                        .ok_or_else(|| error(format!("invalid tempo '{}'", rest)))?;
+                    sequencer.set_tempo(bpm);
+                }
+                "swing" => {
+                    sequencer.timing.swing = rest.parse::<f64>().ok()
+                        .filter(|amount| (0.5..=0.75).contains(amount))
+                        .map(Swing::new)
+                        .ok_or_else(|| error(format!("invalid swing '{}', use 0.5 to 0.75", rest)))?;
+                }
+                "steps" => {
+                    steps = rest.parse().ok()
+                        .filter(|&n: &usize| (1..=256).contains(&n))
+                        .ok_or_else(|| error(format!("invalid step count '{}'", rest)))?;
+                }
+                "step" => {
+                    let (num, den) = rest.split_once('/').unwrap_or((rest, "1"));
+                    let beats = num.parse::<f64>().ok().zip(den.parse::<f64>().ok())
+                        .map(|(num, den)| num / den)
+                        .filter(|beats| beats.is_finite() && *beats > 0.0)
+                        .ok_or_else(|| error(format!("invalid step length '{}'", rest)))?;
+                    sequencer.step_ticks = ((beats * PPQ as f64).round() as u64).max(1);
+                }
+                "track" => {
+                    let (name, preset_name) = split_word(rest)
+                        .filter(|(_, preset)| !preset.is_empty())
+                        .ok_or_else(|| error("usage: track <name> <preset>".to_string()))?;
+                    if sequencer.find_track(name).is_some() {
+                        return Err(error(format!("track '{}' already exists", name)));
+                    }
+                    let (preset_name, preset) = find_preset(preset_name)
+                        .ok_or_else(|| error(format!("unknown preset '{}'", preset_name)))?;
+                    sequencer.add_track(name, &preset_name, preset);
+                }
+                "pattern" => {
+                    if rest.is_empty() || sequencer.find_pattern(rest).is_some() {
+                        return Err(error(format!("pattern needs a new name, got '{}'", rest)));
+                    }
+                    sequencer.patterns.push(Pattern::new(rest, steps));
+                    pattern = Some(sequencer.patterns.len() - 1);
+                }
+                "song" => {
+                    for word in rest.split_whitespace() {
+                        let (name, repeats) = match word.rsplit_once('*') {
+                            Some((name, count)) => (name, count.parse().ok()
+                                .filter(|&n: &u32| n > 0)
+                                .ok_or_else(|| error(format!("invalid repeat count in '{}'", word)))?),
+                            None => (word, 1),
+                        };
+                        let pattern = sequencer.find_pattern(name)
+                            .ok_or_else(|| error(format!("unknown pattern '{}'", name)))?;
+                        sequencer.song.push(SongEntry { pattern, repeats });
+                    }
+                }
+                "loop" => {
+                    sequencer.looping = match rest {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(error(format!("expected 'loop on' or 'loop off', got '{}'", rest))),
+                    };
+                }
+                track_name => {
+                    let track = sequencer.find_track(track_name)
+                        .ok_or_else(|| error(format!("unknown keyword or track '{}'", track_name)))?;
+                    let pattern = &mut sequencer.patterns[pattern
+                        .ok_or_else(|| error(format!("steps for '{}' before any pattern", track_name)))?];
+                    let tokens: Vec<&str> = rest.split_whitespace().filter(|t| *t != "|").collect();
+                    if tokens.len() > pattern.steps {
+                        return Err(error(format!("{} steps in a {}-step pattern", tokens.len(), pattern.steps)));
+                    }
+                    for (i, token) in tokens.iter().enumerate() {
+                        pattern.set_step(track, i, Step::parse(token).map_err(error)?);
+                    }
+                }
+            }
+        }
+
+        if sequencer.song.is_empty() {
+            sequencer.song = (0..sequencer.patterns.len())
+                .map(|pattern| SongEntry { pattern, repeats: 1 })
+                .collect();
+        }
+        Ok(sequencer)
+    }
+}
+
+/// First word and the trimmed remainder
+fn split_word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    })
+}
+
+fn find_named<'a>(names: impl Iterator<Item = &'a str>, name: &str) -> Option<usize> {
+    let names: Vec<&str> = names.collect();
+    if let Ok(num) = name.parse::<usize>() {
+        if num > 0 && num <= names.len() {
+            return Some(num - 1);
+        }
+    }
+    names.iter().position(|n| n.eq_ignore_ascii_case(name))
+}
+
+/// Built-in four-track demo song
+pub const DEMO_SONG: &str = "\
+# Four tracks over two patterns
+tempo 112
+swing 0.56
+steps 16
+track bass Bass
+track keys Electric Piano
+track lead Synth Lead
+track perc Wood Block
+
+pattern groove
+bass C4 . . C4  . . Eb4 .  C4 . . G3  . . Bb3@150 .
+keys . . . . Eb4@60 . . . . . . . G4@60 . . .
+perc C5 . C5*0.5 . C5 . C5*0.5 C5?50 C5 . C5*0.5 . C5 . C5*0.5 C5*0.7?60
+
+pattern lift
+bass Ab3 . . Ab3  . . C4 .  Bb3 . . Bb3  . . D4 .
+keys Eb4@300 . . . . . . . D4@300 . . . . . . .
+lead G4*0.9@180 . Bb4@90 . C5@350 . . . D5@180 . C5@90 . Bb4@350 . . .
+perc C5 . C5*0.5 . C5 . C5*0.5 . C5 . C5*0.5 . C5 C5*0.6 C5*0.7 C5*0.8
+
+song groove*2 lift groove
+";
@@ -0,0 +1,694 @@
+// src/synth.rs - FM oscillator, envelopes and the per-note synth voice
+
+use std::f32::consts::PI;
+
+use crate::effects::EffectsParams;
+use crate::filter::{FilterParams, StateVariableFilter};
+use crate::glide::{Glide, PortamentoParams};
+use crate::modulation::{self, Lfo, LfoParams, LfoShape, ModOutputs, ModRoute, ModSources};
+use crate::operator::{key_octaves, Operator};
+use crate::rng::Rng;
+
+/// Number of samples between modulation matrix evaluations
+pub const BLOCK_SIZE: usize = 32;
+
+/// Voices available to a polyphonic patch
+pub const MAX_VOICES: usize = 8;
+
+/// How a patch responds to overlapping notes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoiceMode {
+    Poly,    // Each note gets its own voice
+    Mono,    // One voice, envelopes retrigger on every note
+    Legato,  // One voice, envelopes only retrigger when no other key is held
+}
+
+/// Envelope times in seconds, sustain as a level
+#[derive(Clone, Debug)]
+pub struct EnvelopeParams {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for EnvelopeParams {
+    fn default() -> Self {
+        Self {
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.5,
+        }
+    }
+}
+
+/// Stacked, detuned copies of the oscillator within each voice
+#[derive(Clone, Debug)]
+pub struct UnisonParams {
+    pub voices: usize,       // 1 (off) to 8 stacked oscillators
+    pub detune: f32,         // Spread between the outermost copies in cents
+    pub spread: f32,         // Stereo width (0.0 - 1.0)
+    pub random_phase: bool,  // Start each copy at a random phase
+}
+
+impl Default for UnisonParams {
+    fn default() -> Self {
+        Self {
+            voices: 1,
+            detune: 0.0,
+            spread: 0.0,
+            random_phase: false,
+        }
+    }
+}
+
+impl UnisonParams {
+    pub fn count(&self) -> usize {
+        self.voices.clamp(1, 8)
+    }
+
+    /// Position of copy `i` across the stack, from -1.0 to 1.0
+    fn position(&self, i: usize) -> f32 {
+        let count = self.count();
+        if count == 1 {
+            0.0
+        } else {
+            2.0 * i as f32 / (count - 1) as f32 - 1.0
+        }
+    }
+
+    /// Frequency multiplier for copy `i`
+    pub fn detune_ratio(&self, i: usize) -> f32 {
+        2.0_f32.powf(self.position(i) * self.detune * 0.5 / 1200.0)
+    }
+
+    /// Pan offset for copy `i`
+    pub fn pan(&self, i: usize) -> f32 {
+        self.position(i) * self.spread.clamp(0.0, 1.0)
+    }
+}
+
+/// FM Synthesizer parameters
+#[derive(Clone, Debug)]
+pub struct FMParams {
+    pub carrier: Operator,
+    pub modulator: Operator,
+    pub modulation_index: f32,  // Modulation depth
+    pub amplitude: f32,         // Output amplitude (0.0 - 1.0)
+    pub velocity_sensitivity: f32,  // 0.0 ignores velocity, 1.0 scales level fully
+    pub amp_envelope: EnvelopeParams,
+    pub mod_envelope: EnvelopeParams,
+    pub lfo1: LfoParams,
+    pub lfo2: LfoParams,
+    pub mod_routes: Vec<ModRoute>,
+    pub voice_mode: VoiceMode,
+    pub portamento: PortamentoParams,
+    pub pitch_bend_range: f32,  // Semitones for a full bend
+    pub unison: UnisonParams,
+    pub filter: FilterParams,
+    pub effects: EffectsParams,
+}
+
+impl Default for FMParams {
+    fn default() -> Self {
+        Self {
+            carrier: Operator::ratio(1.0),
+            modulator: Operator::ratio(0.5),
+            modulation_index: 2.0,
+            amplitude: 0.3,
+            velocity_sensitivity: 1.0,
+            amp_envelope: EnvelopeParams::default(),
+            mod_envelope: EnvelopeParams::default(),
+            lfo1: LfoParams::default(),
+            lfo2: LfoParams {
+                rate: 0.5,
+                shape: LfoShape::Triangle,
+            },
+            mod_routes: Vec::new(),
+            voice_mode: VoiceMode::Poly,
+            portamento: PortamentoParams::default(),
+            pitch_bend_range: 2.0,
+            unison: UnisonParams::default(),
+            filter: FilterParams::default(),
+            effects: EffectsParams::default(),
+        }
+    }
+}
+
+/// FM Synthesizer oscillator
+pub struct FMOscillator {
+    sample_rate: f32,
+    carrier_phase: f32,
+    modulator_phase: f32,
+    params: FMParams,
+
+    // Values for the current block, set by `prepare`
+    carrier_freq: f32,
+    modulator_freq: f32,
+    index: f32,
+    gain: f32,
+}
+
+impl FMOscillator {
+    pub fn new(sample_rate: f32, params: FMParams) -> Self {
+        Self {
+            sample_rate,
+            carrier_phase: 0.0,
+            modulator_phase: 0.0,
+            params,
+            carrier_freq: 0.0,
+            modulator_freq: 0.0,
+            index: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    /// Compute operator frequencies and levels for a note at `note_freq` Hz
+    /// with the current block's modulation applied
+    pub fn prepare(&mut self, note_freq: f32, mods: &ModOutputs) {
+        let pitch_ratio = mods.pitch_ratio();
+        let carrier = &self.params.carrier;
+        let modulator = &self.params.modulator;
+
+        self.carrier_freq = carrier.frequency(note_freq, pitch_ratio);
+        self.modulator_freq = modulator.frequency(note_freq, pitch_ratio);
+        self.index = (self.params.modulation_index + mods.modulation_index).max(0.0)
+            * mods.modulator_gain()
+            * modulator.level_gain(note_freq);
+        self.gain = self.params.amplitude * mods.carrier_gain() * carrier.level_gain(note_freq);
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let modulator = (2.0 * PI * self.modulator_phase).sin();
+        let modulated_freq = self.carrier_freq * (1.0 + self.index * modulator);
+        let carrier = (2.0 * PI * self.carrier_phase).sin();
+
+        self.carrier_phase += modulated_freq / self.sample_rate;
+        self.modulator_phase += self.modulator_freq / self.sample_rate;
+
+        if self.carrier_phase >= 1.0 {
+            self.carrier_phase -= 1.0;
+        }
+        if self.modulator_phase >= 1.0 {
+            self.modulator_phase -= 1.0;
+        }
+
+        carrier * self.gain
+    }
+
+    pub fn set_phase(&mut self, carrier: f32, modulator: f32) {
+        self.carrier_phase = carrier;
+        self.modulator_phase = modulator;
+    }
+
+    pub fn params(&self) -> &FMParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: FMParams) {
+        self.params = params;
+    }
+}
+
+/// ADSR Envelope generator
+pub struct Envelope {
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+
+    sample_rate: f32,
+    rate: f32,      // Speed multiplier for keyboard rate scaling
+    state: EnvelopeState,
+    level: f32,
+    time: f32,
+}
+
+#[derive(PartialEq)]
+enum EnvelopeState {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Fraction of a stage `time` seconds in; a zero-length stage is already done
+fn stage_progress(time: f32, length: f32) -> f32 {
+    if length > 0.0 { time / length } else { 1.0 }
+}
+
+impl Envelope {
+    pub fn new(sample_rate: f32) -> Self {
+        Self::with_params(sample_rate, &EnvelopeParams::default())
+    }
+
+    pub fn with_params(sample_rate: f32, params: &EnvelopeParams) -> Self {
+        Self {
+            attack: params.attack,
+            decay: params.decay,
+            sustain: params.sustain,
+            release: params.release,
+            sample_rate,
+            rate: 1.0,
+            state: EnvelopeState::Idle,
+            level: 0.0,
+            time: 0.0,
+        }
+    }
+
+    pub fn set_params(&mut self, params: &EnvelopeParams) {
+        self.attack = params.attack;
+        self.decay = params.decay;
+        self.sustain = params.sustain;
+        self.release = params.release;
+    }
+
+    /// Scale the speed of every stage (2.0 runs the envelope twice as fast)
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate.max(0.0);
+    }
+
+    pub fn trigger(&mut self) {
+        self.state = EnvelopeState::Attack;
+        self.time = 0.0;
+    }
+
+    pub fn release(&mut self) {
+        if self.state != EnvelopeState::Idle {
+            self.state = EnvelopeState::Release;
+            self.time = 0.0;
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.state == EnvelopeState::Idle
+    }
+
+    /// Current output level without advancing the envelope
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn process(&mut self) -> f32 {
+        let dt = self.rate / self.sample_rate;
+
+        match self.state {
+            EnvelopeState::Idle => {
+                self.level = 0.0;
+            }
+            EnvelopeState::Attack => {
+                self.level = stage_progress(self.time, self.attack);
+                if self.time >= self.attack {
+                    self.state = EnvelopeState::Decay;
+                    self.time = 0.0;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.level = 1.0 - ((1.0 - self.sustain) * stage_progress(self.time, self.decay));
+                if self.time >= self.decay {
+                    self.state = EnvelopeState::Sustain;
+                    self.time = 0.0;
+                }
+            }
+            EnvelopeState::Sustain => {
+                self.level = self.sustain;
+            }
+            EnvelopeState::Release => {
+                self.level = self.sustain * (1.0 - stage_progress(self.time, self.release));
+                if self.time >= self.release {
+                    self.state = EnvelopeState::Idle;
+                    self.level = 0.0;
+                }
+            }
+        }
+
+        self.time += dt;
+        self.level
+    }
+}
+
+/// A single note: unison oscillator stack, envelopes and pitch glide
+struct Voice {
+    sample_rate: f32,
+    oscillators: Vec<FMOscillator>,
+    unison: UnisonParams,
+    envelope: Envelope,
+    mod_envelope: Envelope,
+    filter_params: FilterParams,
+    filters: [StateVariableFilter; 2],  // Left and right
+    glide: Glide,
+    note: f32,          // Frequency of the key that owns this voice
+    velocity: f32,
+    velocity_gain: f32,
+    gate: bool,
+    age: u64,
+
+    // Modulation evaluated at the start of the current block
+    mods: ModOutputs,
+}
+
+impl Voice {
+    fn new(sample_rate: f32, params: &FMParams) -> Self {
+        let mut voice = Self {
+            sample_rate,
+            oscillators: Vec::new(),
+            unison: params.unison.clone(),
+            envelope: Envelope::with_params(sample_rate, &params.amp_envelope),
+            mod_envelope: Envelope::with_params(sample_rate, &params.mod_envelope),
+            filter_params: params.filter.clone(),
+            filters: Default::default(),
+            glide: Glide::new(440.0),
+            note: 0.0,
+            velocity: 1.0,
+            velocity_gain: 1.0,
+            gate: false,
+            age: 0,
+            mods: ModOutputs::default(),
+        };
+        voice.set_params(params);
+        voice
+    }
+
+    fn is_active(&self) -> bool {
+        !self.envelope.is_idle()
+    }
+
+    fn trigger(&mut self, rng: &mut Rng) {
+        // A voice coming out of silence must not inherit stale filter state
+        if !self.is_active() {
+            for filter in &mut self.filters {
+                filter.reset();
+            }
+        }
+
+        // Keyboard rate scaling: the carrier drives the amplitude envelope,
+        // the modulator the modulation envelope
+        let params = self.oscillators[0].params();
+        self.envelope.set_rate(params.carrier.rate_multiplier(self.note));
+        self.mod_envelope.set_rate(params.modulator.rate_multiplier(self.note));
+        self.velocity_gain = 1.0 - params.velocity_sensitivity.clamp(0.0, 1.0) * (1.0 - self.velocity);
+        self.envelope.trigger();
+        self.mod_envelope.trigger();
+
+        if self.unison.random_phase {
+            for oscillator in &mut self.oscillators {
+                oscillator.set_phase(rng.next_f32(), rng.next_f32());
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        self.gate = false;
+        self.envelope.release();
+        self.mod_envelope.release();
+    }
+
+    fn set_params(&mut self, params: &FMParams) {
+        self.envelope.set_params(&params.amp_envelope);
+        self.mod_envelope.set_params(&params.mod_envelope);
+        self.unison = params.unison.clone();
+        self.filter_params = params.filter.clone();
+
+        let count = self.unison.count();
+        self.oscillators.truncate(count);
+        for oscillator in &mut self.oscillators {
+            oscillator.set_params(params.clone());
+        }
+        while self.oscillators.len() < count {
+            self.oscillators.push(FMOscillator::new(self.sample_rate, params.clone()));
+        }
+    }
+
+    /// Update every unison copy and the filter for the current block
+    fn prepare(&mut self, freq: f32) {
+        for (i, oscillator) in self.oscillators.iter_mut().enumerate() {
+            oscillator.prepare(freq * self.unison.detune_ratio(i), &self.mods);
+        }
+
+        // The modulation envelope doubles as the filter envelope
+        let octaves = self.filter_params.env_amount * self.mod_envelope.level()
+            + self.mods.filter_cutoff;
+        let cutoff = self.filter_params.cutoff_for(freq, octaves);
+        for filter in &mut self.filters {
+            filter.set_cutoff(cutoff, self.filter_params.resonance, self.sample_rate);
+        }
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let env_out = self.envelope.process();
+        self.mod_envelope.process();
+
+        // Keep the stack at roughly the level of a single oscillator
+        let gain = env_out * self.velocity_gain / (self.oscillators.len() as f32).sqrt();
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, oscillator) in self.oscillators.iter_mut().enumerate() {
+            let sample = oscillator.next_sample() * gain;
+            let pan = (self.mods.pan + self.unison.pan(i)).clamp(-1.0, 1.0);
+            left += sample * (1.0 - pan).min(1.0);
+            right += sample * (1.0 + pan).min(1.0);
+        }
+
+        let mode = self.filter_params.mode;
+        (self.filters[0].process(left, mode), self.filters[1].process(right, mode))
+    }
+}
+
+/// What one voice is doing, for display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoiceStatus {
+    pub freq: f32,    // Key that owns the voice
+    pub level: f32,   // Amplitude envelope level (0.0 - 1.0)
+    pub gate: bool,   // Key still held
+}
+
+/// Polyphonic FM Synthesizer with envelopes, glide and pitch bend
+pub struct FMSynth {
+    sample_rate: f32,
+    voices: Vec<Voice>,
+    lfo1: Lfo,
+    lfo2: Lfo,
+    mod_routes: Vec<ModRoute>,
+    voice_mode: VoiceMode,
+    portamento: PortamentoParams,
+    pitch_bend_range: f32,
+
+    // Performance controls
+    pitch_bend: f32,
+    mod_wheel: f32,
+    aftertouch: f32,
+
+    held_notes: Vec<f32>,   // Keys currently down, most recent last (mono modes)
+    last_note: Option<f32>, // Glide origin for the next note
+    note_counter: u64,
+    block_pos: usize,
+    rng: Rng,
+}
+
+impl FMSynth {
+    pub fn new(sample_rate: f32, params: FMParams) -> Self {
+        Self {
+            sample_rate,
+            voices: (0..MAX_VOICES).map(|_| Voice::new(sample_rate, &params)).collect(),
+            lfo1: Lfo::new(params.lfo1.clone()),
+            lfo2: Lfo::new(params.lfo2.clone()),
+            mod_routes: params.mod_routes.clone(),
+            voice_mode: params.voice_mode,
+            portamento: params.portamento.clone(),
+            pitch_bend_range: params.pitch_bend_range,
+            pitch_bend: 0.0,
+            mod_wheel: 0.0,
+            aftertouch: 0.0,
+            held_notes: Vec::new(),
+            last_note: None,
+            note_counter: 0,
+            block_pos: 0,
+            rng: Rng::default(),
+        }
+    }
+
+    /// Sample the modulation sources and re-evaluate the matrix for every voice
+    fn update_modulation(&mut self) {
+        let block_time = BLOCK_SIZE as f32 / self.sample_rate;
+        let lfo1 = self.lfo1.advance(block_time);
+        let lfo2 = self.lfo2.advance(block_time);
+        let bend = self.pitch_bend * self.pitch_bend_range;
+
+        for voice in self.voices.iter_mut().filter(|v| v.is_active()) {
+            voice.glide.advance(block_time);
+            let freq = voice.glide.freq();
+            let sources = ModSources {
+                amp_envelope: voice.envelope.level(),
+                mod_envelope: voice.mod_envelope.level(),
+                lfo1,
+                lfo2,
+                velocity: voice.velocity,
+                key_track: key_octaves(freq),
+                mod_wheel: self.mod_wheel,
+                aftertouch: self.aftertouch,
+            };
+            voice.mods = modulation::evaluate(&self.mod_routes, &sources);
+            voice.mods.pitch += bend;
+            voice.prepare(freq);
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let (left, right) = self.next_frame();
+        (left + right) * 0.5
+    }
+
+    /// Next stereo frame, summed over every sounding voice
+    pub fn next_frame(&mut self) -> (f32, f32) {
+        if self.block_pos == 0 {
+            self.update_modulation();
+        }
+        self.block_pos = (self.block_pos + 1) % BLOCK_SIZE;
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for voice in self.voices.iter_mut().filter(|v| v.is_active()) {
+            let (l, r) = voice.next_frame();
+            left += l;
+            right += r;
+        }
+        (left, right)
+    }
+
+    /// Free voice for a new poly note, stealing the oldest if all are busy
+    fn allocate_voice(&self) -> usize {
+        self.voices.iter()
+            .position(|v| !v.is_active())
+            .or_else(|| {
+                self.voices.iter()
+                    .enumerate()
+                    .min_by_key(|(_, v)| (v.gate, v.age))
+                    .map(|(i, _)| i)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Start a note; the returned id lets `release_note` end just this note
+    pub fn note_on(&mut self, freq: f32, velocity: f32) -> u64 {
+        self.note_counter += 1;
+        let glide_from = self.last_note;
+
+        let (index, retrigger) = match self.voice_mode {
+            VoiceMode::Poly => (self.allocate_voice(), true),
+            VoiceMode::Mono => (0, true),
+            VoiceMode::Legato => (0, self.held_notes.is_empty() || !self.voices[0].gate),
+        };
+        if self.voice_mode != VoiceMode::Poly {
+            self.held_notes.retain(|&n| n != freq);
+            self.held_notes.push(freq);
+        }
+
+        let voice = &mut self.voices[index];
+        match glide_from {
+            Some(from) if self.portamento.is_enabled() => {
+                if self.voice_mode == VoiceMode::Poly || !voice.is_active() {
+                    voice.glide.jump(from);
+                }
+                voice.glide.glide_to(freq, &self.portamento);
+            }
+            _ => voice.glide.jump(freq),
+        }
+        voice.note = freq;
+        voice.gate = true;
+        voice.age = self.note_counter;
+        if retrigger {
+            voice.velocity = velocity.clamp(0.0, 1.0);
+            voice.trigger(&mut self.rng);
+        }
+
+        self.last_note = Some(freq);
+        // Pick up the new velocity and key position immediately
+        self.block_pos = 0;
+        self.note_counter
+    }
+
+    pub fn note_off(&mut self, freq: f32) {
+        if self.voice_mode == VoiceMode::Poly {
+            for voice in self.voices.iter_mut().filter(|v| v.gate && v.note == freq) {
+                voice.release();
+            }
+            return;
+        }
+
+        self.held_notes.retain(|&n| n != freq);
+        let voice = &mut self.voices[0];
+        if !voice.gate || voice.note != freq {
+            return;
+        }
+        // Fall back to the most recent key still held, without retriggering
+        match self.held_notes.last() {
+            Some(&previous) => {
+                voice.note = previous;
+                voice.glide.glide_to(previous, &self.portamento);
+                self.last_note = Some(previous);
+            }
+            None => voice.release(),
+        }
+    }
+
+    /// Release the note `note_on` returned `id` for, leaving later notes on
+    /// the same key sounding; does nothing if its voice was stolen
+    pub fn release_note(&mut self, id: u64, freq: f32) {
+        if self.voice_mode == VoiceMode::Poly {
+            for voice in self.voices.iter_mut().filter(|v| v.gate && v.age == id) {
+                voice.release();
+            }
+            return;
+        }
+        // A later note on the same key has taken over the voice
+        if self.voices[0].age != id && self.voices[0].note == freq {
+            return;
+        }
+        self.note_off(freq);
+    }
+
+    /// Status of each voice, or None for voices that are silent
+    pub fn voice_status(&self) -> Vec<Option<VoiceStatus>> {
+        self.voices.iter()
+            .map(|voice| voice.is_active().then(|| VoiceStatus {
+                freq: voice.note,
+                level: voice.envelope.level(),
+                gate: voice.gate,
+            }))
+            .collect()
+    }
+
+    pub fn all_notes_off(&mut self) {
+        self.held_notes.clear();
+        for voice in &mut self.voices {
+            voice.release();
+        }
+    }
+
+    /// Set the pitch wheel position (-1.0 - 1.0)
+    pub fn set_pitch_bend(&mut self, value: f32) {
+        self.pitch_bend = value.clamp(-1.0, 1.0);
+    }
+
+    pub fn set_mod_wheel(&mut self, value: f32) {
+        self.mod_wheel = value.clamp(0.0, 1.0);
+    }
+
+    pub fn set_aftertouch(&mut self, value: f32) {
+        self.aftertouch = value.clamp(0.0, 1.0);
+    }
+
+    pub fn set_params(&mut self, params: FMParams) {
+        for voice in &mut self.voices {
+            voice.set_params(&params);
+        }
+        self.lfo1.set_params(params.lfo1.clone());
+        self.lfo2.set_params(params.lfo2.clone());
+        self.mod_routes = params.mod_routes;
+        self.voice_mode = params.voice_mode;
+        self.portamento = params.portamento;
+        self.pitch_bend_range = params.pitch_bend_range;
+    }
+}
@@ -0,0 +1,164 @@
+// src/timing.rs - Musical time: ticks, tempo changes, time signatures and swing
+
+/// Ticks per quarter note
+pub const PPQ: u32 = 480;
+
+pub const DEFAULT_TEMPO: f64 = 120.0;
+
+/// Beats per bar over the note value that gets one beat
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeSignature {
+    pub beats: u32,
+    pub unit: u32,   // 4 = quarter note, 8 = eighth note
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { beats: 4, unit: 4 }
+    }
+}
+
+impl TimeSignature {
+    pub fn new(beats: u32, unit: u32) -> Self {
+        Self { beats, unit }
+    }
+
+    /// Parse `3/4`, `6/8`, ...; the unit must be a power of two
+    pub fn parse(text: &str) -> Option<Self> {
+        let (beats, unit) = text.split_once('/')?;
+        let (beats, unit): (u32, u32) = (beats.trim().parse().ok()?, unit.trim().parse().ok()?);
+        (beats > 0 && unit.is_power_of_two() && unit <= 64).then_some(Self { beats, unit })
+    }
+
+    pub fn bar_ticks(&self) -> u64 {
+        self.beats as u64 * (PPQ as u64 * 4 / self.unit as u64)
+    }
+}
+
+/// Delays every second subdivision: 0.5 is straight, 0.67 a triplet feel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Swing {
+    pub amount: f64,   // Share of each pair taken by its first note (0.5 to 0.75)
+    pub unit: u32,     // Ticks per swung pair; PPQ swings eighth notes
+}
+
+impl Default for Swing {
+    fn default() -> Self {
+        Self { amount: 0.5, unit: PPQ }
+    }
+}
+
+impl Swing {
+    pub fn new(amount: f64) -> Self {
+        Self { amount: amount.clamp(0.5, 0.75), ..Self::default() }
+    }
+
+    pub fn is_straight(&self) -> bool {
+        self.amount <= 0.5
+    }
+
+    /// Move a tick position to where it falls once swung
+    pub fn apply(&self, tick: f64) -> f64 {
+        if self.is_straight() || self.unit == 0 {
+            return tick;
+        }
+        let unit = self.unit as f64;
+        let pair = (tick / unit).floor() * unit;
+        let position = (tick - pair) / unit;
+        let swung = if position < 0.5 {
+            position * 2.0 * self.amount
+        } else {
+            self.amount + (position - 0.5) * 2.0 * (1.0 - self.amount)
+        };
+        pair + swung * unit
+    }
+}
+
+/// A tempo taking effect at a tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoChange {
+    pub tick: u64,
+    pub bpm: f64,
+}
+
+/// Tempo over time, always starting with a tempo at tick 0
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoMap {
+    changes: Vec<TempoChange>,
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPO)
+    }
+}
+
+impl TempoMap {
+    pub fn new(bpm: f64) -> Self {
+        Self { changes: vec![TempoChange { tick: 0, bpm }] }
+    }
+
+    pub fn changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    /// Tempo at the start
+    pub fn initial(&self) -> f64 {
+        self.changes[0].bpm
+    }
+
+    /// Change tempo from `tick` on, replacing any change already there
+    pub fn set_tempo(&mut self, tick: u64, bpm: f64) {
+        self.changes.retain(|c| c.tick != tick);
+        let index = self.changes.partition_point(|c| c.tick < tick);
+        self.changes.insert(index, TempoChange { tick, bpm });
+    }
+
+    /// Scale every tempo so the piece starts at `bpm`, keeping later changes relative
+    pub fn scaled_to(&self, bpm: f64) -> Self {
+        let factor = bpm / self.initial();
+        Self {
+            changes: self.changes.iter()
+                .map(|c| TempoChange { tick: c.tick, bpm: c.bpm * factor })
+                .collect(),
+        }
+    }
+
+    /// Seconds from the start to a (fractional) tick
+    pub fn seconds(&self, tick: f64) -> f64 {
+        let mut seconds = 0.0;
+        for (i, change) in self.changes.iter().enumerate() {
+            let start = change.tick as f64;
+            if tick <= start {
+                break;
+            }
+            let end = self.changes.get(i + 1).map_or(tick, |next| (next.tick as f64).min(tick));
+            seconds += (end - start) / PPQ as f64 * 60.0 / change.bpm;
+        }
+        seconds
+    }
+}
+
+/// Everything needed to place ticks in time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timing {
+    pub tempo: TempoMap,
+    pub time_signature: TimeSignature,
+    pub swing: Swing,
+}
+
+impl Timing {
+    pub fn new(bpm: f64, time_signature: TimeSignature) -> Self {
+        Self { tempo: TempoMap::new(bpm), time_signature, swing: Swing::default() }
+    }
+
+    pub fn with_swing(mut self, amount: f64) -> Self {
+        self.swing = Swing::new(amount);
+        self
+    }
+
+    /// Seconds from the start to a (fractional) tick, after swing
+    pub fn seconds(&self, tick: f64) -> f64 {
+        self.tempo.seconds(self.swing.apply(tick))
+    }
+}
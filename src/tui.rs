@@ -0,0 +1,483 @@
+// src/tui.rs - Full-screen terminal UI: preset controls, scope, voices and a playable keyboard
+
+use std::io::{self, IsTerminal};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Axis, Block, Chart, Dataset, Gauge, GraphType, LineGauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use fm_synth::effects::EffectsParams;
+use fm_synth::engine::Engine;
+use fm_synth::keyboard;
+use fm_synth::operator::OperatorFreq;
+use fm_synth::pitch::midi_name;
+use fm_synth::scope::spectrum;
+use fm_synth::synth::{EnvelopeParams, FMParams, VoiceStatus, MAX_VOICES};
+use fm_synth::tuning::Tuning;
+
+use crate::piano::LiveKeys;
+use crate::{open_stream, CLI};
+
+/// Samples kept for the scope and spectrum
+const SCOPE_LEN: usize = 4096;
+
+/// Samples drawn in the oscilloscope
+const WAVE_LEN: usize = 1024;
+
+/// Samples analysed for the spectrum (a power of two)
+const SPECTRUM_LEN: usize = 2048;
+
+/// Time between redraws
+const FRAME: Duration = Duration::from_millis(33);
+
+/// How a control's value moves between its limits
+#[derive(Clone, Copy, PartialEq)]
+enum Scale {
+    Linear,
+    Log,   // `step` is in octaves
+}
+
+/// One editable preset parameter
+struct Control {
+    name: &'static str,
+    unit: &'static str,
+    min: f32,
+    max: f32,
+    step: f32,   // Per arrow press; a tenth of it with Shift
+    scale: Scale,
+    get: fn(&FMParams) -> f32,
+    set: fn(&mut FMParams, f32),
+}
+
+/// Frequency ratio of an operator; fixed-frequency operators read as 1
+fn ratio(freq: &OperatorFreq) -> f32 {
+    match freq {
+        OperatorFreq::Ratio { coarse, fine } => coarse * (1.0 + fine),
+        OperatorFreq::Fixed(_) => 1.0,
+    }
+}
+
+/// Set the overall ratio, keeping the fine offset; fixed-frequency
+/// operators are left alone, as they have no ratio to change
+fn set_ratio(freq: &mut OperatorFreq, value: f32) {
+    if let OperatorFreq::Ratio { coarse, fine } = freq {
+        *coarse = value / (1.0 + *fine);
+    }
+}
+
+const CONTROLS: &[Control] = &[
+    Control { name: "Mod index", unit: "", min: 0.0, max: 20.0, step: 0.1, scale: Scale::Linear,
+        get: |p| p.modulation_index, set: |p, v| p.modulation_index = v },
+    Control { name: "Carrier ratio", unit: "", min: 0.125, max: 16.0, step: 0.5, scale: Scale::Linear,
+        get: |p| ratio(&p.carrier.freq), set: |p, v| set_ratio(&mut p.carrier.freq, v) },
+    Control { name: "Mod ratio", unit: "", min: 0.125, max: 16.0, step: 0.5, scale: Scale::Linear,
+        get: |p| ratio(&p.modulator.freq), set: |p, v| set_ratio(&mut p.modulator.freq, v) },
+    Control { name: "Amplitude", unit: "", min: 0.0, max: 1.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.amplitude, set: |p, v| p.amplitude = v },
+    Control { name: "Velocity sens", unit: "", min: 0.0, max: 1.0, step: 0.1, scale: Scale::Linear,
+        get: |p| p.velocity_sensitivity, set: |p, v| p.velocity_sensitivity = v },
+    Control { name: "Amp attack", unit: "s", min: 0.001, max: 5.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.amp_envelope.attack, set: |p, v| p.amp_envelope.attack = v },
+    Control { name: "Amp decay", unit: "s", min: 0.001, max: 5.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.amp_envelope.decay, set: |p, v| p.amp_envelope.decay = v },
+    Control { name: "Amp sustain", unit: "", min: 0.0, max: 1.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.amp_envelope.sustain, set: |p, v| p.amp_envelope.sustain = v },
+    Control { name: "Amp release", unit: "s", min: 0.001, max: 5.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.amp_envelope.release, set: |p, v| p.amp_envelope.release = v },
+    Control { name: "Mod attack", unit: "s", min: 0.001, max: 5.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.mod_envelope.attack, set: |p, v| p.mod_envelope.attack = v },
+    Control { name: "Mod decay", unit: "s", min: 0.001, max: 5.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.mod_envelope.decay, set: |p, v| p.mod_envelope.decay = v },
+    Control { name: "Mod sustain", unit: "", min: 0.0, max: 1.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.mod_envelope.sustain, set: |p, v| p.mod_envelope.sustain = v },
+    Control { name: "Mod release", unit: "s", min: 0.001, max: 5.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.mod_envelope.release, set: |p, v| p.mod_envelope.release = v },
+    Control { name: "Filter cutoff", unit: "Hz", min: 20.0, max: 20000.0, step: 1.0 / 6.0, scale: Scale::Log,
+        get: |p| p.filter.cutoff, set: |p, v| p.filter.cutoff = v },
+    Control { name: "Resonance", unit: "", min: 0.0, max: 1.0, step: 0.05, scale: Scale::Linear,
+        get: |p| p.filter.resonance, set: |p, v| p.filter.resonance = v },
+];
+
+impl Control {
+    /// Position between the limits, 0.0 to 1.0
+    fn position(&self, params: &FMParams) -> f64 {
+        let value = (self.get)(params).clamp(self.min, self.max);
+        let position = match self.scale {
+            Scale::Linear => (value - self.min) / (self.max - self.min),
+            Scale::Log => (value / self.min).log2() / (self.max / self.min).log2(),
+        };
+        position as f64
+    }
+
+    fn adjust(&self, params: &mut FMParams, steps: f32) {
+        let value = (self.get)(params);
+        let value = match self.scale {
+            Scale::Linear => value + steps * self.step,
+            Scale::Log => value * 2.0_f32.powf(steps * self.step),
+        };
+        (self.set)(params, value.clamp(self.min, self.max));
+    }
+
+    fn format(&self, params: &FMParams) -> String {
+        let value = (self.get)(params);
+        match (self.unit, self.scale) {
+            ("", _) => format!("{:.2}", value),
+            (unit, Scale::Log) => format!("{:.0} {}", value, unit),
+            (unit, Scale::Linear) => format!("{:.3} {}", value, unit),
+        }
+    }
+}
+
+/// What the audio thread has been doing, copied out once per frame
+#[derive(Default)]
+struct Snapshot {
+    wave: Vec<(f64, f64)>,
+    spectrum: Vec<(f64, f64)>,
+    voices: Vec<Option<VoiceStatus>>,
+    peak: f32,
+    rms: f32,
+}
+
+impl Snapshot {
+    fn take(engine: &Engine) -> Self {
+        let Some(scope) = engine.scope() else {
+            return Self::default();
+        };
+        let sample_rate = engine.sample_rate();
+        let (peak, rms) = scope.levels((sample_rate * 0.05) as usize);
+        Self {
+            wave: scope.triggered(WAVE_LEN).iter()
+                .enumerate()
+                .map(|(i, &s)| (i as f64, s as f64))
+                .collect(),
+            spectrum: spectrum(&scope.latest(SPECTRUM_LEN), sample_rate).iter()
+                .skip(1)
+                .map(|&(freq, db)| (freq.log10() as f64, db.max(-90.0) as f64))
+                .collect(),
+            voices: engine.synth().voice_status(),
+            peak,
+            rms,
+        }
+    }
+}
+
+/// Presets being edited and the keyboard playing them
+struct Tui {
+    names: Vec<String>,
+    params: Vec<FMParams>,    // Working copies, written back on exit
+    changed: Vec<bool>,
+    original: Vec<FMParams>,
+    current: usize,           // Preset being played and edited
+    selected: usize,          // Control with the cursor
+    keys: LiveKeys,
+    session_effects: Option<EffectsParams>,
+    engine: Arc<Mutex<Engine>>,
+}
+
+impl Tui {
+    fn preset(&self) -> &FMParams {
+        &self.params[self.current]
+    }
+
+    /// Preset as played, with any session effects in place of its own
+    fn playing_params(&self) -> FMParams {
+        let mut params = self.preset().clone();
+        if let Some(effects) = &self.session_effects {
+            params.effects = effects.clone();
+        }
+        params
+    }
+
+    fn adjust(&mut self, steps: f32) {
+        let control = &CONTROLS[self.selected];
+        control.adjust(&mut self.params[self.current], steps);
+        self.changed[self.current] = true;
+        // Only the synth changes, so effect tails carry on undisturbed
+        let params = self.playing_params();
+        self.engine.lock().unwrap().synth_mut().set_params(params);
+    }
+
+    fn reset_control(&mut self) {
+        let control = &CONTROLS[self.selected];
+        let value = (control.get)(&self.original[self.current]);
+        (control.set)(&mut self.params[self.current], value);
+        self.changed[self.current] = true;
+        let params = self.playing_params();
+        self.engine.lock().unwrap().synth_mut().set_params(params);
+    }
+
+    fn switch_preset(&mut self, by: isize) {
+        self.keys.silence();
+        let count = self.params.len() as isize;
+        self.current = (self.current as isize + by).rem_euclid(count) as usize;
+        let params = self.playing_params();
+        self.engine.lock().unwrap().set_params(params);
+    }
+
+    /// Handle a key; false once the UI should close
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        if self.keys.key(&key) || key.kind == KeyEventKind::Release {
+            return true;
+        }
+        match key.code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up => self.selected = (self.selected + CONTROLS.len() - 1) % CONTROLS.len(),
+            KeyCode::Down => self.selected = (self.selected + 1) % CONTROLS.len(),
+            KeyCode::Left => self.adjust(if shift { -0.1 } else { -1.0 }),
+            KeyCode::Right => self.adjust(if shift { 0.1 } else { 1.0 }),
+            KeyCode::Backspace => self.reset_control(),
+            KeyCode::PageUp => self.switch_preset(-1),
+            KeyCode::PageDown => self.switch_preset(1),
+            KeyCode::Char(' ') => self.keys.silence(),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&self, frame: &mut Frame, snapshot: &Snapshot) {
+        let [title, main, voices, keys, meter, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(12),
+            Constraint::Length(3),
+            Constraint::Length(6),
+            Constraint::Length(2),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let edited = if self.changed[self.current] { " (edited)" } else { "" };
+        frame.render_widget(Line::from(vec![
+            " FM Synth ".bold().reversed(),
+            format!("  {} ({}/{}){}", self.names[self.current], self.current + 1, self.names.len(), edited).bold(),
+            format!("   octave {:+}  velocity {:.0}%", self.keys.keyboard.octave, self.keys.keyboard.velocity * 100.0).into(),
+            if self.keys.releases { "".into() } else { "   (no key-release events: notes hold briefly)".dark_gray() },
+        ]), title);
+
+        let [left, right] = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(main);
+        let [controls, envelope] = Layout::vertical([Constraint::Min(CONTROLS.len() as u16 + 2), Constraint::Length(8)]).areas(left);
+        self.draw_controls(frame, controls);
+        self.draw_envelopes(frame, envelope);
+        let [wave, spectrum] = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+        draw_wave(frame, wave, snapshot);
+        draw_spectrum(frame, spectrum, snapshot);
+
+        draw_voices(frame, voices, snapshot, &self.keys.tuning);
+        self.draw_keyboard(frame, keys);
+        draw_meter(frame, meter, snapshot);
+
+        frame.render_widget(Line::from(
+            " Esc quit  ↑↓ select  ←→ adjust (Shift fine)  Backspace reset  PgUp/PgDn preset  -/= octave  [/] velocity  Space silence",
+        ).dark_gray(), help);
+    }
+
+    fn draw_controls(&self, frame: &mut Frame, area: Rect) {
+        let params = self.preset();
+        let bar_width = area.width.saturating_sub(2 + 15 + 11) as usize;
+        let lines: Vec<Line> = CONTROLS.iter()
+            .enumerate()
+            .map(|(i, control)| {
+                let filled = (control.position(params) * bar_width as f64).round() as usize;
+                let style = if i == self.selected {
+                    Style::default().fg(Color::Black).bg(Color::Cyan)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![
+                    Span::styled(format!("{:<14} ", control.name), style),
+                    Span::styled("━".repeat(filled), Style::default().fg(Color::Cyan)),
+                    Span::styled("─".repeat(bar_width - filled.min(bar_width)), Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!(" {:>10}", control.format(params))),
+                ])
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Preset ")), area);
+    }
+
+    fn draw_envelopes(&self, frame: &mut Frame, area: Rect) {
+        let params = self.preset();
+        let amp = envelope_points(&params.amp_envelope);
+        let modulation = envelope_points(&params.mod_envelope);
+        let length = amp.iter().chain(&modulation).map(|p| p.0).fold(0.1, f64::max);
+        let end_label = format!("{:.1}s", length);
+        let chart = Chart::new(vec![
+            Dataset::default().name("amp").marker(Marker::Braille).graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Green)).data(&amp),
+            Dataset::default().name("mod").marker(Marker::Braille).graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Magenta)).data(&modulation),
+        ])
+            .block(Block::bordered().title(" Envelopes "))
+            .x_axis(Axis::default().bounds([0.0, length]).labels(["0", end_label.as_str()]))
+            .y_axis(Axis::default().bounds([0.0, 1.0]));
+        frame.render_widget(chart, area);
+    }
+
+    fn draw_keyboard(&self, frame: &mut Frame, area: Rect) {
+        let (low, _) = self.keys.keyboard.range();
+        let held: Vec<i32> = self.keys.keyboard.held_notes().collect();
+        let key_for = |row: &[(char, i32)], offset: i32| -> String {
+            row.iter().find(|(_, o)| *o == offset).map(|(c, _)| c.to_string()).unwrap_or_default()
+        };
+
+        let mut names = Vec::new();
+        let mut cells = Vec::new();
+        let mut bottom = Vec::new();
+        let mut top = Vec::new();
+        for offset in 0..keyboard::span() {
+            let note = low + offset;
+            let black = matches!(note.rem_euclid(12), 1 | 3 | 6 | 8 | 10);
+            let style = match (held.contains(&note), black) {
+                (true, _) => Style::default().fg(Color::Black).bg(Color::Cyan),
+                (false, true) => Style::default().fg(Color::White).bg(Color::Black),
+                (false, false) => Style::default().fg(Color::Black).bg(Color::White),
+            };
+            let name = if note.rem_euclid(12) == 0 { midi_name(note) } else { String::new() };
+            names.push(Span::raw(format!("{:<3}", name)));
+            cells.push(Span::styled("   ", style));
+            bottom.push(Span::styled(format!(" {:<2}", key_for(&keyboard::BOTTOM_ROW, offset)), style));
+            top.push(Span::raw(format!(" {:<2}", key_for(&keyboard::TOP_ROW, offset))));
+        }
+        let lines = vec![Line::from(names), Line::from(cells), Line::from(bottom), Line::from(top)];
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Keyboard ")), area);
+    }
+}
+
+/// ADSR shape with the sustain held for a quarter of the whole
+fn envelope_points(env: &EnvelopeParams) -> Vec<(f64, f64)> {
+    let (a, d, s, r) = (env.attack as f64, env.decay as f64, env.sustain as f64, env.release as f64);
+    let hold = ((a + d + r) / 4.0).max(0.05);
+    vec![(0.0, 0.0), (a, 1.0), (a + d, s), (a + d + hold, s), (a + d + hold + r, 0.0)]
+}
+
+fn draw_wave(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let chart = Chart::new(vec![
+        Dataset::default().marker(Marker::Braille).graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Yellow)).data(&snapshot.wave),
+    ])
+        .block(Block::bordered().title(" Scope "))
+        .x_axis(Axis::default().bounds([0.0, WAVE_LEN as f64]))
+        .y_axis(Axis::default().bounds([-1.0, 1.0]).labels(["-1", "0", "1"]));
+    frame.render_widget(chart, area);
+}
+
+fn draw_spectrum(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let chart = Chart::new(vec![
+        Dataset::default().marker(Marker::Braille).graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Green)).data(&snapshot.spectrum),
+    ])
+        .block(Block::bordered().title(" Spectrum "))
+        .x_axis(Axis::default().bounds([20f64.log10(), 20000f64.log10()]).labels(["20", "200", "2k", "20k"]))
+        .y_axis(Axis::default().bounds([-90.0, 0.0]).labels(["-90", "-45", "0 dB"]));
+    frame.render_widget(chart, area);
+}
+
+fn draw_voices(frame: &mut Frame, area: Rect, snapshot: &Snapshot, tuning: &Tuning) {
+    let block = Block::bordered().title(" Voices ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let cells = Layout::horizontal(vec![Constraint::Ratio(1, MAX_VOICES as u32); MAX_VOICES]).split(inner);
+    for (i, cell) in cells.iter().enumerate() {
+        let gauge = match snapshot.voices.get(i).copied().flatten() {
+            Some(voice) => Gauge::default()
+                .ratio(voice.level.clamp(0.0, 1.0) as f64)
+                .label(tuning.nearest_key(voice.freq).map_or_else(|| "?".to_string(), midi_name))
+                .gauge_style(Style::default().fg(if voice.gate { Color::Green } else { Color::Yellow })),
+            None => Gauge::default().ratio(0.0).label("-").gauge_style(Style::default().fg(Color::DarkGray)),
+        };
+        frame.render_widget(gauge, Rect { width: cell.width.saturating_sub(1), ..*cell });
+    }
+}
+
+fn draw_meter(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let db = |level: f32| 20.0 * level.max(1e-6).log10();
+    // -60 dBFS to full scale across the bar
+    let ratio = |level: f32| ((db(level) + 60.0) / 60.0).clamp(0.0, 1.0) as f64;
+    let color = if snapshot.peak >= 0.999 { Color::Red } else { Color::Green };
+    let [peak, rms] = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(area);
+    frame.render_widget(LineGauge::default()
+        .ratio(ratio(snapshot.peak))
+        .label(format!("Peak {:>6.1} dB", db(snapshot.peak)))
+        .filled_style(Style::default().fg(color).add_modifier(Modifier::BOLD)), peak);
+    frame.render_widget(LineGauge::default()
+        .ratio(ratio(snapshot.rms))
+        .label(format!("RMS  {:>6.1} dB", db(snapshot.rms)))
+        .filled_style(Style::default().fg(Color::Cyan)), rms);
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, tui: &mut Tui) -> io::Result<()> {
+    loop {
+        let snapshot = Snapshot::take(&tui.engine.lock().unwrap());
+        terminal.draw(|frame| tui.draw(frame, &snapshot))?;
+
+        let deadline = Instant::now() + FRAME;
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            if !event::poll(timeout)? {
+                break;
+            }
+            if let Event::Key(key) = event::read()? {
+                if !tui.key(key) {
+                    return Ok(());
+                }
+            }
+        }
+        tui.keys.expire();
+    }
+}
+
+/// Open the full-screen UI on a preset; edits stay with the presets for the
+/// rest of the session
+pub fn run(cli: &mut CLI, preset: Option<&str>) -> anyhow::Result<Result<(), String>> {
+    if !io::stdout().is_terminal() || !io::stdin().is_terminal() {
+        return Ok(Err("The terminal UI needs an interactive terminal".to_string()));
+    }
+    let preset = preset.map_or_else(|| cli.default_preset(), String::from);
+    let current = match cli.find_preset_index(&preset) {
+        Ok(index) => index,
+        Err(e) => return Ok(Err(crate::lookup_message(e, "presets"))),
+    };
+
+    let params: Vec<FMParams> = cli.presets.iter().map(|preset| preset.params.clone()).collect();
+    let first = cli.with_session_effects(params[current].clone());
+    let master = cli.master.clone();
+    let live = open_stream(cli.output(), |sample_rate| {
+        let mut engine = Engine::new(sample_rate, first);
+        engine.set_master(master);
+        engine.enable_scope(SCOPE_LEN);
+        engine
+    })?;
+    let engine = Arc::clone(&live.engine);
+
+    let mut tui = Tui {
+        names: cli.presets.iter().map(|preset| preset.name.clone()).collect(),
+        changed: vec![false; params.len()],
+        original: params.clone(),
+        params,
+        current,
+        selected: 0,
+        keys: LiveKeys::new(Arc::clone(&engine), cli.tuning.clone()),
+        session_effects: cli.session_effects.clone(),
+        engine,
+    };
+
+    let mut terminal = ratatui::init();
+    tui.keys.request_releases();
+    let result = event_loop(&mut terminal, &mut tui);
+    tui.keys.restore();
+    ratatui::restore();
+    result?;
+
+    tui.keys.silence();
+    for (i, params) in tui.params.into_iter().enumerate() {
+        if tui.changed[i] {
+            println!("Kept changes to '{}' for this session", tui.names[i]);
+            cli.presets[i].params = params;
+        }
+    }
+    Ok(Ok(()))
+}
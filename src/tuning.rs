@@ -0,0 +1,351 @@
+// src/tuning.rs - Equal temperament, Scala scales (.scl) and keyboard mappings (.kbm)
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+use crate::pitch::{parse_midi, PitchError};
+
+pub const DEFAULT_A4: f32 = 440.0;
+
+/// MIDI note number of A4, the default reference
+const A4_MIDI: i32 = 69;
+
+/// Keys a tuning can sound: every note name from octave -11 to 19 and
+/// the MIDI range, with room to spare so key arithmetic never overflows
+pub const KEYS: RangeInclusive<i32> = -128..=255;
+
+/// Scala file problem with a 1-based line number
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuningError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+fn error(line: usize, message: impl Into<String>) -> TuningError {
+    TuningError { line, message: message.into() }
+}
+
+/// Non-comment lines of a Scala file with their line numbers
+fn scala_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.starts_with('!'))
+}
+
+/// A scale of pitches above the unison; the last degree is the period
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    pub cents: Vec<f64>,
+}
+
+/// Built-in scales as (name, .scl text)
+const BUILTIN_SCALES: &[(&str, &str)] = &[
+    ("just", "5-limit just intonation\n12\n16/15\n9/8\n6/5\n5/4\n4/3\n45/32\n3/2\n8/5\n5/3\n9/5\n15/8\n2/1\n"),
+    ("pythagorean", "Pythagorean tuning\n12\n256/243\n9/8\n32/27\n81/64\n4/3\n729/512\n3/2\n128/81\n27/16\n16/9\n243/128\n2/1\n"),
+    ("meantone", "Quarter-comma meantone\n12\n76.049\n193.157\n310.265\n386.314\n503.422\n579.471\n696.579\n772.628\n889.735\n1006.843\n1082.892\n2/1\n"),
+    ("werckmeister3", "Werckmeister III\n12\n90.225\n192.180\n294.135\n390.225\n498.045\n588.270\n696.090\n792.180\n888.270\n996.090\n1092.180\n2/1\n"),
+];
+
+impl Scale {
+    /// Equal division of the octave into `steps`
+    pub fn equal(steps: usize) -> Self {
+        let steps = steps.max(1);
+        Self {
+            description: format!("{}-tone equal temperament", steps),
+            cents: (1..=steps).map(|i| 1200.0 * i as f64 / steps as f64).collect(),
+        }
+    }
+
+    /// Names accepted by `Scale::builtin`, besides `12tet` and `<n>edo`
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN_SCALES.iter().map(|(name, _)| *name)
+    }
+
+    /// A named just intonation, historical temperament or equal division
+    pub fn builtin(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if let Some(steps) = name.strip_suffix("edo").or_else(|| name.strip_suffix("tet")) {
+            return steps.parse().ok().filter(|&n| n > 0).map(Self::equal);
+        }
+        BUILTIN_SCALES.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, text)| Self::parse(text).expect("built-in scale is valid"))
+    }
+
+    /// Parse the text of a `.scl` file
+    pub fn parse(text: &str) -> Result<Self, TuningError> {
+        let mut lines = scala_lines(text);
+        let (_, description) = lines.next().ok_or_else(|| error(1, "missing description line"))?;
+        let (count_line, count) = lines.next().ok_or_else(|| error(1, "missing note count"))?;
+        let count: usize = count.split_whitespace().next()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| error(count_line, format!("invalid note count '{}'", count)))?;
+        if count == 0 {
+            return Err(error(count_line, "a scale needs at least one note"));
+        }
+
+        let mut cents = Vec::with_capacity(count);
+        for (line, text) in lines.take(count) {
+            let value = text.split_whitespace().next()
+                .ok_or_else(|| error(line, "missing pitch"))?;
+            cents.push(Self::parse_pitch(value).ok_or_else(|| error(line, format!("invalid pitch '{}'", value)))?);
+        }
+        if cents.len() < count {
+            return Err(error(text.lines().count(), format!("expected {} notes, found {}", count, cents.len())));
+        }
+        if cents[count - 1] <= 0.0 {
+            return Err(error(count_line, "the last note (the period) must be above the unison"));
+        }
+
+        Ok(Self { description: description.to_string(), cents })
+    }
+
+    /// A pitch in cents (`701.955`) or as a ratio (`3/2`, `2`)
+    fn parse_pitch(value: &str) -> Option<f64> {
+        let cents = if value.contains('.') {
+            value.parse::<f64>().ok()?
+        } else {
+            let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+            let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+            if num <= 0.0 || den <= 0.0 {
+                return None;
+            }
+            1200.0 * (num / den).log2()
+        };
+        cents.is_finite().then_some(cents)
+    }
+
+    /// Size of the repeating interval in cents
+    pub fn period(&self) -> f64 {
+        self.cents[self.cents.len() - 1]
+    }
+
+    /// Cents of any degree, counting from 0 (the unison) and wrapping by the period
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        let len = self.cents.len() as i32;
+        let (periods, step) = (degree.div_euclid(len), degree.rem_euclid(len));
+        let within = if step == 0 { 0.0 } else { self.cents[step as usize - 1] };
+        periods as f64 * self.period() + within
+    }
+}
+
+/// Which scale degree each MIDI key plays and where the scale is anchored
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMap {
+    pub first: i32,               // Lowest key that sounds
+    pub last: i32,                // Highest key that sounds
+    pub middle: i32,              // Key playing degree 0
+    pub reference_key: i32,       // Key tuned to `reference_freq`
+    pub reference_freq: f32,
+    pub octave_degree: i32,       // Degree that repeats the pattern; 0 uses the scale's period
+    pub mapping: Vec<Option<i32>>, // Degree per key in the pattern; empty maps keys linearly
+}
+
+impl KeyboardMap {
+    /// Degree 0 on middle C and A4 at `a4`, one key per degree. Every key
+    /// in `KEYS` sounds, beyond MIDI's 0 to 127, so notes in any octave
+    /// have a pitch.
+    pub fn linear(a4: f32) -> Self {
+        Self {
+            first: *KEYS.start(),
+            last: *KEYS.end(),
+            middle: 60,
+            reference_key: A4_MIDI,
+            reference_freq: a4,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+
+    /// Parse the text of a `.kbm` file
+    pub fn parse(text: &str) -> Result<Self, TuningError> {
+        // First word of each remaining line; the mapping may end early
+        let fields: Vec<(usize, &str)> = scala_lines(text)
+            .filter_map(|(line, text)| text.split_whitespace().next().map(|word| (line, word)))
+            .collect();
+        let end = text.lines().count();
+        let field = |i: usize, name: &str| {
+            fields.get(i).copied().ok_or_else(|| error(end, format!("missing {}", name)))
+        };
+        let number = |i: usize, name: &str| -> Result<i32, TuningError> {
+            let (line, value) = field(i, name)?;
+            value.parse().map_err(|_| error(line, format!("invalid {} '{}'", name, value)))
+        };
+
+        let size = number(0, "map size")?;
+        let first = number(1, "first key")?;
+        let last = number(2, "last key")?;
+        let middle = number(3, "middle key")?;
+        let reference_key = number(4, "reference key")?;
+        let (freq_line, freq) = field(5, "reference frequency")?;
+        let reference_freq = freq.parse::<f32>().ok()
+            .filter(|f| f.is_finite() && *f > 0.0)
+            .ok_or_else(|| error(freq_line, format!("invalid reference frequency '{}'", freq)))?;
+        let octave_degree = number(6, "octave degree")?;
+        if size < 0 || first > last || octave_degree < 0 {
+            return Err(error(fields[0].0, "map size, key range or octave degree out of range"));
+        }
+        for (i, (key, name)) in [(first, "first key"), (last, "last key"), (middle, "middle key"),
+            (reference_key, "reference key")].into_iter().enumerate()
+        {
+            if !KEYS.contains(&key) {
+                return Err(error(fields[1 + i].0, format!("{} {} is outside {} to {}",
+                    name, key, KEYS.start(), KEYS.end())));
+            }
+        }
+
+        // Missing trailing entries are unmapped keys
+        let mut mapping = Vec::with_capacity(size as usize);
+        for i in 0..size as usize {
+            mapping.push(match fields.get(7 + i) {
+                Some((_, entry)) if entry.eq_ignore_ascii_case("x") => None,
+                Some((line, entry)) => Some(entry.parse().map_err(|_| {
+                    error(*line, format!("invalid mapping entry '{}'", entry))
+                })?),
+                None => None,
+            });
+        }
+
+        Ok(Self { first, last, middle, reference_key, reference_freq, octave_degree, mapping })
+    }
+
+    /// Cents of `key` above degree 0, or None if the key is unmapped
+    fn key_cents(&self, scale: &Scale, key: i32) -> Option<f64> {
+        if key < self.first || key > self.last {
+            return None;
+        }
+        let offset = key - self.middle;
+        if self.mapping.is_empty() {
+            return Some(scale.degree_cents(offset));
+        }
+        let size = self.mapping.len() as i32;
+        let degree = self.mapping[offset.rem_euclid(size) as usize]?;
+        let octave = match self.octave_degree {
+            0 => scale.period(),
+            degree => scale.degree_cents(degree),
+        };
+        Some(scale.degree_cents(degree) + offset.div_euclid(size) as f64 * octave)
+    }
+}
+
+/// Maps MIDI keys and note names to frequencies; every note-to-frequency
+/// path (melodies, notation, live input, WASM) goes through one of these
+#[derive(Clone, Debug)]
+pub struct Tuning {
+    pub a4: f32,                      // Reference pitch when no keyboard map sets one
+    scale: Option<Scale>,             // None = 12-tone equal temperament
+    keyboard: Option<KeyboardMap>,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::with_a4(DEFAULT_A4)
+    }
+}
+
+impl Tuning {
+    pub fn with_a4(a4: f32) -> Self {
+        Self { a4, scale: None, keyboard: None }
+    }
+
+    pub fn scale(&self) -> Option<&Scale> {
+        self.scale.as_ref()
+    }
+
+    pub fn keyboard(&self) -> Option<&KeyboardMap> {
+        self.keyboard.as_ref()
+    }
+
+    /// Use a scale, or None for 12-tone equal temperament
+    pub fn set_scale(&mut self, scale: Option<Scale>) {
+        self.scale = scale;
+    }
+
+    /// Use a keyboard map, or None to map keys linearly around middle C and A4
+    pub fn set_keyboard(&mut self, keyboard: Option<KeyboardMap>) {
+        self.keyboard = keyboard;
+    }
+
+    /// One-line summary for display
+    pub fn describe(&self) -> String {
+        let scale = self.scale.as_ref().map_or("12-tone equal temperament", |s| s.description.as_str());
+        match &self.keyboard {
+            Some(map) => format!("{}, key {} = {:.2} Hz", scale, map.reference_key, map.reference_freq),
+            None => format!("{}, A4 = {:.2} Hz", scale, self.a4),
+        }
+    }
+
+    /// Frequency of a MIDI key; fractional keys bend by equal-tempered cents
+    /// from the nearest key. None for keys the mapping leaves silent, or for
+    /// every key if the reference key itself is unmapped.
+    pub fn midi_freq(&self, midi: f32) -> Option<f32> {
+        let key = midi.round() as i32;
+        if self.scale.is_none() && self.keyboard.is_none() {
+            // Plain 12-TET, the common case on the audio path
+            return KEYS.contains(&key)
+                .then(|| self.a4 * 2.0_f32.powf((midi - A4_MIDI as f32) / 12.0));
+        }
+
+        let bend = (midi - key as f32) as f64 * 100.0;
+        let linear = KeyboardMap::linear(self.a4);
+        let map = self.keyboard.as_ref().unwrap_or(&linear);
+
+        static EQUAL: OnceLock<Scale> = OnceLock::new();
+        let scale = self.scale.as_ref().unwrap_or_else(|| EQUAL.get_or_init(|| Scale::equal(12)));
+
+        let cents = map.key_cents(scale, key)? - map.key_cents(scale, map.reference_key)?;
+        Some((map.reference_freq as f64 * 2.0_f64.powf((cents + bend) / 1200.0)) as f32)
+    }
+
+    /// The key that sounds closest to `freq`, the inverse of `midi_freq`;
+    /// None if no key in `KEYS` sounds
+    pub fn nearest_key(&self, freq: f32) -> Option<i32> {
+        if !(freq.is_finite() && freq > 0.0) {
+            return None;
+        }
+        if self.scale.is_none() && self.keyboard.is_none() {
+            let key = (A4_MIDI as f32 + 12.0 * (freq / self.a4).log2()).round() as i32;
+            return Some(key.clamp(*KEYS.start(), *KEYS.end()));
+        }
+        let distance = |key: &i32| self.midi_freq(*key as f32).map(|f| (f / freq).log2().abs());
+        KEYS.filter_map(|key| Some((key, distance(&key)?)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(key, _)| key)
+    }
+
+    /// Keys from one period of the scale to the next: the keyboard map's
+    /// size, or the scale's note count when keys map one per degree
+    pub fn period_keys(&self) -> i32 {
+        match (&self.keyboard, &self.scale) {
+            (Some(map), _) if !map.mapping.is_empty() => map.mapping.len() as i32,
+            (_, Some(scale)) => scale.cents.len() as i32,
+            _ => 12,
+        }
+    }
+
+    /// `freq` moved by whole periods (octaves in 12-TET) through the keyboard,
+    /// keeping any bend from its nearest key; None if that key is unmapped
+    pub fn shift_periods(&self, freq: f32, periods: i32) -> Option<f32> {
+        let key = self.nearest_key(freq)?;
+        let bend = freq / self.midi_freq(key as f32)?;
+        let shifted = key.saturating_add(periods.saturating_mul(self.period_keys()));
+        Some(self.midi_freq(shifted as f32)? * bend)
+    }
+
+    /// Frequency of a note name such as `Bb4`, `C#-1`, `A4+10c` or `60`
+    pub fn freq(&self, note: &str) -> Result<f32, PitchError> {
+        let midi = parse_midi(note)?;
+        self.midi_freq(midi).ok_or(PitchError::Unmapped(midi.round() as i32))
+    }
+}
@@ -0,0 +1,43 @@
+// src/wav.rs - 16-bit stereo WAV output for offline renders
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Encode stereo frames as a 16-bit PCM WAV file
+pub fn encode_wav(frames: &[(f32, f32)], sample_rate: u32) -> Vec<u8> {
+    let channels: u16 = 2;
+    let bits: u16 = 16;
+    let block_align = channels * bits / 8;
+    let data_len = frames.len() as u32 * block_align as u32;
+
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&bits.to_le_bytes());
+
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for &(left, right) in frames {
+        for sample in [left, right] {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    out
+}
+
+pub fn write_wav(path: impl AsRef<Path>, frames: &[(f32, f32)], sample_rate: u32) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&encode_wav(frames, sample_rate))?;
+    file.flush()
+}